@@ -0,0 +1,118 @@
+//! Route lookup on a 1,000 route table.
+//!
+//! `envoy` is the radix tree router, selecting the route for a request
+//! without handling it. `routefinder` is the previous router: one
+//! `routefinder::Router` per method plus one for all methods, a scan of every
+//! other method for `405 Method Not Allowed`, and a second lookup to fall back
+//! from `HEAD` to `GET`.
+//!
+//! Run it with `cargo bench --features bench`.
+
+use std::collections::HashMap;
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use envoy_http as envoy;
+use envoy::{Body, Method, Request, Response, StatusCode};
+
+const RESOURCES: usize = 200;
+
+/// Five routes per resource, for 1,000 routes in total.
+fn routes() -> Vec<(Method, String)> {
+    (0..RESOURCES)
+        .flat_map(|i| {
+            vec![
+                (Method::GET, format!("/resource{}", i)),
+                (Method::POST, format!("/resource{}", i)),
+                (Method::GET, format!("/resource{}/:id", i)),
+                (Method::PUT, format!("/resource{}/:id", i)),
+                (Method::GET, format!("/resource{}/:id/files/*", i)),
+            ]
+        })
+        .collect()
+}
+
+fn cases() -> Vec<(&'static str, Method, String)> {
+    let last = RESOURCES - 1;
+    vec![
+        ("static", Method::GET, format!("/resource{}", last)),
+        ("param", Method::GET, format!("/resource{}/42", last)),
+        ("wildcard", Method::GET, format!("/resource{}/42/files/a/b.txt", last)),
+        ("head fallback", Method::HEAD, format!("/resource{}/42", last)),
+        ("method not allowed", Method::DELETE, format!("/resource{}/42", last)),
+        ("not found", Method::GET, "/missing/42".to_owned()),
+    ]
+}
+
+async fn ok(_ctx: &mut envoy::Context) -> envoy::Result {
+    Ok(Response::new(Body::empty()))
+}
+
+fn envoy_server() -> envoy::Server {
+    let mut app = envoy::new();
+    for (method, path) in routes() {
+        app.at(&path).method(method, ok);
+    }
+    app
+}
+
+/// The routing algorithm `envoy` used before the radix tree.
+struct Baseline {
+    method_map: HashMap<Method, routefinder::Router<usize>>,
+    all_method_router: routefinder::Router<usize>,
+}
+
+impl Baseline {
+    fn new() -> Self {
+        let mut method_map: HashMap<Method, routefinder::Router<usize>> = HashMap::new();
+        for (i, (method, path)) in routes().into_iter().enumerate() {
+            method_map.entry(method).or_default().add(path, i).unwrap();
+        }
+        Self {
+            method_map,
+            all_method_router: routefinder::Router::new(),
+        }
+    }
+
+    fn route(&self, method: &Method, path: &str) -> Result<usize, StatusCode> {
+        if let Some(m) = self.method_map.get(method).and_then(|r| r.best_match(path)) {
+            Ok(*m)
+        } else if let Some(m) = self.all_method_router.best_match(path) {
+            Ok(*m)
+        } else if method == Method::HEAD {
+            self.route(&Method::GET, path)
+        } else if self
+            .method_map
+            .iter()
+            .filter(|(k, _)| *k != method)
+            .any(|(_, r)| r.best_match(path).is_some())
+        {
+            Err(StatusCode::METHOD_NOT_ALLOWED)
+        } else {
+            Err(StatusCode::NOT_FOUND)
+        }
+    }
+}
+
+fn router(c: &mut Criterion) {
+    let app = envoy_server();
+    let baseline = Baseline::new();
+
+    let mut group = c.benchmark_group("router");
+    for (case, method, path) in cases() {
+        let req = Request::builder()
+            .method(method.clone())
+            .uri(path.as_str())
+            .body(Body::empty())
+            .unwrap();
+        group.bench_with_input(BenchmarkId::new("envoy", case), &req, |b, req| {
+            b.iter(|| black_box(app.__route(black_box(req))))
+        });
+        group.bench_with_input(BenchmarkId::new("routefinder", case), &path, |b, path| {
+            b.iter(|| black_box(baseline.route(black_box(&method), black_box(path))))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, router);
+criterion_main!(benches);
@@ -0,0 +1,504 @@
+//! The `Accept` and `Accept-Encoding` headers, with quality values.
+
+use std::cmp::Reverse;
+use std::fmt::{self, Display};
+
+use hyper::header::{HeaderName, HeaderValue, ACCEPT, ACCEPT_ENCODING, VARY};
+use hyper::HeaderMap;
+
+/// The `Accept` request header: the media types a client accepts, with
+/// their quality values.
+///
+/// Decoded with [`Context::typed_header`](crate::Context::typed_header), it
+/// picks the best of the media types an endpoint can produce:
+///
+/// ```rust,no_run
+/// # use envoy_http as envoy;
+/// use envoy::{Accept, Body, Error, Response, StatusCode};
+///
+/// async fn report(ctx: &mut envoy::Context) -> envoy::Result {
+///     let accept = ctx.typed_header::<Accept>().unwrap_or_default();
+///     match accept.negotiate(&["application/json", "text/html"]) {
+///         Some("text/html") => Ok(Response::new(Body::from("<h1>Report</h1>"))),
+///         Some(_) => Ok(Response::new(Body::from(r#"{"title":"Report"}"#))),
+///         None => Err(Error::from_str(StatusCode::NOT_ACCEPTABLE, "Not acceptable")),
+///     }
+/// }
+///
+/// let mut app = envoy::Server::new();
+/// app.at("/report").get(report);
+/// ```
+///
+/// Invalid media ranges are skipped. The header is only invalid if none of
+/// its ranges is valid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Accept {
+    /// The media ranges, by descending quality.
+    ranges: Vec<MediaRange>,
+}
+
+/// A media range in the `Accept` header, e.g. `text/*; q=0.5`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaRange {
+    type_: String,
+    subtype: String,
+    params: Vec<(String, String)>,
+    /// The quality in thousandths.
+    quality: u16,
+}
+
+impl Accept {
+    /// The media ranges, by descending quality. Ranges of the same quality
+    /// keep the order the client sent them in.
+    pub fn iter(&self) -> impl Iterator<Item = &MediaRange> {
+        self.ranges.iter()
+    }
+
+    /// The quality of `media_type`, e.g. `application/json`, between `0.0`
+    /// and `1.0`, from the most specific range matching it.
+    ///
+    /// A range with parameters, e.g. `text/html; level=1`, is only the most
+    /// specific one if `media_type` has the same parameters. Otherwise it
+    /// applies only when no other range matches.
+    ///
+    /// An empty `Accept` header accepts everything.
+    #[must_use]
+    pub fn quality(&self, media_type: &str) -> f32 {
+        if self.ranges.is_empty() {
+            return 1.0;
+        }
+        self.ranges
+            .iter()
+            .filter(|range| range.matches(media_type))
+            .max_by_key(|range| range.specificity(media_type))
+            .map_or(0.0, MediaRange::quality)
+    }
+
+    /// The media type out of `available` with the highest quality, or
+    /// `None` if the client accepts none of them. Between media types of the
+    /// same quality, the one listed first in `available` is preferred.
+    #[must_use]
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let mut best = None;
+        let mut best_quality = 0.0;
+        for media_type in available {
+            let quality = self.quality(media_type);
+            if quality > best_quality {
+                best = Some(*media_type);
+                best_quality = quality;
+            }
+        }
+        best
+    }
+}
+
+impl MediaRange {
+    /// The type, e.g. `text` or `*`.
+    #[must_use]
+    pub fn type_(&self) -> &str {
+        &self.type_
+    }
+
+    /// The subtype, e.g. `html` or `*`.
+    #[must_use]
+    pub fn subtype(&self) -> &str {
+        &self.subtype
+    }
+
+    /// The value of a parameter of the range, other than its quality.
+    /// Parameter names are case-insensitive.
+    #[must_use]
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// The quality of the range, between `0.0` and `1.0`.
+    #[must_use]
+    pub fn quality(&self) -> f32 {
+        f32::from(self.quality) / 1000.0
+    }
+
+    /// Whether the range includes `media_type`, e.g. `application/json`.
+    #[must_use]
+    pub fn matches(&self, media_type: &str) -> bool {
+        let essence = media_type.split(';').next().unwrap_or("").trim();
+        let (type_, subtype) = essence.split_once('/').unwrap_or((essence, ""));
+        self.type_ == "*"
+            || (self.type_.eq_ignore_ascii_case(type_)
+                && (self.subtype == "*" || self.subtype.eq_ignore_ascii_case(subtype)))
+    }
+
+    /// How specific the range is for `media_type`, which it matches.
+    fn specificity(&self, media_type: &str) -> usize {
+        match (self.type_.as_str(), self.subtype.as_str()) {
+            _ if !self.params_match(media_type) => 0,
+            ("*", _) => 1,
+            (_, "*") => 2,
+            _ => 3 + self.params.len(),
+        }
+    }
+
+    /// Whether `media_type` has all the parameters of the range.
+    fn params_match(&self, media_type: &str) -> bool {
+        let params: Vec<(&str, String)> = split_unquoted(media_type, ';')
+            .skip(1)
+            .filter_map(|param| {
+                let (name, value) = param.split_once('=')?;
+                Some((name.trim(), unquote(value.trim())?))
+            })
+            .collect();
+        self.params.iter().all(|(name, value)| {
+            params
+                .iter()
+                .any(|(key, v)| key.eq_ignore_ascii_case(name) && v.eq_ignore_ascii_case(value))
+        })
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        let mut parts = split_unquoted(s, ';');
+        let (type_, subtype) = parts.next()?.trim().split_once('/')?;
+        if type_.is_empty() || subtype.is_empty() || (type_ == "*" && subtype != "*") {
+            return None;
+        }
+
+        let mut range = Self {
+            type_: type_.to_owned(),
+            subtype: subtype.to_owned(),
+            params: Vec::new(),
+            quality: 1000,
+        };
+        for param in parts {
+            let (key, value) = param.split_once('=')?;
+            let (key, value) = (key.trim(), unquote(value.trim())?);
+            if key.eq_ignore_ascii_case("q") {
+                range.quality = parse_quality(&value)?;
+            } else {
+                range.params.push((key.to_ascii_lowercase(), value));
+            }
+        }
+        Some(range)
+    }
+}
+
+impl Display for MediaRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.type_, self.subtype)?;
+        for (key, value) in &self.params {
+            if !value.is_empty() && value.chars().all(is_token_char) {
+                write!(f, "; {}={}", key, value)?;
+            } else {
+                let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+                write!(f, "; {}=\"{}\"", key, value)?;
+            }
+        }
+        if self.quality != 1000 {
+            write!(f, "; q={}", self.quality())?;
+        }
+        Ok(())
+    }
+}
+
+impl headers::Header for Accept {
+    fn name() -> &'static HeaderName {
+        &ACCEPT
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let mut ranges = Vec::new();
+        let mut invalid = false;
+        for value in values {
+            let value = value.to_str().map_err(|_| headers::Error::invalid())?;
+            for range in split_unquoted(value, ',').map(str::trim).filter(|range| !range.is_empty()) {
+                match MediaRange::parse(range) {
+                    Some(range) => ranges.push(range),
+                    None => invalid = true,
+                }
+            }
+        }
+        if invalid && ranges.is_empty() {
+            return Err(headers::Error::invalid());
+        }
+        ranges.sort_by_key(|range| Reverse(range.quality));
+        Ok(Self { ranges })
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
+        let ranges: Vec<String> = self.ranges.iter().map(ToString::to_string).collect();
+        if let Ok(value) = HeaderValue::from_str(&ranges.join(", ")) {
+            values.extend(std::iter::once(value));
+        }
+    }
+}
+
+/// The `Accept-Encoding` request header: the content codings a client
+/// accepts, with their quality values.
+///
+/// ```rust
+/// # use envoy_http as envoy;
+/// use envoy::AcceptEncoding;
+/// use envoy::http::{header::ACCEPT_ENCODING, HeaderMap};
+/// use envoy::headers::HeaderMapExt;
+///
+/// let mut headers = HeaderMap::new();
+/// headers.insert(ACCEPT_ENCODING, "gzip; q=0.5, br".parse().unwrap());
+/// let accept = headers.typed_get::<AcceptEncoding>().unwrap();
+/// assert_eq!(accept.negotiate(&["gzip", "br"]), Some("br"));
+/// assert_eq!(accept.quality("identity"), 1.0);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AcceptEncoding {
+    /// The codings with their quality in thousandths, by descending quality.
+    codings: Vec<(String, u16)>,
+}
+
+impl AcceptEncoding {
+    /// The codings, e.g. `gzip` or `*`, with their quality, by descending
+    /// quality.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, f32)> {
+        self.codings
+            .iter()
+            .map(|(coding, quality)| (coding.as_str(), f32::from(*quality) / 1000.0))
+    }
+
+    /// The quality of `coding`, between `0.0` and `1.0`.
+    ///
+    /// Codings that aren't listed get the quality of `*`, if present.
+    /// Otherwise `identity` is always acceptable and other codings are not.
+    #[must_use]
+    pub fn quality(&self, coding: &str) -> f32 {
+        let find = |name: &str| {
+            self.codings
+                .iter()
+                .find(|(listed, _)| listed.eq_ignore_ascii_case(name))
+                .map(|(_, quality)| f32::from(*quality) / 1000.0)
+        };
+        find(coding).or_else(|| find("*")).unwrap_or_else(|| {
+            if coding.eq_ignore_ascii_case("identity") {
+                1.0
+            } else {
+                0.0
+            }
+        })
+    }
+
+    /// The coding out of `available` with the highest quality, or `None` if
+    /// the client accepts none of them. Between codings of the same quality,
+    /// the one listed first in `available` is preferred.
+    #[must_use]
+    pub fn negotiate<'a>(&self, available: &[&'a str]) -> Option<&'a str> {
+        let mut best = None;
+        let mut best_quality = 0.0;
+        for coding in available {
+            let quality = self.quality(coding);
+            if quality > best_quality {
+                best = Some(*coding);
+                best_quality = quality;
+            }
+        }
+        best
+    }
+}
+
+impl headers::Header for AcceptEncoding {
+    fn name() -> &'static HeaderName {
+        &ACCEPT_ENCODING
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        I: Iterator<Item = &'i HeaderValue>,
+    {
+        let mut codings = Vec::new();
+        for value in values {
+            let value = value.to_str().map_err(|_| headers::Error::invalid())?;
+            for coding in value.split(',').map(str::trim).filter(|coding| !coding.is_empty()) {
+                let mut parts = coding.split(';');
+                let name = parts.next().unwrap_or("").trim();
+                let mut quality = 1000;
+                for param in parts {
+                    match param.split_once('=') {
+                        Some((key, value)) if key.trim().eq_ignore_ascii_case("q") => {
+                            quality = parse_quality(value.trim()).ok_or_else(headers::Error::invalid)?;
+                        }
+                        _ => return Err(headers::Error::invalid()),
+                    }
+                }
+                if name.is_empty() {
+                    return Err(headers::Error::invalid());
+                }
+                codings.push((name.to_ascii_lowercase(), quality));
+            }
+        }
+        codings.sort_by_key(|(_, quality)| Reverse(*quality));
+        Ok(Self { codings })
+    }
+
+    fn encode<E: Extend<HeaderValue>>(&self, values: &mut E) {
+        let codings: Vec<String> = self
+            .iter()
+            .map(|(coding, quality)| match quality {
+                q if q >= 1.0 => coding.to_owned(),
+                q => format!("{}; q={}", coding, q),
+            })
+            .collect();
+        if let Ok(value) = HeaderValue::from_str(&codings.join(", ")) {
+            values.extend(std::iter::once(value));
+        }
+    }
+}
+
+/// Add `name` to the `Vary` header of a response, unless it's listed
+/// already.
+pub(crate) fn add_vary(headers: &mut HeaderMap, name: &HeaderName) {
+    let listed = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|listed| listed == "*" || listed.eq_ignore_ascii_case(name.as_str()));
+    if !listed {
+        headers.append(VARY, HeaderValue::from(name.clone()));
+    }
+}
+
+/// Parse a quality value into thousandths.
+/// Split `s` at `separator`, except inside quoted strings.
+fn split_unquoted(s: &str, separator: char) -> impl Iterator<Item = &str> {
+    let mut quoted = false;
+    let mut escaped = false;
+    s.split(move |c: char| {
+        if escaped {
+            escaped = false;
+            return false;
+        }
+        match c {
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            _ => return c == separator && !quoted,
+        }
+        false
+    })
+}
+
+/// A parameter value, without the quotes and escapes of a quoted string.
+fn unquote(value: &str) -> Option<String> {
+    let quoted = match value.strip_prefix('"') {
+        Some(quoted) => quoted.strip_suffix('"')?,
+        None => return Some(value.to_owned()),
+    };
+    let mut unquoted = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        unquoted.push(if c == '\\' { chars.next()? } else { c });
+    }
+    Some(unquoted)
+}
+
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
+}
+
+fn parse_quality(value: &str) -> Option<u16> {
+    let quality: f32 = value.parse().ok()?;
+    if !(0.0..=1.0).contains(&quality) {
+        return None;
+    }
+    Some((quality * 1000.0).round() as u16)
+}
+
+#[cfg(test)]
+mod test {
+    use headers::HeaderMapExt;
+
+    use super::*;
+
+    fn accept(value: &'static str) -> Option<Accept> {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT, HeaderValue::from_static(value));
+        headers.typed_try_get::<Accept>().ok().flatten()
+    }
+
+    #[test]
+    fn orders_by_quality() {
+        let accept = accept("text/*; q=0.5, application/json, text/html; level=1; q=0.8, */*; q=0.1").unwrap();
+        let ranges: Vec<String> = accept.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            ranges,
+            ["application/json", "text/html; level=1; q=0.8", "text/*; q=0.5", "*/*; q=0.1"]
+        );
+        assert_eq!(accept.iter().nth(1).unwrap().param("LEVEL"), Some("1"));
+    }
+
+    #[test]
+    fn negotiates_media_types() {
+        let accept = accept("text/html; q=0.9, text/*; q=0.5, image/png; q=0").unwrap();
+        assert_eq!(accept.quality("text/html"), 0.9);
+        assert_eq!(accept.quality("text/plain; charset=utf-8"), 0.5);
+        assert_eq!(accept.quality("image/png"), 0.0);
+        assert_eq!(accept.negotiate(&["text/plain", "text/html"]), Some("text/html"));
+        assert_eq!(accept.negotiate(&["image/png", "application/json"]), None);
+        assert_eq!(Accept::default().negotiate(&["application/json"]), Some("application/json"));
+    }
+
+    #[test]
+    fn prefers_ranges_with_matching_params() {
+        let accept = accept("text/html; level=1; q=0.2, text/html; q=0.9, text/*; q=0.5").unwrap();
+        assert_eq!(accept.quality("text/html"), 0.9);
+        assert_eq!(accept.quality("text/html; level=1"), 0.2);
+        assert_eq!(accept.quality("text/html; LEVEL=\"1\"; charset=utf-8"), 0.2);
+        assert_eq!(accept.quality("text/html; level=2"), 0.9);
+
+        let accept = self::accept("application/json; version=2; q=0.4, */*; q=0.1").unwrap();
+        assert_eq!(accept.quality("application/json"), 0.1);
+        let accept = self::accept("application/json; version=2; q=0.4").unwrap();
+        assert_eq!(accept.quality("application/json"), 0.4);
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        assert!(accept("text").is_none());
+        assert!(accept("*/html").is_none());
+        assert!(accept("text/html; q=2").is_none());
+        assert!(accept("text/html; q=high").is_none());
+    }
+
+    #[test]
+    fn skips_invalid_ranges() {
+        let accept = accept("text, text/html; q=2, application/json; q=0.5, */html").unwrap();
+        let ranges: Vec<String> = accept.iter().map(ToString::to_string).collect();
+        assert_eq!(ranges, ["application/json; q=0.5"]);
+    }
+
+    #[test]
+    fn parses_quoted_params() {
+        let accept = accept(r#"text/html; title="a, b; c=\"d\""; q=0.5, application/json"#).unwrap();
+        let html = accept.iter().nth(1).unwrap();
+        assert_eq!(html.param("title"), Some(r#"a, b; c="d""#));
+        assert_eq!(html.quality(), 0.5);
+        assert_eq!(html.to_string(), r#"text/html; title="a, b; c=\"d\""; q=0.5"#);
+        assert!(self::accept(r#"text/html; title="open"#).is_none());
+    }
+
+    #[test]
+    fn negotiates_encodings() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip; q=0.8, BR, identity; q=0"));
+        let accept = headers.typed_get::<AcceptEncoding>().unwrap();
+        assert_eq!(accept.quality("br"), 1.0);
+        assert_eq!(accept.quality("identity"), 0.0);
+        assert_eq!(accept.quality("deflate"), 0.0);
+        assert_eq!(accept.negotiate(&["gzip", "br"]), Some("br"));
+
+        headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("*; q=0.5"));
+        let accept = headers.typed_get::<AcceptEncoding>().unwrap();
+        assert_eq!(accept.quality("zstd"), 0.5);
+        assert_eq!(AcceptEncoding::default().negotiate(&["gzip", "identity"]), Some("identity"));
+    }
+}
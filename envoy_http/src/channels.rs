@@ -0,0 +1,457 @@
+//! Broadcast channels, to push the same messages to many long-lived
+//! connections.
+//!
+//! [`Channels`] holds named topics. Every message published to a topic is
+//! delivered to all its current subscribers, each with a bounded buffer of
+//! its own, so a slow client doesn't hold back the others. Register the
+//! channels with [`Server::channels`] to subscribe from a handler with
+//! [`Context::subscribe`]:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::channels::Channels;
+//! use envoy::sse::{self, SseSender};
+//! use envoy::{Body, Response};
+//!
+//! async fn news(ctx: envoy::Context, sender: SseSender) -> envoy::Result<()> {
+//!     let mut news = ctx.subscribe::<String>("news");
+//!     while let Ok(headline) = news.recv().await {
+//!         sender.send_data(headline).await?;
+//!     }
+//!     Ok(())
+//! }
+//!
+//! async fn publish(ctx: &mut envoy::Context) -> envoy::Result {
+//!     let readers = ctx.channels::<String>().topic("news").publish("Envoy ships channels".to_owned());
+//!     Ok(Response::new(Body::from(format!("sent to {} readers", readers))))
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.channels(Channels::<String>::new());
+//! app.at("/news").get(sse::endpoint(news)).post(publish);
+//! ```
+//!
+//! [`Server::channels`]: crate::Server::channels
+//! [`Context::subscribe`]: crate::Context::subscribe
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::{self, Debug, Display};
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context as TaskContext, Poll, Waker};
+
+use futures_util::Stream;
+
+/// What happens when a subscriber's buffer is full and another message is
+/// published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagPolicy {
+    /// Drop the oldest message in the buffer. The subscriber learns how many
+    /// messages it missed from [`RecvError::Lagged`].
+    DropOldest,
+    /// Disconnect the subscriber, which receives [`RecvError::Closed`] after
+    /// the messages already buffered.
+    Disconnect,
+}
+
+/// Why [`Subscription::recv`] didn't return a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecvError {
+    /// The subscriber fell behind, and this many messages were dropped.
+    /// The next call returns the oldest message still buffered.
+    Lagged(u64),
+    /// The subscriber was disconnected for falling behind, or the topic was
+    /// removed, and the messages buffered before were received.
+    Closed,
+}
+
+impl Display for RecvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecvError::Lagged(missed) => {
+                write!(f, "Subscriber lagged behind, {} messages were dropped", missed)
+            }
+            RecvError::Closed => f.write_str("Subscription closed"),
+        }
+    }
+}
+
+impl std::error::Error for RecvError {}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// The topics of [`Channels`], by name.
+type Topics<T> = Arc<Mutex<HashMap<String, Arc<TopicInner<T>>>>>;
+
+/// Named topics carrying messages of type `T`.
+///
+/// A topic exists while it has subscribers: it's created by the first
+/// subscription, and removed once the last one is dropped. Cloning
+/// `Channels` is cheap, and clones share their topics.
+pub struct Channels<T> {
+    inner: Topics<T>,
+    capacity: usize,
+    lag_policy: LagPolicy,
+}
+
+impl<T: Clone + Send + 'static> Channels<T> {
+    /// Channels whose subscribers buffer up to 64 messages, dropping the
+    /// oldest ones when they fall behind.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::default(),
+            capacity: 64,
+            lag_policy: LagPolicy::DropOldest,
+        }
+    }
+
+    /// The number of messages each subscriber buffers.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    #[must_use]
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "Subscribers must buffer at least one message");
+        self.capacity = capacity;
+        self
+    }
+
+    /// What happens when a subscriber's buffer is full.
+    #[must_use]
+    pub fn lag_policy(mut self, lag_policy: LagPolicy) -> Self {
+        self.lag_policy = lag_policy;
+        self
+    }
+
+    /// The topic named `name`. Messages published while it has no
+    /// subscribers are dropped.
+    #[must_use]
+    pub fn topic(&self, name: &str) -> Topic<T> {
+        Topic {
+            topics: self.inner.clone(),
+            name: name.to_owned(),
+            capacity: self.capacity,
+            lag_policy: self.lag_policy,
+        }
+    }
+
+    /// Subscribe to the topic named `name`.
+    pub fn subscribe(&self, name: &str) -> Subscription<T> {
+        self.topic(name).subscribe()
+    }
+
+    /// The number of subscribers of the topic named `name`.
+    #[must_use]
+    pub fn presence(&self, name: &str) -> usize {
+        self.topic(name).subscriber_count()
+    }
+
+    /// The names of the topics with subscribers.
+    #[must_use]
+    pub fn topics(&self) -> Vec<String> {
+        lock(&self.inner).keys().cloned().collect()
+    }
+
+    /// Remove the topic named `name`, closing its subscriptions. Returns
+    /// whether there was such a topic.
+    pub fn remove(&self, name: &str) -> bool {
+        match lock(&self.inner).remove(name) {
+            Some(topic) => {
+                for (_, buffer) in lock(&topic.subscribers).list.drain(..) {
+                    lock(&buffer).close();
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Default for Channels<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for Channels<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            capacity: self.capacity,
+            lag_policy: self.lag_policy,
+        }
+    }
+}
+
+impl<T> Debug for Channels<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Channels")
+            .field("topics", &lock(&self.inner).len())
+            .field("capacity", &self.capacity)
+            .field("lag_policy", &self.lag_policy)
+            .finish()
+    }
+}
+
+/// A topic of [`Channels`], delivering every message published to all its
+/// subscribers.
+pub struct Topic<T> {
+    topics: Topics<T>,
+    name: String,
+    capacity: usize,
+    lag_policy: LagPolicy,
+}
+
+struct TopicInner<T> {
+    subscribers: Mutex<Subscribers<T>>,
+}
+
+struct Subscribers<T> {
+    next_id: u64,
+    list: Vec<(u64, Arc<Mutex<Buffer<T>>>)>,
+}
+
+/// Remove `topic` from `topics` if it has no subscribers left. The lock of
+/// the topics is always taken before the one of the subscribers.
+fn remove_if_empty<T>(topics: &Topics<T>, name: &str, topic: &Arc<TopicInner<T>>) {
+    let mut topics = lock(topics);
+    let current = topics.get(name).is_some_and(|current| Arc::ptr_eq(current, topic));
+    if current && lock(&topic.subscribers).list.is_empty() {
+        topics.remove(name);
+    }
+}
+
+/// The messages waiting for a subscriber.
+struct Buffer<T> {
+    messages: VecDeque<T>,
+    capacity: usize,
+    lag_policy: LagPolicy,
+    missed: u64,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+impl<T> Buffer<T> {
+    /// Buffer `message`, returning whether the subscriber is still
+    /// connected.
+    fn push(&mut self, message: T) -> bool {
+        if self.closed {
+            return false;
+        }
+        if self.messages.len() >= self.capacity {
+            match self.lag_policy {
+                LagPolicy::DropOldest => {
+                    self.messages.pop_front();
+                    self.missed += 1;
+                }
+                LagPolicy::Disconnect => {
+                    self.close();
+                    return false;
+                }
+            }
+        }
+        self.messages.push_back(message);
+        self.wake();
+        true
+    }
+
+    fn close(&mut self) {
+        self.closed = true;
+        self.wake();
+    }
+
+    fn wake(&mut self) {
+        if let Some(waker) = self.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Clone + Send + 'static> Topic<T> {
+    /// Send `message` to every subscriber, returning how many there were.
+    /// Subscribers disconnected for falling behind aren't counted.
+    pub fn publish(&self, message: T) -> usize {
+        let topic = match lock(&self.topics).get(&self.name) {
+            Some(topic) => topic.clone(),
+            None => return 0,
+        };
+        let count = {
+            let mut subscribers = lock(&topic.subscribers);
+            subscribers
+                .list
+                .retain(|(_, buffer)| lock(buffer).push(message.clone()));
+            subscribers.list.len()
+        };
+        if count == 0 {
+            remove_if_empty(&self.topics, &self.name, &topic);
+        }
+        count
+    }
+
+    /// Subscribe to the messages published from now on.
+    pub fn subscribe(&self) -> Subscription<T> {
+        self.subscribe_with(self.capacity, self.lag_policy)
+    }
+
+    /// Subscribe with a buffer of `capacity` messages and the given lag
+    /// policy, instead of the ones of the [`Channels`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is 0.
+    pub fn subscribe_with(&self, capacity: usize, lag_policy: LagPolicy) -> Subscription<T> {
+        assert!(capacity > 0, "Subscribers must buffer at least one message");
+        let buffer = Arc::new(Mutex::new(Buffer {
+            messages: VecDeque::new(),
+            capacity,
+            lag_policy,
+            missed: 0,
+            closed: false,
+            waker: None,
+        }));
+
+        let mut topics = lock(&self.topics);
+        let topic = topics.entry(self.name.clone()).or_insert_with(|| {
+            Arc::new(TopicInner {
+                subscribers: Mutex::new(Subscribers {
+                    next_id: 0,
+                    list: Vec::new(),
+                }),
+            })
+        });
+        let mut subscribers = lock(&topic.subscribers);
+        let id = subscribers.next_id;
+        subscribers.next_id += 1;
+        subscribers.list.push((id, buffer.clone()));
+        Subscription {
+            topics: self.topics.clone(),
+            name: self.name.clone(),
+            topic: topic.clone(),
+            id,
+            buffer,
+        }
+    }
+}
+
+impl<T> Topic<T> {
+    /// The number of subscribers, e.g. to show who's online.
+    #[must_use]
+    pub fn subscriber_count(&self) -> usize {
+        lock(&self.topics)
+            .get(&self.name)
+            .map_or(0, |topic| lock(&topic.subscribers).list.len())
+    }
+}
+
+impl<T> Clone for Topic<T> {
+    fn clone(&self) -> Self {
+        Self {
+            topics: self.topics.clone(),
+            name: self.name.clone(),
+            capacity: self.capacity,
+            lag_policy: self.lag_policy,
+        }
+    }
+}
+
+impl<T> Debug for Topic<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Topic")
+            .field("name", &self.name)
+            .field("subscribers", &self.subscriber_count())
+            .finish()
+    }
+}
+
+/// A subscription to a [`Topic`]. Dropping it unsubscribes, and removes the
+/// topic if it was the last subscription.
+///
+/// It's also a `Stream` of the messages, which skips over lagging and ends
+/// once the subscription is closed.
+pub struct Subscription<T> {
+    topics: Topics<T>,
+    name: String,
+    topic: Arc<TopicInner<T>>,
+    id: u64,
+    buffer: Arc<Mutex<Buffer<T>>>,
+}
+
+impl<T> Subscription<T> {
+    /// The next message.
+    ///
+    /// # Errors
+    ///
+    /// Fails with [`RecvError::Lagged`] after messages were dropped for this
+    /// subscriber, and with [`RecvError::Closed`] once it won't receive any
+    /// more messages.
+    pub async fn recv(&mut self) -> Result<T, RecvError> {
+        poll_fn(|cx| self.poll_recv(cx)).await
+    }
+
+    /// The next message, if one is buffered.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Subscription::recv`].
+    pub fn try_recv(&mut self) -> Result<Option<T>, RecvError> {
+        let mut buffer = lock(&self.buffer);
+        Self::take(&mut buffer).transpose()
+    }
+
+    fn poll_recv(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<T, RecvError>> {
+        let mut buffer = lock(&self.buffer);
+        match Self::take(&mut buffer) {
+            Some(result) => Poll::Ready(result),
+            None => {
+                buffer.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+
+    fn take(buffer: &mut Buffer<T>) -> Option<Result<T, RecvError>> {
+        if buffer.missed > 0 {
+            let missed = std::mem::take(&mut buffer.missed);
+            return Some(Err(RecvError::Lagged(missed)));
+        }
+        match buffer.messages.pop_front() {
+            Some(message) => Some(Ok(message)),
+            None if buffer.closed => Some(Err(RecvError::Closed)),
+            None => None,
+        }
+    }
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<T>> {
+        let this = self.get_mut();
+        loop {
+            return match this.poll_recv(cx) {
+                Poll::Ready(Ok(message)) => Poll::Ready(Some(message)),
+                Poll::Ready(Err(RecvError::Lagged(_))) => continue,
+                Poll::Ready(Err(RecvError::Closed)) => Poll::Ready(None),
+                Poll::Pending => Poll::Pending,
+            };
+        }
+    }
+}
+
+impl<T> Drop for Subscription<T> {
+    fn drop(&mut self) {
+        lock(&self.topic.subscribers).list.retain(|(id, _)| *id != self.id);
+        remove_if_empty(&self.topics, &self.name, &self.topic);
+    }
+}
+
+impl<T> Debug for Subscription<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Subscription").field("id", &self.id).finish()
+    }
+}
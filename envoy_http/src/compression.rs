@@ -0,0 +1,346 @@
+//! Response compression and request decompression.
+//!
+//! The [`Compression`] middleware compresses response bodies with the best
+//! content coding the client accepts, out of Brotli, zstd, gzip and
+//! deflate:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::compression::Compression;
+//!
+//! let mut app = envoy::Server::new();
+//! app.with(Compression::new().threshold(512));
+//! ```
+//!
+//! Bodies are compressed while they are sent, so streaming responses stay
+//! streaming. Responses that are already encoded, partial, or of a content
+//! type that doesn't compress well, like images and videos, are left alone,
+//! as are bodies known to be smaller than the threshold.
+//!
+//! The [`Decompression`] middleware decodes request bodies sent with a
+//! `Content-Encoding`, so endpoints read them like any other body:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::compression::Decompression;
+//!
+//! let mut app = envoy::Server::new();
+//! app.with(Decompression::new().limit(4 * 1024 * 1024));
+//! ```
+
+use std::io;
+
+use async_compression::tokio::bufread::{
+    BrotliDecoder, BrotliEncoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZlibEncoder, ZstdDecoder,
+    ZstdEncoder,
+};
+use async_trait::async_trait;
+use futures_util::{StreamExt, TryStreamExt};
+use hyper::body::HttpBody;
+use hyper::header::{
+    HeaderValue, ACCEPT_ENCODING, ACCEPT_RANGES, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH,
+    CONTENT_TYPE, ETAG,
+};
+use hyper::{Body, HeaderMap, Method, Response, StatusCode};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+use crate::accept;
+use crate::middleware::Next;
+use crate::stream::BodyTooLarge;
+use crate::{AcceptEncoding, Context, Error, Middleware};
+
+/// The codings compressed and decompressed by default, by preference.
+const ENCODINGS: [Encoding; 4] = [Encoding::Brotli, Encoding::Zstd, Encoding::Gzip, Encoding::Deflate];
+
+/// A content coding of compressed bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Encoding {
+    /// Brotli, `br`.
+    Brotli,
+    /// Zstandard, `zstd`.
+    Zstd,
+    /// `gzip`.
+    Gzip,
+    /// `deflate`, which is the zlib format, not raw deflate.
+    Deflate,
+}
+
+impl Encoding {
+    /// The name of the coding in `Accept-Encoding` and `Content-Encoding`.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Zstd => "zstd",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+
+    /// The coding named `name`, case-insensitively. `x-gzip` is an alias of
+    /// `gzip`.
+    fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        match name.as_str() {
+            "br" => Some(Encoding::Brotli),
+            "zstd" => Some(Encoding::Zstd),
+            "gzip" | "x-gzip" => Some(Encoding::Gzip),
+            "deflate" => Some(Encoding::Deflate),
+            _ => None,
+        }
+    }
+
+    /// Compress `body` while it's read.
+    fn encode(self, body: Body) -> Body {
+        let reader = StreamReader::new(TryStreamExt::map_err(body, io::Error::other));
+        match self {
+            Encoding::Brotli => Body::wrap_stream(ReaderStream::new(BrotliEncoder::new(reader))),
+            Encoding::Zstd => Body::wrap_stream(ReaderStream::new(ZstdEncoder::new(reader))),
+            Encoding::Gzip => Body::wrap_stream(ReaderStream::new(GzipEncoder::new(reader))),
+            Encoding::Deflate => Body::wrap_stream(ReaderStream::new(ZlibEncoder::new(reader))),
+        }
+    }
+
+    /// Decompress `body` while it's read, failing once more than `limit`
+    /// bytes are decompressed.
+    fn decode(self, body: Body, limit: u64) -> Body {
+        let reader = StreamReader::new(TryStreamExt::map_err(body, io::Error::other));
+        let decoded = match self {
+            Encoding::Brotli => ReaderStream::new(BrotliDecoder::new(reader)).boxed(),
+            Encoding::Zstd => ReaderStream::new(ZstdDecoder::new(reader)).boxed(),
+            Encoding::Gzip => ReaderStream::new(GzipDecoder::new(reader)).boxed(),
+            Encoding::Deflate => ReaderStream::new(ZlibDecoder::new(reader)).boxed(),
+        };
+        let mut read = 0;
+        Body::wrap_stream(decoded.map(move |chunk| {
+            let chunk = chunk?;
+            read += chunk.len() as u64;
+            if read > limit {
+                return Err(Box::new(BodyTooLarge) as Box<dyn std::error::Error + Send + Sync>);
+            }
+            Ok(chunk)
+        }))
+    }
+}
+
+/// Middleware compressing response bodies, see the
+/// [module documentation](self).
+#[derive(Debug, Clone)]
+pub struct Compression {
+    /// The enabled codings, by preference.
+    encodings: Vec<Encoding>,
+    threshold: u64,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            encodings: ENCODINGS.to_vec(),
+            threshold: 1024,
+        }
+    }
+}
+
+impl Compression {
+    /// Compress with all codings, preferring Brotli, then zstd, gzip and
+    /// deflate between codings the client accepts equally, and leave bodies
+    /// smaller than 1 KiB alone.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compress with `encodings` only, in the order of preference.
+    #[must_use]
+    pub fn encodings(mut self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        self.encodings = encodings.into_iter().collect();
+        self
+    }
+
+    /// Leave bodies alone that are known to be smaller than `threshold`
+    /// bytes. Bodies of unknown length are always compressed.
+    #[must_use]
+    pub fn threshold(mut self, threshold: u64) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Whether the response could be compressed, regardless of what the
+    /// client accepts.
+    fn is_compressible(&self, res: &Response<Body>) -> bool {
+        let headers = res.headers();
+        let status = res.status();
+        let no_transform = headers
+            .get_all(CACHE_CONTROL)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"));
+        let small = res.body().size_hint().exact().is_some_and(|len| len < self.threshold);
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or("");
+
+        !(status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+            || status == StatusCode::PARTIAL_CONTENT
+            || headers.contains_key(CONTENT_ENCODING)
+            || no_transform
+            || small
+            || is_precompressed(content_type))
+    }
+}
+
+/// Whether bodies of `content_type` are compressed already, or shouldn't be
+/// buffered by a compressor, like event streams.
+fn is_precompressed(content_type: &str) -> bool {
+    let essence = content_type.split(';').next().unwrap_or("").trim().to_ascii_lowercase();
+    let (type_, subtype) = essence.split_once('/').unwrap_or((&essence, ""));
+    match type_ {
+        "image" => subtype != "svg+xml",
+        "audio" | "video" => true,
+        "font" => subtype == "woff" || subtype == "woff2",
+        "text" => subtype == "event-stream",
+        "application" => matches!(
+            subtype,
+            "zip" | "gzip" | "x-gzip" | "zstd" | "x-bzip2" | "x-xz" | "x-7z-compressed"
+                | "vnd.rar" | "pdf" | "octet-stream"
+        ),
+        _ => false,
+    }
+}
+
+#[async_trait]
+impl Middleware for Compression {
+    async fn handle(&self, ctx: &mut Context, next: Next) -> crate::Result {
+        let accept = ctx.typed_header::<AcceptEncoding>();
+        let head = ctx.try_borrow::<Method>() == Some(&Method::HEAD);
+        let mut res = next.run(ctx).await?;
+        if !self.is_compressible(&res) {
+            return Ok(res);
+        }
+
+        let headers = res.headers_mut();
+        accept::add_vary(headers, &ACCEPT_ENCODING);
+
+        let available: Vec<&str> = self.encodings.iter().map(|encoding| encoding.as_str()).collect();
+        let encoding = accept
+            .unwrap_or_default()
+            .negotiate(&available)
+            .and_then(|coding| self.encodings.iter().find(|encoding| encoding.as_str() == coding));
+        let encoding = match encoding {
+            Some(encoding) => *encoding,
+            None => return Ok(res),
+        };
+
+        headers.insert(CONTENT_ENCODING, HeaderValue::from_static(encoding.as_str()));
+        headers.remove(CONTENT_LENGTH);
+        headers.remove(ACCEPT_RANGES);
+        // The compressed body isn't byte-for-byte the same as the original.
+        if let Some(etag) = headers.get(ETAG).and_then(|etag| etag.to_str().ok()) {
+            if !etag.starts_with("W/") {
+                let weak = HeaderValue::from_str(&format!("W/{}", etag))?;
+                headers.insert(ETAG, weak);
+            }
+        }
+        if !head {
+            let body = std::mem::take(res.body_mut());
+            *res.body_mut() = encoding.encode(body);
+        }
+        Ok(res)
+    }
+}
+
+/// Middleware decompressing request bodies, see the
+/// [module documentation](self).
+///
+/// The body is decoded while it's read, and reading it fails with
+/// `413 Payload Too Large` once more than the limit is decompressed, so a
+/// small body can't expand into an unbounded amount of data. Requests with
+/// a `Content-Encoding` that isn't supported fail with
+/// `415 Unsupported Media Type`, listing the supported codings in the
+/// `Accept-Encoding` header of the response.
+#[derive(Debug, Clone)]
+pub struct Decompression {
+    encodings: Vec<Encoding>,
+    limit: u64,
+}
+
+impl Default for Decompression {
+    fn default() -> Self {
+        Self {
+            encodings: ENCODINGS.to_vec(),
+            limit: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl Decompression {
+    /// Decompress all codings, up to 16 MiB.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decompress `encodings` only, rejecting the others.
+    #[must_use]
+    pub fn encodings(mut self, encodings: impl IntoIterator<Item = Encoding>) -> Self {
+        self.encodings = encodings.into_iter().collect();
+        self
+    }
+
+    /// Fail once a body decompresses to more than `limit` bytes.
+    #[must_use]
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = limit;
+        self
+    }
+}
+
+#[async_trait]
+impl Middleware for Decompression {
+    async fn handle(&self, ctx: &mut Context, next: Next) -> crate::Result {
+        let codings: Vec<String> = ctx
+            .header_all(CONTENT_ENCODING)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"))
+            .map(str::to_owned)
+            .collect();
+        if codings.is_empty() {
+            return next.run(ctx).await;
+        }
+
+        let mut encodings = Vec::with_capacity(codings.len());
+        for coding in &codings {
+            match Encoding::from_name(coding).filter(|encoding| self.encodings.contains(encoding)) {
+                Some(encoding) => encodings.push(encoding),
+                None => {
+                    let accepted: Vec<&str> =
+                        self.encodings.iter().map(|encoding| encoding.as_str()).collect();
+                    let accepted = HeaderValue::from_str(&accepted.join(", "))?;
+                    ctx.response_headers_mut().insert(ACCEPT_ENCODING, accepted);
+                    return Err(Error::from_str(
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        format!("Unsupported content encoding `{}`", coding),
+                    ));
+                }
+            }
+        }
+
+        // Codings are listed in the order they were applied.
+        let mut body = ctx.try_take::<Body>().unwrap_or_default();
+        for encoding in encodings.into_iter().rev() {
+            body = encoding.decode(body, self.limit);
+        }
+        ctx.insert(body);
+        if let Some(headers) = ctx.try_borrow_mut::<HeaderMap>() {
+            headers.remove(CONTENT_ENCODING);
+            headers.remove(CONTENT_LENGTH);
+        }
+        next.run(ctx).await
+    }
+}
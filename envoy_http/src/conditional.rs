@@ -0,0 +1,283 @@
+//! Conditional requests.
+//!
+//! The [`Conditional`] middleware gives `GET` and `HEAD` responses an
+//! `ETag` computed from their body, unless the endpoint set validators
+//! itself, and answers the conditional headers of the request following
+//! [RFC 9110](https://www.rfc-editor.org/rfc/rfc9110#section-13.2.2):
+//! `304 Not Modified` if the client's copy is current, and
+//! `412 Precondition Failed` if a precondition doesn't hold.
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::conditional::Conditional;
+//!
+//! let mut app = envoy::Server::new();
+//! app.with(Conditional::new());
+//! ```
+//!
+//! Requests changing a resource have to be checked before the change is
+//! made, which is up to the endpoint, with
+//! [`Context::check_preconditions`]:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::conditional::Validators;
+//! use envoy::{Body, Response};
+//!
+//! async fn update(ctx: &mut envoy::Context) -> envoy::Result {
+//!     let version = 7; // The version of the stored document.
+//!     ctx.check_preconditions(Some(&Validators::new().etag(version)))?;
+//!     // Only now update the document, and return its new version.
+//!     let mut res = Response::new(Body::empty());
+//!     Validators::new().etag(version + 1).apply(&mut res);
+//!     Ok(res)
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.at("/doc").put(update);
+//! ```
+//!
+//! [`Context::check_preconditions`]: crate::Context::check_preconditions
+
+use std::fmt::Display;
+use std::time::SystemTime;
+
+use async_trait::async_trait;
+use headers::{
+    ETag, HeaderMapExt, IfMatch, IfModifiedSince, IfNoneMatch, IfUnmodifiedSince, LastModified,
+};
+use hyper::body::HttpBody;
+use hyper::header::{CONTENT_LENGTH, TRANSFER_ENCODING};
+use hyper::{Body, Method, Response, StatusCode};
+
+use crate::middleware::Next;
+use crate::{Context, Error, Middleware};
+
+/// The validators of the current version of a resource: an entity tag, a
+/// modification date, or both.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validators {
+    etag: Option<ETag>,
+    last_modified: Option<SystemTime>,
+}
+
+impl Validators {
+    /// No validators yet.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A strong entity tag, e.g. a version number or a hash of the
+    /// contents, without the quotes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains quotes or characters that aren't visible
+    /// ASCII.
+    #[must_use]
+    pub fn etag(mut self, tag: impl Display) -> Self {
+        self.etag = Some(parse_etag(&format!("\"{}\"", tag)));
+        self
+    }
+
+    /// A weak entity tag, for versions that are equivalent but not
+    /// byte-for-byte identical, without the quotes and the `W/` prefix.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the tag contains quotes or characters that aren't visible
+    /// ASCII.
+    #[must_use]
+    pub fn weak_etag(mut self, tag: impl Display) -> Self {
+        self.etag = Some(parse_etag(&format!("W/\"{}\"", tag)));
+        self
+    }
+
+    /// The date of the last modification.
+    #[must_use]
+    pub fn last_modified(mut self, last_modified: SystemTime) -> Self {
+        self.last_modified = Some(last_modified);
+        self
+    }
+
+    /// Set the `ETag` and `Last-Modified` headers of `res`. The
+    /// [`Conditional`] middleware uses them instead of computing an `ETag`.
+    pub fn apply<B>(&self, res: &mut Response<B>) {
+        if let Some(etag) = &self.etag {
+            res.headers_mut().typed_insert(etag.clone());
+        }
+        if let Some(last_modified) = self.last_modified {
+            res.headers_mut().typed_insert(LastModified::from(last_modified));
+        }
+    }
+
+    fn from_response<B>(res: &Response<B>) -> Self {
+        Self {
+            etag: res.headers().typed_get(),
+            last_modified: res.headers().typed_get::<LastModified>().map(SystemTime::from),
+        }
+    }
+}
+
+fn parse_etag(etag: &str) -> ETag {
+    etag.parse().unwrap_or_else(|_| panic!("invalid entity tag: {}", etag))
+}
+
+/// A strong `ETag` of `contents`: its length and 64-bit FNV-1a hash,
+/// including the quotes.
+pub(crate) fn content_etag(contents: &[u8]) -> String {
+    let hash = contents.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    });
+    format!("\"{:x}-{:016x}\"", contents.len(), hash)
+}
+
+/// Evaluate the conditional headers of the request against `current`, the
+/// validators of the current version of the resource, or `None` if it
+/// doesn't exist. Returns the status to respond with if a condition fails.
+fn evaluate(ctx: &Context, safe: bool, current: Option<&Validators>) -> Option<StatusCode> {
+    let etag = current.and_then(|current| current.etag.as_ref());
+    let last_modified = current.and_then(|current| current.last_modified);
+
+    if let Some(if_match) = ctx.typed_header::<IfMatch>() {
+        let passes = current.is_some()
+            && (if_match.is_any() || etag.is_some_and(|etag| if_match.precondition_passes(etag)));
+        if !passes {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+    } else if let Some(since) = ctx.typed_header::<IfUnmodifiedSince>() {
+        if last_modified.is_some_and(|last_modified| !since.precondition_passes(last_modified)) {
+            return Some(StatusCode::PRECONDITION_FAILED);
+        }
+    }
+
+    if let Some(if_none_match) = ctx.typed_header::<IfNoneMatch>() {
+        let fails = current.is_some()
+            && (if_none_match == IfNoneMatch::any()
+                || etag.is_some_and(|etag| !if_none_match.precondition_passes(etag)));
+        if fails {
+            return Some(if safe {
+                StatusCode::NOT_MODIFIED
+            } else {
+                StatusCode::PRECONDITION_FAILED
+            });
+        }
+    } else if let (true, Some(since), Some(last_modified)) =
+        (safe, ctx.typed_header::<IfModifiedSince>(), last_modified)
+    {
+        if !since.is_modified(last_modified) {
+            return Some(StatusCode::NOT_MODIFIED);
+        }
+    }
+    None
+}
+
+fn is_safe(ctx: &Context) -> bool {
+    matches!(ctx.try_borrow::<Method>(), Some(&Method::GET) | Some(&Method::HEAD))
+}
+
+/// See [`Context::check_preconditions`](crate::Context::check_preconditions).
+pub(crate) fn check(ctx: &mut Context, current: Option<&Validators>) -> crate::Result<()> {
+    match evaluate(ctx, is_safe(ctx), current) {
+        None => Ok(()),
+        Some(StatusCode::NOT_MODIFIED) => {
+            if let Some(current) = current {
+                let mut res = Response::new(());
+                current.apply(&mut res);
+                ctx.response_headers_mut().extend(res.into_parts().0.headers);
+            }
+            Err(Error::from_str(StatusCode::NOT_MODIFIED, ""))
+        }
+        Some(status) => Err(Error::from_str(status, "Precondition failed")),
+    }
+}
+
+/// Middleware answering conditional `GET` and `HEAD` requests, see the
+/// [module documentation](self).
+///
+/// Responses with an `ETag` or `Last-Modified` header, e.g. set with
+/// [`Validators::apply`], are evaluated against those. Otherwise `200 OK`
+/// responses get an `ETag` hashed from their body, if its length is known
+/// and at most the maximum size, as the body has to be buffered for that.
+/// Streaming bodies are left alone, and so are the empty bodies of `HEAD`
+/// endpoints: only `HEAD` requests answered by the `GET` endpoint get the
+/// same `ETag` as a `GET` request.
+#[derive(Debug, Clone)]
+pub struct Conditional {
+    weak: bool,
+    max_size: u64,
+}
+
+impl Default for Conditional {
+    fn default() -> Self {
+        Self {
+            weak: false,
+            max_size: 1024 * 1024,
+        }
+    }
+}
+
+impl Conditional {
+    /// Compute strong `ETag`s for bodies of up to 1 MiB.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compute weak `ETag`s, e.g. when a later middleware compresses the
+    /// body, so it isn't byte-for-byte the same as the one hashed.
+    #[must_use]
+    pub fn weak(mut self) -> Self {
+        self.weak = true;
+        self
+    }
+
+    /// Compute `ETag`s for bodies of up to `max_size` bytes.
+    #[must_use]
+    pub fn max_size(mut self, max_size: u64) -> Self {
+        self.max_size = max_size;
+        self
+    }
+}
+
+#[async_trait]
+impl Middleware for Conditional {
+    async fn handle(&self, ctx: &mut Context, next: Next) -> crate::Result {
+        let head = ctx.try_borrow::<Method>() == Some(&Method::HEAD);
+        let safe = is_safe(ctx);
+        let mut res = next.run(ctx).await?;
+        // Requests changing a resource are checked by the endpoint, before
+        // the change is made.
+        if !safe || res.status() != StatusCode::OK {
+            return Ok(res);
+        }
+
+        let mut current = Validators::from_response(&res);
+        let size = res.body().size_hint().exact();
+        // `HEAD` requests falling back to the `GET` endpoint still have the
+        // body here, but an empty one is all a `HEAD` endpoint can return.
+        let size = size.filter(|size| !head || *size > 0);
+        if current == Validators::default() && size.is_some_and(|size| size <= self.max_size) {
+            let body = std::mem::take(res.body_mut());
+            let bytes = hyper::body::to_bytes(body).await?;
+            let etag = content_etag(&bytes);
+            let etag = if self.weak { format!("W/{}", etag) } else { etag };
+            current.etag = Some(parse_etag(&etag));
+            current.apply(&mut res);
+            *res.body_mut() = Body::from(bytes);
+        }
+
+        match evaluate(ctx, true, Some(&current)) {
+            Some(StatusCode::NOT_MODIFIED) => {
+                *res.status_mut() = StatusCode::NOT_MODIFIED;
+                *res.body_mut() = Body::empty();
+                res.headers_mut().remove(CONTENT_LENGTH);
+                res.headers_mut().remove(TRANSFER_ENCODING);
+                Ok(res)
+            }
+            Some(status) => Err(Error::from_str(status, "Precondition failed")),
+            None => Ok(res),
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! Route parameter constraints.
+//!
+//! A parameter segment can be followed by a regular expression in
+//! parentheses, e.g. `/users/:id(\d+)`, `/posts/:slug([a-z-]+)` or
+//! `/posts/:kind(draft|published)`. The expression has to match the whole
+//! captured segment for the route to be selected.
+
+use std::fmt::{self, Debug};
+
+use regex::Regex;
+use routefinder::Captures;
+
+/// The constraints declared on the parameters of a single route.
+#[derive(Clone, Default)]
+pub(crate) struct Constraints {
+    params: Vec<(String, Regex)>,
+}
+
+impl Debug for Constraints {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(self.params.iter().map(|(name, re)| (name, re.as_str())))
+            .finish()
+    }
+}
+
+impl Constraints {
+    /// Split a route pattern into the plain path understood by `routefinder`
+    /// and the constraints declared on its parameters.
+    pub(crate) fn parse(pattern: &str) -> Result<(String, Self), String> {
+        let mut path = String::with_capacity(pattern.len());
+        let mut constraints = Self::default();
+        let mut chars = pattern.char_indices().peekable();
+
+        while let Some((_, c)) = chars.next() {
+            path.push(c);
+            if c != ':' {
+                continue;
+            }
+
+            let mut name = String::new();
+            while let Some(&(_, c)) = chars.peek() {
+                if c == '(' || c == '/' || c == '.' {
+                    break;
+                }
+                name.push(c);
+                chars.next();
+            }
+            path.push_str(&name);
+
+            let start = match chars.peek() {
+                Some(&(i, '(')) => i + 1,
+                _ => continue,
+            };
+            chars.next();
+
+            let end = closing_paren(&mut chars)
+                .ok_or_else(|| format!("unterminated constraint for `:{}` in `{}`", name, pattern))?;
+            if name.is_empty() {
+                return Err(format!("constraints require a named param in `{}`", pattern));
+            }
+
+            let expr = &pattern[start..end];
+            let regex = Regex::new(&format!("^(?:{})$", expr))
+                .map_err(|err| format!("invalid constraint for `:{}`: {}", name, err))?;
+            constraints.params.push((name, regex));
+        }
+
+        Ok((path, constraints))
+    }
+
+    /// Whether the captured params satisfy every constraint.
+    pub(crate) fn matches(&self, captures: &Captures<'_, '_>) -> bool {
+        self.params.iter().all(|(name, regex)| {
+            captures
+                .get(name)
+                .is_some_and(|value| regex.is_match(value))
+        })
+    }
+
+    /// Whether the param with the given name is constrained.
+    pub(crate) fn contains(&self, name: &str) -> bool {
+        self.params.iter().any(|(param, _)| param == name)
+    }
+}
+
+/// Advance past a balanced regular expression, returning the byte index of
+/// the closing parenthesis. Escapes and character classes are skipped so that
+/// `\)` or `[)]` don't terminate the expression early.
+fn closing_paren(chars: &mut std::iter::Peekable<std::str::CharIndices<'_>>) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_class = false;
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '[' if !in_class => in_class = true,
+            ']' if in_class => in_class = false,
+            '(' if !in_class => depth += 1,
+            ')' if !in_class && depth == 0 => return Some(i),
+            ')' if !in_class => depth -= 1,
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn strips_constraints_from_pattern() {
+        let (path, constraints) =
+            Constraints::parse(r"/users/:id(\d+)/posts/:kind(draft|published)").unwrap();
+        assert_eq!(path, "/users/:id/posts/:kind");
+        assert!(constraints.contains("id"));
+        assert!(constraints.contains("kind"));
+        assert!(!constraints.contains("slug"));
+    }
+
+    #[test]
+    fn skips_escaped_and_class_parens() {
+        let (path, constraints) = Constraints::parse(r"/:a([)]+)/:b(\)|(x))").unwrap();
+        assert_eq!(path, "/:a/:b");
+
+        let mut captures = Captures::new();
+        captures.push(("a", ")))"));
+        captures.push(("b", ")"));
+        assert!(constraints.matches(&captures));
+
+        let mut captures = Captures::new();
+        captures.push(("a", ")"));
+        captures.push(("b", "y"));
+        assert!(!constraints.matches(&captures));
+    }
+
+    #[test]
+    fn rejects_malformed_constraints() {
+        assert!(Constraints::parse(r"/:id(\d+").is_err());
+        assert!(Constraints::parse(r"/:id([0-9)").is_err());
+        assert!(Constraints::parse(r"/:(\d+)").is_err());
+    }
+}
@@ -2,10 +2,42 @@ use std::{
     any::{Any, TypeId},
     collections::HashMap,
     fmt::Debug,
+    sync::Arc,
 };
 
-use hyper::{Body};
+use cookie::{Cookie, CookieJar};
+use futures_util::TryStreamExt;
+use headers::HeaderMapExt;
+use hyper::header::{AsHeaderName, HeaderName, HeaderValue, CONTENT_LENGTH, CONTENT_TYPE};
+use hyper::{Body, HeaderMap, Response, StatusCode, Uri};
 use routefinder::Captures;
+use serde::de::DeserializeOwned;
+
+use crate::accept;
+use crate::channels::{Channels, Subscription};
+use crate::conditional::{self, Validators};
+use crate::cookies::{self, Cookies};
+use crate::multipart::{self, Limits, Multipart};
+use crate::sessions::Session;
+use crate::stream::BodyStream;
+use crate::{CookieKeys, Error, MatchedRoute, PrivateJar, SignedJar};
+
+/// The request URI as received by the outermost server.
+#[derive(Debug)]
+struct OriginalUri(Uri);
+
+/// Headers staged for the response.
+#[derive(Debug, Default)]
+struct ResponseHeaders(HeaderMap);
+
+/// The request headers the routes a request went through are dispatched
+/// on.
+#[derive(Debug, Default)]
+struct Vary(Vec<HeaderName>);
+
+/// The path prefixes stripped by the servers a request was nested through.
+#[derive(Debug)]
+pub(crate) struct MountPath(pub(crate) String);
 
 /// ## The context of a request.
 ///
@@ -29,6 +61,15 @@ impl Context {
             params,
         };
 
+        ctx.insert(OriginalUri(req.uri().clone()));
+        ctx.insert_request(req);
+
+        ctx
+    }
+
+    /// Store the parts of a request in the context, replacing the current
+    /// ones.
+    pub(crate) fn insert_request(&mut self, req: crate::Request<Body>) {
         let (
             hyper::http::request::Parts {
                 method,
@@ -41,14 +82,43 @@ impl Context {
             body,
         ) = req.into_parts();
 
-        ctx.insert(method);
-        ctx.insert(uri);
-        ctx.insert(version);
-        ctx.insert(headers);
-        ctx.insert(body);
-        ctx.insert(extensions);
+        self.insert(method);
+        self.insert(uri);
+        self.insert(version);
+        self.insert(headers);
+        self.insert(body);
+        self.insert(extensions);
+    }
 
-        ctx
+    /// Move the request out of the context, e.g. to hand it to a
+    /// `tower::Service`. Parts that were already taken are left at their
+    /// defaults.
+    pub(crate) fn take_request(&mut self) -> crate::Request<Body> {
+        let mut req = crate::Request::new(self.try_take::<Body>().unwrap_or_default());
+        if let Some(method) = self.try_take() {
+            *req.method_mut() = method;
+        }
+        if let Some(uri) = self.try_take() {
+            *req.uri_mut() = uri;
+        }
+        if let Some(version) = self.try_take() {
+            *req.version_mut() = version;
+        }
+        if let Some(headers) = self.try_take() {
+            *req.headers_mut() = headers;
+        }
+        if let Some(extensions) = self.try_take() {
+            *req.extensions_mut() = extensions;
+        }
+        req
+    }
+
+    /// Move everything out of the context, leaving it empty.
+    pub(crate) fn take_all(&mut self) -> Self {
+        Self {
+            state: std::mem::take(&mut self.state),
+            params: std::mem::take(&mut self.params),
+        }
     }
 
     /// Try borrow a context value
@@ -120,6 +190,287 @@ impl Context {
             .map(|v| *v.downcast::<T>().unwrap())
     }
 
+    /// The first value of a request header, if the request has it.
+    #[must_use]
+    pub fn header(&self, name: impl AsHeaderName) -> Option<&HeaderValue> {
+        self.try_borrow::<HeaderMap>()?.get(name)
+    }
+
+    /// All values of a request header, in the order they were received.
+    pub fn header_all(&self, name: impl AsHeaderName) -> impl Iterator<Item = &HeaderValue> {
+        self.try_borrow::<HeaderMap>()
+            .map(|headers| headers.get_all(name))
+            .into_iter()
+            .flatten()
+    }
+
+    /// Decode a request header into one of the typed headers of the
+    /// [`headers`](crate::headers) crate, or into an [`Accept`](crate::Accept).
+    ///
+    /// Returns `None` if the request doesn't have the header, or if it's
+    /// invalid. Use the [`Header`](crate::extract::Header) extractor to tell
+    /// the two apart.
+    #[must_use]
+    pub fn typed_header<H: headers::Header>(&self) -> Option<H> {
+        self.try_borrow::<HeaderMap>()?.typed_get()
+    }
+
+    /// The headers staged for the response.
+    #[must_use]
+    pub fn response_headers(&self) -> Option<&HeaderMap> {
+        self.try_borrow::<ResponseHeaders>().map(|ResponseHeaders(headers)| headers)
+    }
+
+    /// Stage headers for the response, e.g. in middleware before the
+    /// endpoint builds the response.
+    ///
+    /// Once the request is handled, the staged headers are added to the
+    /// response, including error responses, unless the response sets a
+    /// header of the same name itself. Typed headers can be staged with
+    /// [`HeaderMapExt::typed_insert`](headers::HeaderMapExt::typed_insert).
+    pub fn response_headers_mut(&mut self) -> &mut HeaderMap {
+        if self.try_borrow::<ResponseHeaders>().is_none() {
+            self.insert(ResponseHeaders::default());
+        }
+        &mut self.borrow_mut::<ResponseHeaders>().0
+    }
+
+    /// Record the request headers the selected route was dispatched on, for
+    /// the `Vary` header of the response.
+    pub(crate) fn vary(&mut self, names: Vec<HeaderName>) {
+        if names.is_empty() {
+            return;
+        }
+        match self.try_borrow_mut::<Vary>() {
+            Some(Vary(vary)) => vary.extend(names),
+            None => {
+                self.insert(Vary(names));
+            }
+        }
+    }
+
+    /// Add the staged headers to the response, and the request headers the
+    /// routes were dispatched on to its `Vary` header.
+    pub(crate) fn apply_response_headers(&mut self, res: &mut Response<Body>) {
+        let headers = res.headers_mut();
+        if let Some(ResponseHeaders(staged)) = self.try_take::<ResponseHeaders>() {
+            let mut name = None;
+            for (key, value) in staged {
+                if key.is_some() {
+                    name = key.filter(|key| !headers.contains_key(key));
+                }
+                if let Some(name) = &name {
+                    headers.append(name.clone(), value);
+                }
+            }
+        }
+        if let Some(Vary(vary)) = self.try_take::<Vary>() {
+            for name in &vary {
+                accept::add_vary(headers, name);
+            }
+        }
+    }
+
+    /// A cookie of the request, or the one set while handling it.
+    #[must_use]
+    pub fn cookie(&self, name: &str) -> Option<Cookie<'static>> {
+        match self.try_borrow::<Cookies>() {
+            Some(Cookies(jar)) => jar.get(name).cloned(),
+            None => cookies::parse(self.try_borrow::<HeaderMap>()).find(|cookie| cookie.name() == name),
+        }
+    }
+
+    /// Send a cookie to the client, in a `Set-Cookie` header added to the
+    /// response once the request is handled.
+    pub fn set_cookie(&mut self, cookie: Cookie<'static>) {
+        self.cookie_jar().add(cookie);
+    }
+
+    /// Remove a cookie from the client. The path and domain must match the
+    /// ones the cookie was set with.
+    pub fn remove_cookie(&mut self, cookie: impl Into<Cookie<'static>>) {
+        self.cookie_jar().remove(cookie);
+    }
+
+    /// The signed cookies of the request, which clients can read but not
+    /// change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server has no [`CookieKeys`](crate::CookieKeys), see
+    /// [`Server::cookie_keys`](crate::Server::cookie_keys).
+    pub fn signed_cookies(&mut self) -> SignedJar<'_> {
+        let keys = self.cookie_keys();
+        SignedJar::new(self.cookie_jar(), keys)
+    }
+
+    /// The encrypted cookies of the request, which clients can neither read
+    /// nor change.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server has no [`CookieKeys`](crate::CookieKeys), see
+    /// [`Server::cookie_keys`](crate::Server::cookie_keys).
+    pub fn private_cookies(&mut self) -> PrivateJar<'_> {
+        let keys = self.cookie_keys();
+        PrivateJar::new(self.cookie_jar(), keys)
+    }
+
+    fn cookie_keys(&self) -> CookieKeys {
+        self.try_borrow::<CookieKeys>()
+            .cloned()
+            .expect("Cookie keys are not configured, use `Server::cookie_keys`")
+    }
+
+    fn cookie_jar(&mut self) -> &mut CookieJar {
+        if self.try_borrow::<Cookies>().is_none() {
+            let cookies = Cookies::from_headers(self.try_borrow::<HeaderMap>());
+            self.insert(cookies);
+        }
+        &mut self.borrow_mut::<Cookies>().0
+    }
+
+    /// Add a `Set-Cookie` header to the response for every cookie set or
+    /// removed.
+    pub(crate) fn apply_cookies(&mut self, res: &mut Response<Body>) {
+        if let Some(cookies) = self.try_take::<Cookies>() {
+            cookies.apply(res);
+        }
+    }
+
+    /// The [`Channels`](crate::channels::Channels) carrying messages of type
+    /// `T`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server has no such channels, see
+    /// [`Server::channels`](crate::Server::channels).
+    #[must_use]
+    pub fn channels<T: Clone + Send + 'static>(&self) -> Channels<T> {
+        self.try_borrow::<Channels<T>>()
+            .cloned()
+            .expect("Channels are not configured, use `Server::channels`")
+    }
+
+    /// Subscribe to the topic named `topic` of the channels carrying
+    /// messages of type `T`, see [`Context::channels`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the server has no such channels.
+    #[must_use]
+    pub fn subscribe<T: Clone + Send + 'static>(&self, topic: &str) -> Subscription<T> {
+        self.channels::<T>().subscribe(topic)
+    }
+
+    /// The session of the request, loaded by the
+    /// [`Sessions`](crate::sessions::Sessions) middleware.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request didn't pass through the `Sessions` middleware.
+    #[must_use]
+    pub fn session(&self) -> &Session {
+        self.try_borrow::<Session>()
+            .expect("There is no session, add the `Sessions` middleware")
+    }
+
+    /// Change the session of the request, see [`Context::session`]. The
+    /// changes are saved once the request is handled.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the request didn't pass through the `Sessions` middleware.
+    pub fn session_mut(&mut self) -> &mut Session {
+        self.try_borrow_mut::<Session>()
+            .expect("There is no session, add the `Sessions` middleware")
+    }
+
+    /// Evaluate the conditional headers of the request, like `If-Match`,
+    /// against `current`, the validators of the current version of the
+    /// resource, or `None` if it doesn't exist. Call it before changing the
+    /// resource, e.g. in a `PUT` or `PATCH` endpoint. See the
+    /// [`conditional`](crate::conditional) module.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `412 Precondition Failed` if a precondition doesn't hold,
+    /// and with `304 Not Modified` for `GET` and `HEAD` requests if the
+    /// client's copy is current.
+    pub fn check_preconditions(&mut self, current: Option<&Validators>) -> crate::Result<()> {
+        conditional::check(self, current)
+    }
+
+    /// The request body as a stream of chunks, see [`BodyStream`]. Takes the
+    /// body out of the context.
+    pub fn body_stream(&mut self) -> BodyStream {
+        let length = self
+            .header(CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        BodyStream::new(self.try_take::<Body>().unwrap_or_default(), length)
+    }
+
+    /// The fields of a `multipart/form-data` request body, read with the
+    /// [`Limits`](crate::multipart::Limits) added with
+    /// [`Server::with_state`](crate::Server::with_state), or the default ones.
+    /// Takes the body out of the context.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `415 Unsupported Media Type` if the request isn't
+    /// `multipart/form-data`.
+    pub fn multipart(&mut self) -> crate::Result<Multipart> {
+        let limits = self.try_borrow::<Limits>().cloned().unwrap_or_default();
+        self.multipart_with(&limits)
+    }
+
+    /// The fields of a `multipart/form-data` request body, read with the
+    /// given limits, see [`Context::multipart`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with `415 Unsupported Media Type` if the request isn't
+    /// `multipart/form-data`.
+    pub fn multipart_with(&mut self, limits: &Limits) -> crate::Result<Multipart> {
+        let boundary = multipart::boundary(self.try_borrow::<HeaderMap>())?;
+        let body = self.try_take::<Body>().unwrap_or_default();
+        Ok(Multipart::new(body, boundary, limits))
+    }
+
+    /// The fields of a form, deserialized from an
+    /// `application/x-www-form-urlencoded` body, or from the text fields of a
+    /// `multipart/form-data` one. Files are skipped. Both are read with the
+    /// limits of [`Context::multipart`].
+    ///
+    /// # Errors
+    ///
+    /// Fails with `415 Unsupported Media Type` if the body is neither, with
+    /// `413 Payload Too Large` if it exceeds the total limit, and with
+    /// `400 Bad Request` if the fields don't match `T`.
+    pub async fn form_data<T: DeserializeOwned>(&mut self) -> crate::Result<T> {
+        let urlencoded = self
+            .header(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(';').next())
+            .is_some_and(|essence| {
+                essence
+                    .trim()
+                    .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+            });
+        if !urlencoded {
+            return multipart::form_data(self.multipart()?).await;
+        }
+
+        let limits = self.try_borrow::<Limits>().cloned().unwrap_or_default();
+        let mut body = self.body_stream().limit(limits.total_limit());
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.try_next().await? {
+            bytes.extend_from_slice(&chunk);
+        }
+        serde_urlencoded::from_bytes(&bytes).map_err(|err| Error::new(StatusCode::BAD_REQUEST, err))
+    }
+
     /// Extract and parse a route parameter by name.
     ///
     /// Returns the parameter as a `&str`, borrowed from this `Request`.
@@ -148,4 +499,64 @@ impl Context {
             .rev()
             .find_map(|captures| captures.wildcard())
     }
+
+    /// The URI of the request before any prefix was stripped by a nested
+    /// [`Server`](crate::Server).
+    ///
+    /// Inside a nested server the request URI only contains the path relative
+    /// to the mount point; this returns the URI the outermost server routed
+    /// on, including the query string.
+    #[must_use]
+    pub fn original_uri(&self) -> &Uri {
+        match self.try_borrow::<OriginalUri>() {
+            Some(OriginalUri(uri)) => uri,
+            None => self.borrow::<Uri>(),
+        }
+    }
+
+    /// The path a nested [`Server`](crate::Server) is mounted at, e.g.
+    /// `/api/v1` for a server nested at `/api`, which is in turn nested at
+    /// `/v1`. Empty outside of nested servers.
+    ///
+    /// Prepending the mount path to a path within the nested server yields
+    /// the path the client has to request.
+    #[must_use]
+    pub fn mount_path(&self) -> &str {
+        self.try_borrow::<MountPath>()
+            .map_or("", |MountPath(path)| path.as_str())
+    }
+
+    /// The route selected to handle the request, or `None` if no route
+    /// matched and the request is answered with e.g. `404 Not Found`.
+    ///
+    /// The route is selected before any middleware runs, so middleware
+    /// applied with [`Server::with`](crate::Server::with) can see it too.
+    #[must_use]
+    pub fn route(&self) -> Option<&MatchedRoute> {
+        self.try_borrow::<Arc<MatchedRoute>>().map(AsRef::as_ref)
+    }
+
+    /// Borrow the metadata of type `T` attached to the route handling the
+    /// request with [`Route::meta`](crate::Route::meta).
+    #[must_use]
+    pub fn meta<T: 'static>(&self) -> Option<&T> {
+        self.route()?.meta::<T>()
+    }
+
+    /// Record the route selected by a server. Inside a nested server, the
+    /// route is combined with the route the server is nested at.
+    pub(crate) fn set_route(&mut self, route: Option<Arc<MatchedRoute>>) {
+        match route {
+            Some(route) => {
+                let route = match self.try_borrow::<Arc<MatchedRoute>>() {
+                    Some(outer) => Arc::new(outer.nest(&route)),
+                    None => route,
+                };
+                self.insert(route);
+            }
+            None => {
+                let _ = self.try_take::<Arc<MatchedRoute>>();
+            }
+        }
+    }
 }
@@ -0,0 +1,218 @@
+//! Request and response cookies.
+
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+use cookie::{Cookie, CookieJar, Key};
+use hyper::header::{HeaderValue, COOKIE, SET_COOKIE};
+use hyper::{Body, HeaderMap, Response};
+
+/// The keys signing and encrypting cookies, see
+/// [`Server::cookie_keys`](crate::Server::cookie_keys).
+///
+/// New cookies are signed and encrypted with the current key. To rotate
+/// keys without invalidating the cookies clients already have, keep the
+/// previous keys around: cookies are still read with them. As the `Cookie`
+/// header only carries the name and value of a cookie, re-issuing it with
+/// the current key is left to the caller, who knows its attributes, see
+/// [`SignedJar::needs_rotation`].
+///
+/// ```rust,no_run
+/// # use envoy_http as envoy;
+/// # fn load_key(_: &str) -> Vec<u8> { vec![0; 64] }
+/// use envoy::cookie::Key;
+/// use envoy::CookieKeys;
+///
+/// let keys = CookieKeys::new(Key::from(&load_key("2024-06")))
+///     .previous(Key::from(&load_key("2024-01")));
+///
+/// let mut app = envoy::Server::new();
+/// app.cookie_keys(keys);
+/// ```
+#[derive(Clone)]
+pub struct CookieKeys {
+    /// The current key, followed by the previous ones.
+    keys: Arc<Vec<Key>>,
+}
+
+impl CookieKeys {
+    /// Sign and encrypt cookies with `key`.
+    #[must_use]
+    pub fn new(key: Key) -> Self {
+        Self {
+            keys: Arc::new(vec![key]),
+        }
+    }
+
+    /// Also read cookies signed or encrypted with a previous key. Keys added
+    /// later are tried later.
+    #[must_use]
+    pub fn previous(mut self, key: Key) -> Self {
+        Arc::make_mut(&mut self.keys).push(key);
+        self
+    }
+
+    fn current(&self) -> &Key {
+        &self.keys[0]
+    }
+}
+
+impl Debug for CookieKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CookieKeys")
+            .field("keys", &self.keys.len())
+            .finish()
+    }
+}
+
+/// The cookies of a request, and the changes to send back.
+#[derive(Debug)]
+pub(crate) struct Cookies(pub(crate) CookieJar);
+
+impl Cookies {
+    /// Parse the cookies of the `Cookie` headers.
+    pub(crate) fn from_headers(headers: Option<&HeaderMap>) -> Self {
+        let mut jar = CookieJar::new();
+        for cookie in parse(headers) {
+            jar.add_original(cookie);
+        }
+        Self(jar)
+    }
+
+    /// Add a `Set-Cookie` header to the response for every change.
+    pub(crate) fn apply(&self, res: &mut Response<Body>) {
+        for cookie in self.0.delta() {
+            if let Ok(value) = HeaderValue::from_str(&cookie.to_string()) {
+                res.headers_mut().append(SET_COOKIE, value);
+            }
+        }
+    }
+}
+
+/// The cookies of the `Cookie` headers. Invalid cookies are skipped.
+pub(crate) fn parse(headers: Option<&HeaderMap>) -> impl Iterator<Item = Cookie<'static>> + '_ {
+    headers
+        .into_iter()
+        .flat_map(|headers| headers.get_all(COOKIE))
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(Cookie::split_parse)
+        .filter_map(Result::ok)
+        .map(Cookie::into_owned)
+}
+
+/// The cookies of a request that are signed, so clients can read but not
+/// change them. Returned by
+/// [`Context::signed_cookies`](crate::Context::signed_cookies).
+#[derive(Debug)]
+pub struct SignedJar<'a> {
+    jar: &'a mut CookieJar,
+    keys: CookieKeys,
+}
+
+impl<'a> SignedJar<'a> {
+    pub(crate) fn new(jar: &'a mut CookieJar, keys: CookieKeys) -> Self {
+        Self { jar, keys }
+    }
+
+    /// The cookie named `name`, if it's signed with one of the keys.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        self.find(name).map(|(_, cookie)| cookie)
+    }
+
+    /// Whether the cookie named `name` is only signed with a previous key.
+    /// Such a cookie should be added again, with the attributes it was set
+    /// with, so it's signed with the current key:
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::cookie::Cookie;
+    /// use envoy::{Body, Response};
+    ///
+    /// async fn account(ctx: &mut envoy::Context) -> envoy::Result {
+    ///     let mut jar = ctx.signed_cookies();
+    ///     if let (true, Some(user)) = (jar.needs_rotation("user"), jar.get("user")) {
+    ///         jar.add(Cookie::build(user).path("/").http_only(true).secure(true).build());
+    ///     }
+    ///     Ok(Response::new(Body::empty()))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.at("/account").get(account);
+    /// ```
+    #[must_use]
+    pub fn needs_rotation(&self, name: &str) -> bool {
+        self.find(name).is_some_and(|(index, _)| index > 0)
+    }
+
+    /// The cookie named `name` and the index of the key it's signed with.
+    fn find(&self, name: &str) -> Option<(usize, Cookie<'static>)> {
+        self.keys
+            .keys
+            .iter()
+            .enumerate()
+            .find_map(|(index, key)| Some((index, self.jar.signed(key).get(name)?)))
+    }
+
+    /// Sign a cookie with the current key and send it to the client.
+    pub fn add(&mut self, cookie: Cookie<'static>) {
+        self.jar.signed_mut(self.keys.current()).add(cookie);
+    }
+
+    /// Remove a cookie from the client. The path and domain must match the
+    /// ones the cookie was set with.
+    pub fn remove(&mut self, cookie: impl Into<Cookie<'static>>) {
+        self.jar.signed_mut(self.keys.current()).remove(cookie);
+    }
+}
+
+/// The cookies of a request that are encrypted, so clients can neither read
+/// nor change them. Returned by
+/// [`Context::private_cookies`](crate::Context::private_cookies).
+#[derive(Debug)]
+pub struct PrivateJar<'a> {
+    jar: &'a mut CookieJar,
+    keys: CookieKeys,
+}
+
+impl<'a> PrivateJar<'a> {
+    pub(crate) fn new(jar: &'a mut CookieJar, keys: CookieKeys) -> Self {
+        Self { jar, keys }
+    }
+
+    /// The cookie named `name`, decrypted, if it's encrypted with one of the
+    /// keys.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Cookie<'static>> {
+        self.find(name).map(|(_, cookie)| cookie)
+    }
+
+    /// Whether the cookie named `name` is only encrypted with a previous
+    /// key. Like with [`SignedJar::needs_rotation`], such a cookie should be
+    /// added again, with the attributes it was set with, so it's encrypted
+    /// with the current key.
+    #[must_use]
+    pub fn needs_rotation(&self, name: &str) -> bool {
+        self.find(name).is_some_and(|(index, _)| index > 0)
+    }
+
+    /// The cookie named `name` and the index of the key it's encrypted with.
+    fn find(&self, name: &str) -> Option<(usize, Cookie<'static>)> {
+        self.keys
+            .keys
+            .iter()
+            .enumerate()
+            .find_map(|(index, key)| Some((index, self.jar.private(key).get(name)?)))
+    }
+
+    /// Encrypt a cookie with the current key and send it to the client.
+    pub fn add(&mut self, cookie: Cookie<'static>) {
+        self.jar.private_mut(self.keys.current()).add(cookie);
+    }
+
+    /// Remove a cookie from the client. The path and domain must match the
+    /// ones the cookie was set with.
+    pub fn remove(&mut self, cookie: impl Into<Cookie<'static>>) {
+        self.jar.private_mut(self.keys.current()).remove(cookie);
+    }
+}
@@ -0,0 +1,298 @@
+//! Deserializing route params, for the [`Path`](crate::extract::Path)
+//! extractor.
+//!
+//! The params of a route are deserialized as a map from their names to their
+//! values, as a sequence of their values, or, if the route has exactly one
+//! param, as that value. Values are parsed from their text according to the
+//! type they are deserialized into.
+
+use std::fmt::{self, Display};
+use std::slice;
+
+use serde::de::{self, DeserializeSeed, IntoDeserializer, MapAccess, SeqAccess, Visitor};
+use serde::forward_to_deserialize_any;
+
+/// An error deserializing route params.
+#[derive(Debug)]
+pub(crate) struct ParamsError(String);
+
+impl Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ParamsError {}
+
+impl de::Error for ParamsError {
+    fn custom<T: Display>(msg: T) -> Self {
+        Self(msg.to_string())
+    }
+}
+
+/// Route params as pairs of names and values.
+pub(crate) struct Params<'de>(pub(crate) &'de [(&'de str, &'de str)]);
+
+impl<'de> Params<'de> {
+    fn single(&self) -> Result<Value<'de>, ParamsError> {
+        match self.0 {
+            [(_, value)] => Ok(Value(value)),
+            params => Err(ParamsError(format!(
+                "Expected a single route param, found {}",
+                params.len()
+            ))),
+        }
+    }
+}
+
+macro_rules! single {
+    ($($method:ident)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            self.single()?.$method(visitor)
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for Params<'de> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_map(ParamsMap {
+            params: self.0.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_seq(ParamsSeq(self.0.iter()))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        if len == self.0.len() {
+            self.deserialize_seq(visitor)
+        } else {
+            Err(ParamsError(format!(
+                "Expected {} route params, found {}",
+                len,
+                self.0.len()
+            )))
+        }
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.deserialize_tuple(len, visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        self.single()?.deserialize_enum(name, variants, visitor)
+    }
+
+    single! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string deserialize_bytes
+        deserialize_byte_buf deserialize_option deserialize_unit deserialize_identifier
+    }
+}
+
+struct ParamsMap<'de> {
+    params: slice::Iter<'de, (&'de str, &'de str)>,
+    value: Option<&'de str>,
+}
+
+impl<'de> MapAccess<'de> for ParamsMap<'de> {
+    type Error = ParamsError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Self::Error> {
+        match self.params.next() {
+            Some((name, value)) => {
+                self.value = Some(value);
+                seed.deserialize(Value(name)).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Self::Error> {
+        let value = self.value.take().expect("A value is only requested after its key");
+        seed.deserialize(Value(value))
+    }
+}
+
+struct ParamsSeq<'de>(slice::Iter<'de, (&'de str, &'de str)>);
+
+impl<'de> SeqAccess<'de> for ParamsSeq<'de> {
+    type Error = ParamsError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Self::Error> {
+        match self.0.next() {
+            Some((_, value)) => seed.deserialize(Value(value)).map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The value of a single route param.
+struct Value<'de>(&'de str);
+
+macro_rules! parse {
+    ($($method:ident => $visit:ident: $ty:ty,)*) => {$(
+        fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            match self.0.parse::<$ty>() {
+                Ok(value) => visitor.$visit(value),
+                Err(_) => Err(ParamsError(format!(
+                    "Could not parse `{}` as {}",
+                    self.0,
+                    stringify!($ty)
+                ))),
+            }
+        }
+    )*};
+}
+
+impl<'de> de::Deserializer<'de> for Value<'de> {
+    type Error = ParamsError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_borrowed_str(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error> {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    parse! {
+        deserialize_bool => visit_bool: bool,
+        deserialize_i8 => visit_i8: i8,
+        deserialize_i16 => visit_i16: i16,
+        deserialize_i32 => visit_i32: i32,
+        deserialize_i64 => visit_i64: i64,
+        deserialize_u8 => visit_u8: u8,
+        deserialize_u16 => visit_u16: u16,
+        deserialize_u32 => visit_u32: u32,
+        deserialize_u64 => visit_u64: u64,
+        deserialize_f32 => visit_f32: f32,
+        deserialize_f64 => visit_f64: f64,
+    }
+
+    forward_to_deserialize_any! {
+        char str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use serde::Deserialize;
+
+    use super::*;
+
+    fn params<T: serde::de::DeserializeOwned>(params: &[(&str, &str)]) -> Result<T, ParamsError> {
+        T::deserialize(Params(params))
+    }
+
+    #[test]
+    fn deserializes_params() {
+        #[derive(Debug, Deserialize, PartialEq)]
+        #[serde(rename_all = "lowercase")]
+        enum Kind {
+            Post,
+            Page,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        struct Item {
+            kind: Kind,
+            id: u32,
+            draft: Option<bool>,
+        }
+
+        let pair = [("kind", "post"), ("id", "42")];
+        assert_eq!(
+            params::<Item>(&pair).unwrap(),
+            Item { kind: Kind::Post, id: 42, draft: None }
+        );
+        assert_eq!(params::<(Kind, u64)>(&pair).unwrap(), (Kind::Post, 42));
+        assert_eq!(params::<Vec<String>>(&pair).unwrap(), ["post", "42"]);
+        assert_eq!(params::<HashMap<String, String>>(&pair).unwrap()["id"], "42");
+        assert_eq!(params::<Kind>(&[("kind", "page")]).unwrap(), Kind::Page);
+        assert_eq!(params::<i16>(&[("n", "-7")]).unwrap(), -7);
+
+        assert!(params::<u32>(&pair).is_err());
+        assert!(params::<(u32, u32)>(&pair).is_err());
+        assert!(params::<(Kind, u32, u32)>(&pair).is_err());
+        assert!(params::<u8>(&[("n", "300")]).is_err());
+    }
+}
@@ -0,0 +1,258 @@
+//! Assets embedded in the binary.
+//!
+//! A build script embeds a directory with [`generate`], which writes a Rust
+//! source file including every file of the directory with `include_bytes!`,
+//! along with ETags computed from their contents:
+//!
+//! ```rust,no_run
+//! // build.rs
+//! fn main() -> std::io::Result<()> {
+//!     envoy_http::embed::generate("public", "assets.rs")
+//! }
+//! ```
+//!
+//! The generated file evaluates to an [`EmbeddedDir`], served like a
+//! directory on disk with [`Route::serve_embedded`], including conditional
+//! and range requests:
+//!
+//! ```rust,ignore
+//! use envoy_http::embed::EmbeddedDir;
+//!
+//! static ASSETS: EmbeddedDir = include!(concat!(env!("OUT_DIR"), "/assets.rs"));
+//!
+//! let mut app = envoy_http::Server::new();
+//! app.at("/static").serve_embedded(&ASSETS);
+//! ```
+//!
+//! The `.br` and `.gz` siblings of a file, e.g. `app.js.br`, are embedded
+//! as precompressed variants of it and served to clients accepting them.
+//!
+//! [`Route::serve_embedded`]: crate::Route::serve_embedded
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use async_trait::async_trait;
+
+use crate::conditional::content_etag;
+use crate::fs::{self, Representation, Source};
+use crate::{Context, Endpoint, Error};
+
+/// A directory embedded in the binary, usually generated by [`generate`].
+#[derive(Debug)]
+pub struct EmbeddedDir {
+    files: &'static [EmbeddedFile],
+}
+
+/// A file of an [`EmbeddedDir`].
+#[derive(Debug)]
+pub struct EmbeddedFile {
+    /// The path of the file relative to the directory, separated by `/`.
+    pub path: &'static str,
+    /// The contents of the file.
+    pub contents: &'static [u8],
+    /// The strong ETag of the contents, including the quotes.
+    pub etag: &'static str,
+    /// The precompressed variants of the file.
+    pub variants: &'static [Variant],
+}
+
+/// A precompressed variant of an [`EmbeddedFile`].
+#[derive(Debug)]
+pub struct Variant {
+    /// The content coding, `br` or `gzip`.
+    pub coding: &'static str,
+    /// The compressed contents.
+    pub contents: &'static [u8],
+    /// The strong ETag of the compressed contents, including the quotes.
+    pub etag: &'static str,
+}
+
+impl EmbeddedDir {
+    /// An embedded directory of `files`, which have to be sorted by path.
+    pub const fn new(files: &'static [EmbeddedFile]) -> Self {
+        Self { files }
+    }
+
+    /// The file at `path`, relative to the directory.
+    #[must_use]
+    pub fn get(&self, path: &str) -> Option<&'static EmbeddedFile> {
+        self.files
+            .binary_search_by(|file| file.path.cmp(path))
+            .ok()
+            .map(|index| &self.files[index])
+    }
+
+    /// The files of the directory, sorted by path.
+    pub fn files(&self) -> impl Iterator<Item = &'static EmbeddedFile> {
+        self.files.iter()
+    }
+}
+
+/// An endpoint serving an [`EmbeddedDir`], see
+/// [`Route::serve_embedded`](crate::Route::serve_embedded).
+///
+/// Like [`ServeDir`](crate::fs::ServeDir), the file is selected by the
+/// wildcard of the route, and requests for a directory are answered with
+/// its `index.html`, if any.
+#[derive(Debug, Clone)]
+pub struct ServeEmbedded {
+    dir: &'static EmbeddedDir,
+    precompressed: bool,
+}
+
+impl ServeEmbedded {
+    /// Serve the files of `dir`.
+    #[must_use]
+    pub fn new(dir: &'static EmbeddedDir) -> Self {
+        Self {
+            dir,
+            precompressed: true,
+        }
+    }
+
+    /// Don't serve the precompressed variants of files.
+    #[must_use]
+    pub fn without_precompressed(mut self) -> Self {
+        self.precompressed = false;
+        self
+    }
+}
+
+#[async_trait]
+impl Endpoint for ServeEmbedded {
+    async fn call(&self, ctx: &mut Context) -> crate::Result {
+        let path = fs::relative_path(ctx.wildcard().unwrap_or("")).ok_or_else(fs::not_found)?;
+        let file = match self.dir.get(&path) {
+            Some(file) => file,
+            None => {
+                let index = match path.as_str() {
+                    "" => "index.html".to_owned(),
+                    path => format!("{}/index.html", path),
+                };
+                let file = self.dir.get(&index).ok_or_else(fs::not_found)?;
+                if let Some(res) = fs::redirect_to_directory(ctx)? {
+                    return Ok(res);
+                }
+                file
+            }
+        };
+
+        let variant = if self.precompressed {
+            fs::accepted_codings(ctx).into_iter().find_map(|(coding, _)| {
+                file.variants.iter().find(|variant| variant.coding == coding)
+            })
+        } else {
+            None
+        };
+        let (contents, etag) = match variant {
+            Some(variant) => (variant.contents, variant.etag),
+            None => (file.contents, file.etag),
+        };
+
+        fs::respond(
+            ctx,
+            Representation {
+                source: Source::Static(contents),
+                len: contents.len() as u64,
+                etag: etag.parse().map_err(|_| Error::from_str(500, "Invalid ETag"))?,
+                last_modified: None,
+                content_type: fs::mime_type(Path::new(file.path)),
+                coding: variant.map(|variant| variant.coding),
+                precompressed: self.precompressed && !file.variants.is_empty(),
+            },
+        )
+    }
+}
+
+/// Embed the directory `dir`, writing the generated source to `out`.
+///
+/// Meant to be called from a build script: a relative `out` is resolved
+/// against the `OUT_DIR` of the build, and Cargo is told to rerun the build
+/// script when the directory changes. The generated source is an
+/// expression of type [`EmbeddedDir`], to be included with `include!`.
+///
+/// # Errors
+///
+/// Fails if the directory can't be read, contains paths that aren't valid
+/// UTF-8, or `out` can't be written.
+pub fn generate(dir: impl AsRef<Path>, out: impl AsRef<Path>) -> io::Result<()> {
+    let dir = std::fs::canonicalize(dir)?;
+    let mut files = BTreeMap::new();
+    collect(&dir, &dir, &mut files)?;
+
+    let mut source = String::from("::envoy_http::embed::EmbeddedDir::new(&[\n");
+    for (path, file) in &files {
+        // Precompressed siblings are embedded as variants of their file.
+        let sibling_of = path.strip_suffix(".br").or_else(|| path.strip_suffix(".gz"));
+        if sibling_of.is_some_and(|original| files.contains_key(original)) {
+            continue;
+        }
+
+        let _ = writeln!(source, "    ::envoy_http::embed::EmbeddedFile {{");
+        let _ = writeln!(source, "        path: {:?},", path);
+        let _ = writeln!(source, "        contents: include_bytes!({:?}),", to_str(file)?);
+        let _ = writeln!(source, "        etag: {:?},", content_etag(&std::fs::read(file)?));
+        let _ = writeln!(source, "        variants: &[");
+        for (coding, extension) in [("br", "br"), ("gzip", "gz")] {
+            let variant = match files.get(&format!("{}.{}", path, extension)) {
+                Some(variant) => variant,
+                None => continue,
+            };
+            let _ = writeln!(source, "            ::envoy_http::embed::Variant {{");
+            let _ = writeln!(source, "                coding: {:?},", coding);
+            let _ = writeln!(
+                source,
+                "                contents: include_bytes!({:?}),",
+                to_str(variant)?
+            );
+            let _ = writeln!(source, "                etag: {:?},", content_etag(&std::fs::read(variant)?));
+            let _ = writeln!(source, "            }},");
+        }
+        let _ = writeln!(source, "        ],");
+        let _ = writeln!(source, "    }},");
+    }
+    source.push_str("])\n");
+
+    let out = match std::env::var_os("OUT_DIR") {
+        Some(out_dir) => Path::new(&out_dir).join(out),
+        None => out.as_ref().to_owned(),
+    };
+    std::fs::write(out, source)
+}
+
+/// Collect the files below `path` by their path relative to `root`, telling
+/// Cargo to rerun the build script when any of them changes.
+fn collect(root: &Path, path: &Path, files: &mut BTreeMap<String, PathBuf>) -> io::Result<()> {
+    println!("cargo:rerun-if-changed={}", to_str(path)?);
+    for entry in std::fs::read_dir(path)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect(root, &path, files)?;
+            continue;
+        }
+
+        println!("cargo:rerun-if-changed={}", to_str(&path)?);
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let segments: Vec<&str> = relative
+            .components()
+            .map(|component| component.as_os_str().to_str())
+            .collect::<Option<_>>()
+            .ok_or_else(|| invalid_path(&path))?;
+        files.insert(segments.join("/"), path);
+    }
+    Ok(())
+}
+
+fn to_str(path: &Path) -> io::Result<&str> {
+    path.to_str().ok_or_else(|| invalid_path(path))
+}
+
+fn invalid_path(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("path isn't valid UTF-8: {}", path.display()),
+    )
+}
@@ -1,11 +1,13 @@
 use std::fmt::Debug;
 use std::future::Future;
+use std::marker::PhantomData;
 use std::sync::Arc;
 
 use async_trait::async_trait;
 
+use crate::extract::FromContext;
 use crate::middleware::{Next};
-use crate::{Middleware};
+use crate::{Context, Middleware};
 
 /// An HTTP request handler.
 ///
@@ -61,6 +63,186 @@ impl<F: Fn(Arg1) -> O, Arg1, O> Fn1<Arg1> for F {
     type Output = O;
 }
 
+/// Something that can be registered as an endpoint.
+///
+/// This is implemented for every [`Endpoint`], and for async functions
+/// taking up to 12 arguments implementing [`FromContext`], optionally
+/// preceded by the [`Context`]:
+///
+/// ```rust,no_run
+/// # use envoy_http as envoy;
+/// use envoy::extract::Path;
+/// use envoy::{Body, Response};
+///
+/// async fn user(Path(id): Path<u64>) -> envoy::Result {
+///     Ok(Response::new(Body::from(format!("user {}", id))))
+/// }
+///
+/// async fn post(ctx: &mut envoy::Context, Path((user, post)): Path<(u64, u64)>) -> envoy::Result {
+///     let route = ctx.route().map_or("", |route| route.pattern());
+///     Ok(Response::new(Body::from(format!("{}: post {} of user {}", route, post, user))))
+/// }
+///
+/// let mut app = envoy::Server::new();
+/// app.at("/users/:id").get(user);
+/// app.at("/users/:user/posts/:post").get(post);
+/// ```
+///
+/// The `Args` parameter only tells the implementations apart, and is
+/// inferred when registering an endpoint.
+pub trait IntoEndpoint<Args> {
+    /// The endpoint this turns into.
+    type Endpoint: Endpoint + 'static;
+
+    /// Turn this into an endpoint.
+    fn into_endpoint(self) -> Self::Endpoint;
+}
+
+/// The `Args` of [`IntoEndpoint`] for endpoints themselves.
+#[derive(Debug)]
+pub enum AsIs {}
+
+/// The `Args` of [`IntoEndpoint`] for functions taking the [`Context`]
+/// before the extracted arguments.
+#[derive(Debug)]
+pub struct WithContext<Args>(PhantomData<Args>);
+
+impl<E: Endpoint + 'static> IntoEndpoint<AsIs> for E {
+    type Endpoint = Self;
+
+    fn into_endpoint(self) -> Self {
+        self
+    }
+}
+
+/// An endpoint calling a function with arguments extracted from the context.
+pub struct ExtractEndpoint<F, Args> {
+    handler: F,
+    args: PhantomData<fn() -> Args>,
+}
+
+impl<F, Args> Debug for ExtractEndpoint<F, Args> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ExtractEndpoint<{}>", std::any::type_name::<F>())
+    }
+}
+
+impl<F, Args> ExtractEndpoint<F, Args> {
+    fn new(handler: F) -> Self {
+        Self {
+            handler,
+            args: PhantomData,
+        }
+    }
+}
+
+trait ContextFn<'a, Args> {
+    type Output;
+
+    fn call(&self, ctx: &'a mut Context, args: Args) -> Self::Output;
+}
+
+macro_rules! extract {
+    ($($arg:ident),*) => {
+        impl<F, Fut, $($arg,)*> IntoEndpoint<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = crate::Result> + Send + 'static,
+            $($arg: FromContext + 'static,)*
+        {
+            type Endpoint = ExtractEndpoint<F, ($($arg,)*)>;
+
+            fn into_endpoint(self) -> Self::Endpoint {
+                ExtractEndpoint::new(self)
+            }
+        }
+
+        #[async_trait]
+        impl<F, Fut, $($arg,)*> Endpoint for ExtractEndpoint<F, ($($arg,)*)>
+        where
+            F: Fn($($arg),*) -> Fut + Send + Sync + 'static,
+            Fut: Future<Output = crate::Result> + Send + 'static,
+            $($arg: FromContext + 'static,)*
+        {
+            #[allow(non_snake_case, unused_variables)]
+            async fn call(&self, ctx: &mut Context) -> crate::Result {
+                $(let $arg = $arg::from_context(ctx).await?;)*
+                (self.handler)($($arg),*).await
+            }
+        }
+    };
+}
+
+macro_rules! extract_with_context {
+    ($($arg:ident),*) => {
+        impl<'a, F, O, $($arg,)*> ContextFn<'a, ($($arg,)*)> for F
+        where
+            F: Fn(&'a mut Context, $($arg),*) -> O,
+        {
+            type Output = O;
+
+            #[allow(non_snake_case)]
+            fn call(&self, ctx: &'a mut Context, ($($arg,)*): ($($arg,)*)) -> O {
+                self(ctx, $($arg),*)
+            }
+        }
+
+        impl<F, $($arg,)*> IntoEndpoint<WithContext<($($arg,)*)>> for F
+        where
+            F: for<'a> ContextFn<'a, ($($arg,)*)> + Send + Sync + 'static,
+            for<'a> <F as ContextFn<'a, ($($arg,)*)>>::Output: Future<Output = crate::Result> + Send,
+            $($arg: FromContext + 'static,)*
+        {
+            type Endpoint = ExtractEndpoint<F, WithContext<($($arg,)*)>>;
+
+            fn into_endpoint(self) -> Self::Endpoint {
+                ExtractEndpoint::new(self)
+            }
+        }
+
+        #[async_trait]
+        impl<F, $($arg,)*> Endpoint for ExtractEndpoint<F, WithContext<($($arg,)*)>>
+        where
+            F: for<'a> ContextFn<'a, ($($arg,)*)> + Send + Sync + 'static,
+            for<'a> <F as ContextFn<'a, ($($arg,)*)>>::Output: Future<Output = crate::Result> + Send,
+            $($arg: FromContext + 'static,)*
+        {
+            #[allow(non_snake_case)]
+            async fn call(&self, ctx: &mut Context) -> crate::Result {
+                $(let $arg = $arg::from_context(ctx).await?;)*
+                self.handler.call(ctx, ($($arg,)*)).await
+            }
+        }
+    };
+}
+
+extract!();
+extract!(T1);
+extract!(T1, T2);
+extract!(T1, T2, T3);
+extract!(T1, T2, T3, T4);
+extract!(T1, T2, T3, T4, T5);
+extract!(T1, T2, T3, T4, T5, T6);
+extract!(T1, T2, T3, T4, T5, T6, T7);
+extract!(T1, T2, T3, T4, T5, T6, T7, T8);
+extract!(T1, T2, T3, T4, T5, T6, T7, T8, T9);
+extract!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+extract!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+extract!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+
+extract_with_context!(T1);
+extract_with_context!(T1, T2);
+extract_with_context!(T1, T2, T3);
+extract_with_context!(T1, T2, T3, T4);
+extract_with_context!(T1, T2, T3, T4, T5);
+extract_with_context!(T1, T2, T3, T4, T5, T6);
+extract_with_context!(T1, T2, T3, T4, T5, T6, T7);
+extract_with_context!(T1, T2, T3, T4, T5, T6, T7, T8);
+extract_with_context!(T1, T2, T3, T4, T5, T6, T7, T8, T9);
+extract_with_context!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10);
+extract_with_context!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11);
+extract_with_context!(T1, T2, T3, T4, T5, T6, T7, T8, T9, T10, T11, T12);
+
 pub(crate) struct MiddlewareEndpoint {
     endpoint: Arc<dyn Endpoint>,
     middleware: Arc<Vec<Arc<dyn Middleware>>>,
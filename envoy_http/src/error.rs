@@ -1,5 +1,6 @@
-use std::{fmt::{Debug, Display, self}, error::Error as StdError, convert::TryInto};
+use std::{fmt::{Debug, Display, self}, error::Error as StdError, convert::TryInto, future::Future};
 
+use async_trait::async_trait;
 use hyper::StatusCode;
 
 /// A http error.
@@ -212,4 +213,33 @@ impl AsRef<anyhow::Error> for Error {
     fn as_ref(&self) -> &anyhow::Error {
         &self.error
     }
-}
\ No newline at end of file
+}
+
+/// A handler for errors returned by the endpoints and middleware of a route.
+///
+/// This trait is automatically implemented for async functions taking the
+/// [`Context`](crate::Context) and the [`Error`], see
+/// [`Route::on_error`](crate::Route::on_error).
+#[async_trait]
+pub trait ErrorHandler: Send + Sync {
+    /// Handle the error, either turning it into a response or returning an error.
+    async fn handle(&self, ctx: &mut crate::Context, error: Error) -> crate::Result;
+}
+
+#[async_trait]
+impl<F> ErrorHandler for F
+where
+    F: for<'a> Fn2<&'a mut crate::Context, Error> + Sync + Send,
+    for<'a> <F as Fn2<&'a mut crate::Context, Error>>::Output: Future<Output = crate::Result> + Send,
+{
+    async fn handle(&self, ctx: &mut crate::Context, error: Error) -> crate::Result {
+        self(ctx, error).await
+    }
+}
+
+trait Fn2<Arg1, Arg2>: Fn(Arg1, Arg2) -> <Self as Fn2<Arg1, Arg2>>::Output {
+    type Output;
+}
+impl<F: Fn(Arg1, Arg2) -> O, Arg1, Arg2, O> Fn2<Arg1, Arg2> for F {
+    type Output = O;
+}
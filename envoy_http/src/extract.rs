@@ -0,0 +1,295 @@
+//! Typed arguments for endpoints.
+//!
+//! Besides taking the [`Context`], endpoints can be async functions taking up
+//! to 12 arguments implementing [`FromContext`], which are extracted from the
+//! request before the function is called:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::extract::{Json, Path, Query};
+//! use envoy::{Body, Response};
+//! use serde::Deserialize;
+//!
+//! #[derive(Deserialize)]
+//! struct Pagination {
+//!     page: u32,
+//! }
+//!
+//! #[derive(Deserialize)]
+//! struct Comment {
+//!     text: String,
+//! }
+//!
+//! async fn comments(Path(post): Path<u64>, Query(pagination): Query<Pagination>) -> envoy::Result {
+//!     let body = format!("comments on post {}, page {}", post, pagination.page);
+//!     Ok(Response::new(Body::from(body)))
+//! }
+//!
+//! async fn comment(Path(post): Path<u64>, Json(comment): Json<Comment>) -> envoy::Result {
+//!     let body = format!("comment on post {}: {}", post, comment.text);
+//!     Ok(Response::new(Body::from(body)))
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.at("/posts/:post/comments").get(comments).post(comment);
+//! ```
+//!
+//! Arguments are extracted in order. When one can't be extracted, the
+//! function isn't called and the request fails with the error of the
+//! extractor, e.g. `400 Bad Request` for a query string that doesn't match
+//! the expected type. Wrapping an extractor in an `Option` makes it optional
+//! instead: it's `None` when the value is missing, e.g. a header that wasn't
+//! sent, while a value that is present but invalid still fails the request.
+//!
+//! To have both the context and extracted arguments, take `&mut Context` as
+//! the first argument, followed by the extractors.
+
+use std::fmt::Debug;
+use std::ops::{Deref, DerefMut};
+
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use headers::HeaderMapExt;
+use hyper::header::CONTENT_TYPE;
+use hyper::{HeaderMap, StatusCode, Uri};
+use serde::de::DeserializeOwned;
+
+use crate::de::Params;
+use crate::multipart::Limits;
+use crate::{Context, Error};
+
+/// A value extracted from the context of a request, to be passed to an
+/// endpoint as an argument.
+#[async_trait]
+pub trait FromContext: Sized + Send {
+    /// Extract the value, or fail with an error, usually a `4xx` one.
+    async fn from_context(ctx: &mut Context) -> crate::Result<Self>;
+
+    /// Extract the value for an `Option<Self>` argument: `None` if it's
+    /// missing, and an error if it's present but can't be extracted.
+    ///
+    /// By default every error counts as a missing value.
+    async fn from_context_optional(ctx: &mut Context) -> crate::Result<Option<Self>> {
+        Ok(Self::from_context(ctx).await.ok())
+    }
+}
+
+#[async_trait]
+impl<T: FromContext> FromContext for Option<T> {
+    async fn from_context(ctx: &mut Context) -> crate::Result<Self> {
+        T::from_context_optional(ctx).await
+    }
+}
+
+macro_rules! deref {
+    ($($extractor:ident),*) => {$(
+        impl<T> Deref for $extractor<T> {
+            type Target = T;
+
+            fn deref(&self) -> &T {
+                &self.0
+            }
+        }
+
+        impl<T> DerefMut for $extractor<T> {
+            fn deref_mut(&mut self) -> &mut T {
+                &mut self.0
+            }
+        }
+    )*};
+}
+
+deref!(Json, Query, Path, State, Header);
+
+/// The request body, deserialized from JSON.
+///
+/// The body is read with the total limit of the
+/// [`Limits`](crate::multipart::Limits) added with
+/// [`Server::with_state`](crate::Server::with_state), or the default one.
+///
+/// Fails with `415 Unsupported Media Type` if the request isn't
+/// `application/json`, with `413 Payload Too Large` if the body exceeds the
+/// limit, with `400 Bad Request` if the body isn't valid JSON, and with
+/// `422 Unprocessable Entity` if it doesn't match `T`. As the body can only
+/// be read once, `Json` is usually the last argument. `Option<Json<T>>` is
+/// `None` for requests without a `Content-Type`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+/// Whether the `Content-Type` is `application/json` or a `+json` type.
+fn is_json(headers: Option<&HeaderMap>) -> bool {
+    let content_type = headers
+        .and_then(|headers| headers.get(CONTENT_TYPE))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(|essence| essence.trim().to_ascii_lowercase());
+    match content_type {
+        Some(essence) => {
+            essence == "application/json"
+                || (essence.starts_with("application/") && essence.ends_with("+json"))
+        }
+        None => false,
+    }
+}
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromContext for Json<T> {
+    async fn from_context(ctx: &mut Context) -> crate::Result<Self> {
+        if !is_json(ctx.try_borrow::<HeaderMap>()) {
+            return Err(Error::from_str(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Expected a request with `Content-Type: application/json`",
+            ));
+        }
+
+        let limits = ctx.try_borrow::<Limits>().cloned().unwrap_or_default();
+        let mut body = ctx.body_stream().limit(limits.total_limit());
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.try_next().await? {
+            bytes.extend_from_slice(&chunk);
+        }
+        serde_json::from_slice(&bytes).map(Json).map_err(|err| {
+            let status = if err.is_data() {
+                StatusCode::UNPROCESSABLE_ENTITY
+            } else {
+                StatusCode::BAD_REQUEST
+            };
+            Error::new(status, err)
+        })
+    }
+
+    async fn from_context_optional(ctx: &mut Context) -> crate::Result<Option<Self>> {
+        let headers = ctx.try_borrow::<HeaderMap>();
+        if headers.is_some_and(|headers| headers.contains_key(CONTENT_TYPE)) {
+            Self::from_context(ctx).await.map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// The query string of the request, deserialized from
+/// `application/x-www-form-urlencoded`.
+///
+/// Fails with `400 Bad Request` if the query string doesn't match `T`. A
+/// request without a query string is treated like one with an empty query
+/// string, except that `Option<Query<T>>` is `None` for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Query<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromContext for Query<T> {
+    async fn from_context(ctx: &mut Context) -> crate::Result<Self> {
+        let query = ctx.try_borrow::<Uri>().and_then(Uri::query).unwrap_or("");
+        serde_urlencoded::from_str(query)
+            .map(Query)
+            .map_err(|err| Error::new(StatusCode::BAD_REQUEST, err))
+    }
+
+    async fn from_context_optional(ctx: &mut Context) -> crate::Result<Option<Self>> {
+        match ctx.try_borrow::<Uri>().and_then(Uri::query) {
+            Some(_) => Self::from_context(ctx).await.map(Some),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The params of the route, deserialized by name into a struct or map, in
+/// order into a tuple or sequence, or, for routes with a single param, into
+/// that param's type. The wildcard of a route is included as a param named
+/// `*`.
+///
+/// Only the params of the path matched by the innermost server are used:
+/// params captured from the host, and the params and wildcard of the routes
+/// a server is nested at, are left out. They are still available with
+/// [`Context::param`](crate::Context::param).
+///
+/// Fails with `400 Bad Request` if the params don't match `T`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Path<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send> FromContext for Path<T> {
+    async fn from_context(ctx: &mut Context) -> crate::Result<Self> {
+        // Every server pushes the params of its host and then of its path.
+        let params: Vec<(&str, &str)> = match ctx.params.last() {
+            Some(captures) => {
+                let wildcard = captures.wildcard().map(|wildcard| ("*", wildcard));
+                captures
+                    .params()
+                    .iter()
+                    .map(|capture| (capture.name(), capture.value()))
+                    .chain(wildcard)
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        T::deserialize(Params(&params))
+            .map(Path)
+            .map_err(|err| Error::new(StatusCode::BAD_REQUEST, err))
+    }
+
+    async fn from_context_optional(ctx: &mut Context) -> crate::Result<Option<Self>> {
+        Self::from_context(ctx).await.map(Some)
+    }
+}
+
+/// A value of type `T` in the context, cloned, such as state added with
+/// [`Server::with_state`](crate::Server::with_state).
+///
+/// Fails with `500 Internal Server Error` if there is no such value.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct State<T>(pub T);
+
+#[async_trait]
+impl<T: Clone + Send + Sync + 'static> FromContext for State<T> {
+    async fn from_context(ctx: &mut Context) -> crate::Result<Self> {
+        ctx.try_borrow::<T>().cloned().map(State).ok_or_else(|| {
+            Error::from_str(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("State `{}` does not exist", std::any::type_name::<T>()),
+            )
+        })
+    }
+
+    async fn from_context_optional(ctx: &mut Context) -> crate::Result<Option<Self>> {
+        Ok(ctx.try_borrow::<T>().cloned().map(State))
+    }
+}
+
+/// A request header, decoded into one of the typed headers of the
+/// [`headers`] crate.
+///
+/// Fails with `400 Bad Request` if the header is missing or invalid; use
+/// `Option<Header<T>>` for optional headers, which still fails if the header
+/// is invalid.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Header<T>(pub T);
+
+#[async_trait]
+impl<T: headers::Header + Send> FromContext for Header<T> {
+    async fn from_context(ctx: &mut Context) -> crate::Result<Self> {
+        let headers = ctx.try_borrow::<HeaderMap>();
+        match headers.map(HeaderMapExt::typed_try_get::<T>) {
+            Some(Ok(Some(header))) => Ok(Header(header)),
+            Some(Err(_)) => Err(Error::from_str(
+                StatusCode::BAD_REQUEST,
+                format!("Invalid `{}` header", T::name()),
+            )),
+            _ => Err(Error::from_str(
+                StatusCode::BAD_REQUEST,
+                format!("Missing `{}` header", T::name()),
+            )),
+        }
+    }
+
+    async fn from_context_optional(ctx: &mut Context) -> crate::Result<Option<Self>> {
+        let headers = ctx.try_borrow::<HeaderMap>();
+        if headers.is_some_and(|headers| headers.contains_key(T::name())) {
+            Self::from_context(ctx).await.map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,696 @@
+//! Serving static files.
+//!
+//! [`Route::serve_dir`] serves the files below a directory, and
+//! [`Route::serve_file`] a single file:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! # fn main() -> std::io::Result<()> {
+//! let mut app = envoy::Server::new();
+//! app.at("/static").serve_dir("public/")?;
+//! app.at("/favicon.ico").serve_file("public/favicon.ico")?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Files are served with a `Content-Type` guessed from their extension, an
+//! `ETag` and a `Last-Modified` header. Conditional requests get a
+//! `304 Not Modified` response, and `Range` requests a
+//! `206 Partial Content` response, using `multipart/byteranges` for more
+//! than one range. When a client accepts Brotli or gzip and a `.br` or `.gz`
+//! sibling of the file exists, e.g. `app.js.br`, the sibling is served
+//! instead with the matching `Content-Encoding`.
+//!
+//! [`Route::serve_spa`] hosts a single-page app, see [`Spa`].
+//!
+//! [`Route::serve_dir`]: crate::Route::serve_dir
+//! [`Route::serve_file`]: crate::Route::serve_file
+//! [`Route::serve_spa`]: crate::Route::serve_spa
+
+use std::fmt::Write as _;
+use std::io::{self, SeekFrom};
+use std::ops::Bound;
+use std::path::{Component, Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use async_trait::async_trait;
+use futures_util::stream::{self, BoxStream};
+use futures_util::{future, StreamExt, TryStreamExt};
+use headers::{
+    AcceptRanges, ContentLength, ContentRange, ETag, HeaderMapExt, IfModifiedSince,
+    IfNoneMatch, IfRange, LastModified, Range,
+};
+use hyper::body::Bytes;
+use hyper::header::{CACHE_CONTROL, CONTENT_ENCODING, CONTENT_TYPE, LOCATION, VARY};
+use hyper::{Body, Method, Response, StatusCode};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, Take};
+use tokio_util::io::ReaderStream;
+
+use crate::path::percent_decode;
+use crate::{Accept, AcceptEncoding, Context, Endpoint, Error};
+
+/// The precompressed siblings looked for, by content coding.
+const PRECOMPRESSED: [(&str, &str); 2] = [("br", "br"), ("gzip", "gz")];
+
+/// An endpoint serving the files below a directory, see
+/// [`Route::serve_dir`](crate::Route::serve_dir).
+///
+/// The file is selected by the wildcard of the route, as returned by
+/// [`Context::wildcard`]. Paths leaving the directory, through `..` or a
+/// symlink pointing outside of it, are answered with `404 Not Found`.
+///
+/// Requests for a directory are answered with its `index.html`, if any,
+/// or else with a listing of its entries if enabled with
+/// [`ServeDir::listing`].
+#[derive(Debug, Clone)]
+pub struct ServeDir {
+    root: PathBuf,
+    listing: bool,
+    precompressed: bool,
+}
+
+impl ServeDir {
+    /// Serve the files below `root`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `root` doesn't exist or isn't a directory.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        let root = std::fs::canonicalize(root)?;
+        if !root.is_dir() {
+            return Err(io::Error::other("not a directory"));
+        }
+        Ok(Self {
+            root,
+            listing: false,
+            precompressed: true,
+        })
+    }
+
+    /// Render a listing of the entries of directories without an
+    /// `index.html`.
+    #[must_use]
+    pub fn listing(mut self) -> Self {
+        self.listing = true;
+        self
+    }
+
+    /// Don't serve `.br` and `.gz` siblings of files.
+    #[must_use]
+    pub fn without_precompressed(mut self) -> Self {
+        self.precompressed = false;
+        self
+    }
+
+    /// The canonical path of the file or directory at `rest`, the
+    /// percent-encoded path relative to the root, if it exists within the
+    /// root.
+    pub(crate) async fn resolve(&self, rest: &str) -> Option<PathBuf> {
+        let path = self.root.join(relative_path(rest)?);
+        let path = tokio::fs::canonicalize(path).await.ok()?;
+        if path.starts_with(&self.root) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+}
+
+#[async_trait]
+impl Endpoint for ServeDir {
+    async fn call(&self, ctx: &mut Context) -> crate::Result {
+        let rest = ctx.wildcard().unwrap_or("");
+        let path = self.resolve(rest).await.ok_or_else(not_found)?;
+        if !path.is_dir() {
+            return serve(ctx, &path, self.precompressed).await;
+        }
+
+        if let Some(res) = redirect_to_directory(ctx)? {
+            return Ok(res);
+        }
+
+        let index = path.join("index.html");
+        if index.is_file() {
+            return serve(ctx, &index, self.precompressed).await;
+        }
+        if self.listing {
+            return listing(ctx, &path, path != self.root).await;
+        }
+        Err(not_found())
+    }
+}
+
+/// An endpoint serving a single file, see
+/// [`Route::serve_file`](crate::Route::serve_file).
+#[derive(Debug, Clone)]
+pub struct ServeFile {
+    path: PathBuf,
+    precompressed: bool,
+}
+
+impl ServeFile {
+    /// Serve the file at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `path` doesn't exist.
+    pub fn new(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            path: std::fs::canonicalize(path)?,
+            precompressed: true,
+        })
+    }
+
+    /// Don't serve `.br` and `.gz` siblings of the file.
+    #[must_use]
+    pub fn without_precompressed(mut self) -> Self {
+        self.precompressed = false;
+        self
+    }
+}
+
+#[async_trait]
+impl Endpoint for ServeFile {
+    async fn call(&self, ctx: &mut Context) -> crate::Result {
+        serve(ctx, &self.path, self.precompressed).await
+    }
+}
+
+/// An endpoint hosting a single-page app, see
+/// [`Route::serve_spa`](crate::Route::serve_spa).
+///
+/// Assets are served from a directory like with [`ServeDir`]. Navigation
+/// requests, `GET` or `HEAD` requests accepting `text/html` explicitly, for
+/// paths that match no file are answered with the `index.html` of the
+/// directory, so the app can route them on the client. Paths that look like
+/// assets, with an extension in their last segment, are never answered with
+/// the index and get `404 Not Found` if missing.
+///
+/// The index is served with `Cache-Control: no-cache`, so new deployments
+/// are picked up right away. Assets with a content hash in their file name,
+/// e.g. `app.3f2a9c1b.js` or `index-Bk9x2sLq.css`, are served with a
+/// `Cache-Control` letting clients cache them for a year.
+#[derive(Debug, Clone)]
+pub struct Spa {
+    dir: ServeDir,
+    index: PathBuf,
+}
+
+impl Spa {
+    /// Host the app in `root`, which has to contain an `index.html`.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `root` isn't a directory or has no `index.html`.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = ServeDir::new(root)?;
+        let index = dir.root.join("index.html");
+        if !index.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "index.html not found"));
+        }
+        Ok(Self { dir, index })
+    }
+
+    /// Don't serve `.br` and `.gz` siblings of files.
+    #[must_use]
+    pub fn without_precompressed(mut self) -> Self {
+        self.dir.precompressed = false;
+        self
+    }
+
+    /// Whether the request is a navigation of the browser, rather than a
+    /// request for an asset.
+    fn is_navigation(ctx: &Context, rest: &str) -> bool {
+        let method = ctx.try_borrow::<Method>();
+        let last = rest.rsplit('/').next().unwrap_or("");
+        let accept = ctx.typed_header::<Accept>().unwrap_or_default();
+        matches!(method, Some(&Method::GET) | Some(&Method::HEAD))
+            && !last.contains('.')
+            && accept.iter().any(|range| {
+                range.type_().eq_ignore_ascii_case("text")
+                    && range.subtype().eq_ignore_ascii_case("html")
+                    && range.quality() > 0.0
+            })
+    }
+}
+
+#[async_trait]
+impl Endpoint for Spa {
+    async fn call(&self, ctx: &mut Context) -> crate::Result {
+        let rest = ctx.wildcard().unwrap_or("");
+        let path = match self.dir.resolve(rest).await {
+            Some(path) if path.is_file() => path,
+            _ if rest.is_empty() || Self::is_navigation(ctx, rest) => self.index.clone(),
+            _ => return Err(not_found()),
+        };
+
+        let mut res = serve(ctx, &path, self.dir.precompressed).await?;
+        let cache_control = if path == self.index {
+            Some("no-cache")
+        } else if is_hashed(&path) {
+            Some("public, max-age=31536000, immutable")
+        } else {
+            None
+        };
+        if let Some(cache_control) = cache_control {
+            res.headers_mut().insert(CACHE_CONTROL, cache_control.parse()?);
+        }
+        Ok(res)
+    }
+}
+
+/// Whether the file name contains a content hash: a part, separated by `.`
+/// or `-`, of at least 8 letters, digits or underscores, with at least one
+/// digit.
+fn is_hashed(path: &Path) -> bool {
+    let stem = match path.file_stem().and_then(|stem| stem.to_str()) {
+        Some(stem) => stem,
+        None => return false,
+    };
+    stem.split(['.', '-']).skip(1).any(|part| {
+        part.len() >= 8
+            && part.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+            && part.bytes().any(|b| b.is_ascii_digit())
+    })
+}
+
+/// The percent-encoded path `rest` decoded, with empty and `.` segments
+/// removed, or `None` if it tries to leave the directory it's relative to.
+pub(crate) fn relative_path(rest: &str) -> Option<String> {
+    let rest = percent_decode(rest)?;
+    let mut segments = Vec::new();
+    for segment in rest.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." => return None,
+            segment => {
+                let mut components = Path::new(segment).components();
+                match (components.next(), components.next()) {
+                    (Some(Component::Normal(_)), None) => segments.push(segment),
+                    _ => return None,
+                }
+            }
+        }
+    }
+    Some(segments.join("/"))
+}
+
+/// Redirect requests for a directory to its path with a trailing slash, as
+/// the entries of a directory are linked relative to it.
+pub(crate) fn redirect_to_directory(ctx: &Context) -> crate::Result<Option<Response<Body>>> {
+    let uri = ctx.original_uri();
+    if uri.path().ends_with('/') {
+        return Ok(None);
+    }
+    let location = match uri.query() {
+        Some(query) => format!("{}/?{}", uri.path(), query),
+        None => format!("{}/", uri.path()),
+    };
+    let res = Response::builder()
+        .status(StatusCode::MOVED_PERMANENTLY)
+        .header(LOCATION, location)
+        .body(Body::empty())?;
+    Ok(Some(res))
+}
+
+pub(crate) fn not_found() -> Error {
+    Error::from_str(StatusCode::NOT_FOUND, "Not found")
+}
+
+/// A representation of a resource, served by [`respond`].
+pub(crate) struct Representation {
+    pub(crate) source: Source,
+    pub(crate) len: u64,
+    pub(crate) etag: ETag,
+    pub(crate) last_modified: Option<LastModified>,
+    pub(crate) content_type: &'static str,
+    /// The content coding of a precompressed variant.
+    pub(crate) coding: Option<&'static str>,
+    /// Whether precompressed variants are served, so the response varies by
+    /// `Accept-Encoding`.
+    pub(crate) precompressed: bool,
+}
+
+/// Where the contents of a [`Representation`] come from.
+pub(crate) enum Source {
+    File(PathBuf, File),
+    Static(&'static [u8]),
+}
+
+impl Source {
+    /// The `len` bytes starting at `start`, read when the body is sent.
+    fn part(&self, start: u64, len: u64) -> BoxStream<'static, io::Result<Bytes>> {
+        match self {
+            Source::File(path, _) => {
+                let path = path.clone();
+                stream::once(async move { open_part(&path, start, len).await })
+                    .map_ok(ReaderStream::new)
+                    .try_flatten()
+                    .boxed()
+            }
+            Source::Static(data) => {
+                let part = &data[start as usize..(start + len) as usize];
+                stream::once(future::ready(Ok(Bytes::from_static(part)))).boxed()
+            }
+        }
+    }
+}
+
+/// The content codings of precompressed variants the client accepts, by
+/// descending preference, with the file extension of the variant.
+pub(crate) fn accepted_codings(ctx: &Context) -> Vec<(&'static str, &'static str)> {
+    let accept = ctx.typed_header::<AcceptEncoding>().unwrap_or_default();
+    let mut codings: Vec<_> = PRECOMPRESSED
+        .iter()
+        .copied()
+        .filter(|(coding, _)| accept.quality(coding) > 0.0)
+        .collect();
+    codings.sort_by(|(a, _), (b, _)| accept.quality(b).total_cmp(&accept.quality(a)));
+    codings
+}
+
+/// Serve the file at `path`, answering conditional and range requests.
+pub(crate) async fn serve(ctx: &Context, path: &Path, precompressed: bool) -> crate::Result {
+    let (opened, file, coding) = open(ctx, path, precompressed).await?.ok_or_else(not_found)?;
+    let metadata = file.metadata().await?;
+    if !metadata.is_file() {
+        return Err(not_found());
+    }
+    let len = metadata.len();
+    let modified = metadata.modified()?;
+    let since_epoch = modified.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let tag = format!(
+        "\"{:x}-{:x}{}\"",
+        len,
+        since_epoch.as_nanos(),
+        coding.map(|coding| format!("-{}", coding)).unwrap_or_default()
+    );
+
+    respond(
+        ctx,
+        Representation {
+            source: Source::File(opened, file),
+            len,
+            etag: tag.parse().map_err(|_| Error::from_str(500, "Invalid ETag"))?,
+            last_modified: Some(LastModified::from(modified)),
+            content_type: mime_type(path),
+            coding,
+            precompressed,
+        },
+    )
+}
+
+/// Respond with `representation`, answering conditional and range requests.
+pub(crate) fn respond(ctx: &Context, representation: Representation) -> crate::Result {
+    let Representation {
+        source,
+        len,
+        etag,
+        last_modified,
+        content_type,
+        coding,
+        precompressed,
+    } = representation;
+
+    let mut res = Response::new(Body::empty());
+    let headers = res.headers_mut();
+    headers.typed_insert(etag.clone());
+    if let Some(last_modified) = last_modified {
+        headers.typed_insert(last_modified);
+    }
+    headers.typed_insert(AcceptRanges::bytes());
+    if precompressed {
+        headers.insert(VARY, "accept-encoding".parse()?);
+    }
+    if let Some(coding) = coding {
+        headers.insert(CONTENT_ENCODING, coding.parse()?);
+    }
+
+    let not_modified = match ctx.typed_header::<IfNoneMatch>() {
+        Some(if_none_match) => !if_none_match.precondition_passes(&etag),
+        None => match (ctx.typed_header::<IfModifiedSince>(), last_modified) {
+            (Some(since), Some(last_modified)) => !since.is_modified(last_modified.into()),
+            _ => false,
+        },
+    };
+    if not_modified {
+        *res.status_mut() = StatusCode::NOT_MODIFIED;
+        return Ok(res);
+    }
+
+    res.headers_mut().insert(CONTENT_TYPE, content_type.parse()?);
+    let head = ctx.try_borrow::<Method>() == Some(&Method::HEAD);
+    let range = ctx.typed_header::<Range>().filter(|_| {
+        ctx.typed_header::<IfRange>()
+            .is_none_or(|if_range| !if_range.is_modified(Some(&etag), last_modified.as_ref()))
+    });
+    // Too many ranges are answered with the whole file, rather than sending
+    // more data than that in a `multipart/byteranges` body.
+    let ranges = range
+        .map(|range| satisfiable(&range, len))
+        .filter(|ranges| ranges.len() <= MAX_RANGES);
+    let ranges = match ranges {
+        Some(ranges) => ranges,
+        None => {
+            res.headers_mut().typed_insert(ContentLength(len));
+            if !head {
+                *res.body_mut() = match source {
+                    Source::File(_, file) => Body::wrap_stream(ReaderStream::new(file)),
+                    Source::Static(data) => Body::from(data),
+                };
+            }
+            return Ok(res);
+        }
+    };
+
+    match ranges.as_slice() {
+        [] => {
+            *res.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            res.headers_mut().typed_insert(ContentRange::unsatisfied_bytes(len));
+            res.headers_mut().remove(CONTENT_TYPE);
+        }
+        [(start, end)] => {
+            *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let content_range = ContentRange::bytes(start..=end, len)
+                .map_err(|_| Error::from_str(500, "Invalid range"))?;
+            res.headers_mut().typed_insert(content_range);
+            res.headers_mut().typed_insert(ContentLength(end - start + 1));
+            if !head {
+                *res.body_mut() = Body::wrap_stream(source.part(*start, end - start + 1));
+            }
+        }
+        ranges => {
+            let boundary = format!("{:016x}", rand::random::<u64>());
+            let parts: Vec<(Bytes, u64, u64)> = ranges
+                .iter()
+                .map(|(start, end)| {
+                    let head = format!(
+                        "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                        boundary, content_type, start, end, len
+                    );
+                    (Bytes::from(head), *start, end - start + 1)
+                })
+                .collect();
+            let tail = Bytes::from(format!("\r\n--{}--\r\n", boundary));
+            let length = parts
+                .iter()
+                .map(|(head, _, len)| head.len() as u64 + len)
+                .sum::<u64>()
+                + tail.len() as u64;
+
+            *res.status_mut() = StatusCode::PARTIAL_CONTENT;
+            let content_type = format!("multipart/byteranges; boundary={}", boundary);
+            res.headers_mut().insert(CONTENT_TYPE, content_type.parse()?);
+            res.headers_mut().typed_insert(ContentLength(length));
+            if !head {
+                let body = stream::iter(parts)
+                    .flat_map(move |(head, start, len)| {
+                        stream::once(future::ready(Ok(head))).chain(source.part(start, len))
+                    })
+                    .chain(stream::once(future::ready(Ok(tail))));
+                *res.body_mut() = Body::wrap_stream(body);
+            }
+        }
+    }
+    Ok(res)
+}
+
+/// Open the file at `path`, or one of its precompressed siblings if the
+/// client accepts it, returning the path opened and the content coding of
+/// the sibling.
+async fn open(
+    ctx: &Context,
+    path: &Path,
+    precompressed: bool,
+) -> crate::Result<Option<(PathBuf, File, Option<&'static str>)>> {
+    if precompressed {
+        for (coding, extension) in accepted_codings(ctx) {
+            let mut sibling = path.as_os_str().to_owned();
+            sibling.push(".");
+            sibling.push(extension);
+            let sibling = PathBuf::from(sibling);
+            // Siblings have to be regular files, not symlinks leading elsewhere.
+            let is_file = tokio::fs::symlink_metadata(&sibling)
+                .await
+                .is_ok_and(|metadata| metadata.is_file());
+            if is_file {
+                let file = File::open(&sibling).await?;
+                return Ok(Some((sibling, file, Some(coding))));
+            }
+        }
+    }
+
+    match File::open(path).await {
+        Ok(file) => Ok(Some((path.to_owned(), file, None))),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err.into()),
+    }
+}
+
+async fn open_part(path: &Path, start: u64, len: u64) -> io::Result<Take<File>> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(start)).await?;
+    Ok(file.take(len))
+}
+
+/// The most ranges a `multipart/byteranges` response has, after
+/// overlapping and adjacent ones are coalesced.
+const MAX_RANGES: usize = 16;
+
+/// The ranges of `range` that overlap a file of `len` bytes, as inclusive
+/// bounds, in order and with overlapping and adjacent ranges coalesced.
+fn satisfiable(range: &Range, len: u64) -> Vec<(u64, u64)> {
+    let mut ranges: Vec<(u64, u64)> = range
+        .iter()
+        .filter_map(|bounds| match bounds {
+            (Bound::Included(start), Bound::Included(end)) if start < len && start <= end => {
+                Some((start, end.min(len - 1)))
+            }
+            (Bound::Included(start), Bound::Unbounded) if start < len => Some((start, len - 1)),
+            (Bound::Unbounded, Bound::Included(suffix)) if suffix > 0 && len > 0 => {
+                Some((len - suffix.min(len), len - 1))
+            }
+            _ => None,
+        })
+        .collect();
+    ranges.sort_unstable();
+
+    let mut coalesced: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match coalesced.last_mut() {
+            Some((_, last)) if start <= last.saturating_add(1) => *last = (*last).max(end),
+            _ => coalesced.push((start, end)),
+        }
+    }
+    coalesced
+}
+
+/// Render the entries of the directory at `path` as an HTML page.
+async fn listing(ctx: &Context, path: &Path, parent: bool) -> crate::Result {
+    let mut entries = Vec::new();
+    let mut dir = tokio::fs::read_dir(path).await?;
+    while let Some(entry) = dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let is_dir = entry.file_type().await?.is_dir();
+        entries.push((!is_dir, name));
+    }
+    entries.sort();
+
+    let title = escape(ctx.original_uri().path());
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Index of {0}</title></head>\n\
+         <body>\n<h1>Index of {0}</h1>\n<ul>\n",
+        title
+    );
+    if parent {
+        html.push_str("<li><a href=\"../\">../</a></li>\n");
+    }
+    for (is_file, name) in entries {
+        let slash = if is_file { "" } else { "/" };
+        let _ = writeln!(
+            html,
+            "<li><a href=\"{}{}\">{}{}</a></li>",
+            encode(&name),
+            slash,
+            escape(&name),
+            slash
+        );
+    }
+    html.push_str("</ul>\n</body>\n</html>\n");
+
+    let mut res = Response::new(Body::from(html));
+    res.headers_mut().insert(CONTENT_TYPE, "text/html; charset=utf-8".parse()?);
+    Ok(res)
+}
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Percent-encode a path segment.
+fn encode(segment: &str) -> String {
+    let mut out = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                out.push(byte as char)
+            }
+            byte => {
+                let _ = write!(out, "%{:02X}", byte);
+            }
+        }
+    }
+    out
+}
+
+/// Guess the media type of a file from its extension.
+pub(crate) fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "md" => "text/markdown; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => "application/octet-stream",
+    }
+}
@@ -0,0 +1,142 @@
+//! Host patterns for virtual host routing.
+//!
+//! A pattern is a dot-separated list of labels. Labels are compared
+//! case-insensitively, and a label written as `:name` captures the matching
+//! label as a route param. The first label may be `*`, which matches one or
+//! more labels and captures them as the `subdomain` param, e.g.
+//! `*.tenant.example.com` matches `acme.tenant.example.com` with
+//! `subdomain = "acme"`.
+
+use std::fmt::{self, Display};
+
+/// The name of the param a leading `*` label is captured as.
+pub(crate) const SUBDOMAIN: &str = "subdomain";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Label {
+    Exact(String),
+    Param(String),
+}
+
+/// A parsed host pattern.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct HostPattern {
+    wildcard: bool,
+    labels: Vec<Label>,
+}
+
+impl HostPattern {
+    /// Parse a host pattern such as `api.example.com` or `*.example.com`.
+    pub(crate) fn parse(pattern: &str) -> Result<Self, String> {
+        let pattern = pattern.trim_end_matches('.');
+        let (wildcard, rest) = match pattern.strip_prefix("*.") {
+            Some(rest) => (true, rest),
+            None => (false, pattern),
+        };
+
+        let labels = rest
+            .split('.')
+            .map(|label| match label {
+                "" => Err(format!("empty label in host `{}`", pattern)),
+                "*" => Err(format!("`*` is only allowed as the first label of `{}`", pattern)),
+                ":" => Err(format!("host params must be named in `{}`", pattern)),
+                label => Ok(match label.strip_prefix(':') {
+                    Some(name) => Label::Param(name.to_owned()),
+                    None => Label::Exact(label.to_ascii_lowercase()),
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { wildcard, labels })
+    }
+
+    /// Match a request host, returning the captured params.
+    pub(crate) fn matches(&self, host: &str) -> Option<Vec<(String, String)>> {
+        let host: Vec<&str> = host.trim_end_matches('.').split('.').collect();
+        let skip = match (self.wildcard, host.len().checked_sub(self.labels.len())) {
+            (false, Some(0)) => 0,
+            (true, Some(n)) if n > 0 => n,
+            _ => return None,
+        };
+
+        let mut captures = Vec::new();
+        if self.wildcard {
+            captures.push((SUBDOMAIN.to_owned(), host[..skip].join(".")));
+        }
+        for (label, value) in self.labels.iter().zip(&host[skip..]) {
+            match label {
+                Label::Exact(exact) if exact.eq_ignore_ascii_case(value) => {}
+                Label::Exact(_) => return None,
+                Label::Param(name) => captures.push((name.clone(), (*value).to_owned())),
+            }
+        }
+
+        Some(captures)
+    }
+
+    /// How specific the pattern is: fully literal hosts win over patterns.
+    pub(crate) fn specificity(&self) -> u8 {
+        if self.wildcard || self.labels.iter().any(|l| matches!(l, Label::Param(_))) {
+            1
+        } else {
+            2
+        }
+    }
+}
+
+impl Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.wildcard {
+            f.write_str("*")?;
+        }
+        for (i, label) in self.labels.iter().enumerate() {
+            if i > 0 || self.wildcard {
+                f.write_str(".")?;
+            }
+            match label {
+                Label::Exact(exact) => f.write_str(exact)?,
+                Label::Param(name) => write!(f, ":{}", name)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exact_hosts() {
+        let pattern = HostPattern::parse("API.example.com").unwrap();
+        assert_eq!(pattern.matches("api.Example.com."), Some(vec![]));
+        assert_eq!(pattern.matches("example.com"), None);
+        assert_eq!(pattern.matches("v1.api.example.com"), None);
+        assert_eq!(pattern.specificity(), 2);
+    }
+
+    #[test]
+    fn captures_subdomains() {
+        let pattern = HostPattern::parse("*.tenant.example.com").unwrap();
+        assert_eq!(pattern.to_string(), "*.tenant.example.com");
+        assert_eq!(
+            pattern.matches("eu.acme.tenant.example.com"),
+            Some(vec![("subdomain".to_owned(), "eu.acme".to_owned())])
+        );
+        assert_eq!(pattern.matches("tenant.example.com"), None);
+
+        let pattern = HostPattern::parse(":tenant.example.com").unwrap();
+        assert_eq!(
+            pattern.matches("acme.example.com"),
+            Some(vec![("tenant".to_owned(), "acme".to_owned())])
+        );
+        assert_eq!(pattern.specificity(), 1);
+    }
+
+    #[test]
+    fn rejects_malformed_patterns() {
+        assert!(HostPattern::parse("api..example.com").is_err());
+        assert!(HostPattern::parse("api.*.example.com").is_err());
+        assert!(HostPattern::parse(":.example.com").is_err());
+    }
+}
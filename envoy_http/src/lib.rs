@@ -54,21 +54,49 @@
 #![doc(html_favicon_url = "https://yoshuawuyts.com/assets/http-rs/favicon.ico")]
 #![doc(html_logo_url = "https://yoshuawuyts.com/assets/http-rs/logo-rounded.png")]
 
+mod accept;
+pub mod channels;
+pub mod compression;
+pub mod conditional;
+mod constraint;
 mod context;
+mod cookies;
+mod de;
+pub mod embed;
 mod endpoint;
 mod error;
+pub mod extract;
+pub mod fs;
+mod host;
+mod meta;
 mod middleware;
+pub mod multipart;
+mod path;
 mod route;
 mod router;
 mod server;
+mod service;
+pub mod sessions;
+pub mod sse;
+pub mod stream;
+mod tree;
+mod version;
+pub mod websocket;
 
+pub use accept::{Accept, AcceptEncoding, MediaRange};
 pub use context::Context;
-pub use endpoint::Endpoint;
-pub use error::Error;
+pub use cookies::{CookieKeys, PrivateJar, SignedJar};
+pub use endpoint::{Endpoint, IntoEndpoint};
+pub use error::{Error, ErrorHandler};
 pub use middleware::{Middleware, Next};
-pub use route::Route;
+pub use path::{Normalization, PercentDecoding, TrailingSlash};
+pub use route::{MatchedRoute, Route};
 pub use server::Server;
+pub use service::{NextService, TowerLayer};
+pub use version::ApiVersion;
 
+pub use cookie;
+pub use headers;
 pub use hyper::{body, http, Body, HeaderMap, Method, Request, Response, StatusCode, Uri, Version};
 
 /// Create a new Envoy server.
@@ -0,0 +1,33 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+/// Typed metadata attached to a route, keyed by type.
+#[derive(Clone, Default)]
+pub(crate) struct Metadata(HashMap<TypeId, Arc<dyn Any + Send + Sync>>);
+
+impl Debug for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Metadata (length: {})", self.0.len())
+    }
+}
+
+impl Metadata {
+    pub(crate) fn insert<T: Send + Sync + 'static>(&mut self, value: T) {
+        self.0.insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    pub(crate) fn get<T: 'static>(&self) -> Option<&T> {
+        self.0
+            .get(&TypeId::of::<T>())
+            .and_then(|v| v.downcast_ref::<T>())
+    }
+
+    /// Merge `other` into `self`, with the values of `other` taking
+    /// precedence.
+    pub(crate) fn extend(&mut self, other: &Metadata) {
+        self.0
+            .extend(other.0.iter().map(|(k, v)| (*k, v.clone())));
+    }
+}
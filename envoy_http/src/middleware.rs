@@ -48,7 +48,7 @@ impl<F: Fn(Arg1, Arg2) -> O, Arg1, Arg2, O> Fn1<Arg1, Arg2> for F {
 }
 
 /// The remainder of a middleware chain, including the endpoint.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Next {
     endpoint: Arc<DynEndpoint>,
     middleware: Arc<Vec<Arc<dyn Middleware>>>,
@@ -0,0 +1,326 @@
+//! `multipart/form-data` request bodies, e.g. file uploads.
+//!
+//! [`Context::multipart`] reads the fields of the body one at a time. Each
+//! [`Field`] is a stream of chunks, so large uploads don't have to fit in
+//! memory:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::multipart::{Limits, Spooled};
+//! use envoy::{Body, Response};
+//!
+//! async fn upload(ctx: &mut envoy::Context) -> envoy::Result {
+//!     let mut multipart = ctx.multipart()?;
+//!     let mut saved = Vec::new();
+//!     while let Some(field) = multipart.next_field().await? {
+//!         let name = field.file_name().unwrap_or("upload").to_owned();
+//!         if let Spooled::File(file) = field.spool().await? {
+//!             file.persist(format!("uploads/{}", name))?;
+//!             saved.push(name);
+//!         }
+//!     }
+//!     Ok(Response::new(Body::from(saved.join("\n"))))
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.with_state(Limits::new().total(64 * 1024 * 1024));
+//! app.at("/upload").post(upload);
+//! ```
+//!
+//! The body is limited to 16 MiB by default. Other [`Limits`] apply to every
+//! request when added with [`Server::with_state`], or to a single request
+//! with [`Context::multipart_with`]. Exceeding a limit fails with
+//! `413 Payload Too Large`.
+//!
+//! [`Context::multipart`]: crate::Context::multipart
+//! [`Context::multipart_with`]: crate::Context::multipart_with
+//! [`Server::with_state`]: crate::Server::with_state
+
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use futures_util::Stream;
+use hyper::body::Bytes;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, HeaderMap, StatusCode};
+use serde::de::DeserializeOwned;
+use tempfile::NamedTempFile;
+use tokio::io::AsyncWriteExt;
+
+use crate::de::Params;
+use crate::{stream, Error};
+
+/// The size limits of a `multipart/form-data` body.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    total: u64,
+    per_field: Option<u64>,
+    fields: Vec<(String, u64)>,
+    in_memory: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            total: 16 * 1024 * 1024,
+            per_field: None,
+            fields: Vec::new(),
+            in_memory: 256 * 1024,
+        }
+    }
+}
+
+impl Limits {
+    /// The default limits: 16 MiB for the whole body, and fields spooled to
+    /// a file past 256 KiB.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limit the size of the whole body.
+    #[must_use]
+    pub fn total(mut self, limit: u64) -> Self {
+        self.total = limit;
+        self
+    }
+
+    /// The limit of the size of the whole body.
+    pub(crate) fn total_limit(&self) -> u64 {
+        self.total
+    }
+
+    /// Limit the size of every field.
+    #[must_use]
+    pub fn per_field(mut self, limit: u64) -> Self {
+        self.per_field = Some(limit);
+        self
+    }
+
+    /// Limit the size of the field named `name`, instead of the
+    /// [`per_field`](Limits::per_field) limit.
+    #[must_use]
+    pub fn field(mut self, name: impl Into<String>, limit: u64) -> Self {
+        self.fields.push((name.into(), limit));
+        self
+    }
+
+    /// The size past which [`Field::spool`] writes a field to a temporary
+    /// file instead of keeping it in memory.
+    #[must_use]
+    pub fn in_memory(mut self, limit: usize) -> Self {
+        self.in_memory = limit;
+        self
+    }
+
+    fn constraints(&self) -> multer::Constraints {
+        let mut size_limit = multer::SizeLimit::new().whole_stream(self.total);
+        if let Some(limit) = self.per_field {
+            size_limit = size_limit.per_field(limit);
+        }
+        for (name, limit) in &self.fields {
+            size_limit = size_limit.for_field(name.clone(), *limit);
+        }
+        multer::Constraints::new().size_limit(size_limit)
+    }
+}
+
+/// The fields of a `multipart/form-data` body, returned by
+/// [`Context::multipart`](crate::Context::multipart).
+#[derive(Debug)]
+pub struct Multipart {
+    inner: multer::Multipart<'static>,
+    in_memory: usize,
+}
+
+impl Multipart {
+    /// Read a multipart body with the given `boundary`.
+    pub(crate) fn new(body: Body, boundary: String, limits: &Limits) -> Self {
+        Self {
+            inner: multer::Multipart::with_constraints(body, boundary, limits.constraints()),
+            in_memory: limits.in_memory,
+        }
+    }
+
+    /// The next field, or `None` after the last one. The rest of the
+    /// previous field, if it wasn't read to the end, is skipped.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `400 Bad Request` if the body is malformed, and with
+    /// `413 Payload Too Large` if it exceeds the total limit.
+    pub async fn next_field(&mut self) -> crate::Result<Option<Field>> {
+        let field = self.inner.next_field().await.map_err(error)?;
+        Ok(field.map(|inner| Field {
+            inner,
+            in_memory: self.in_memory,
+        }))
+    }
+}
+
+/// A field of a `multipart/form-data` body: a stream of the chunks of its
+/// contents.
+#[derive(Debug)]
+pub struct Field {
+    inner: multer::Field<'static>,
+    in_memory: usize,
+}
+
+impl Field {
+    /// The name of the field in the form.
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.inner.name()
+    }
+
+    /// The name of the uploaded file, for file fields. It's sent by the
+    /// client, so don't use it as a path without checking it.
+    #[must_use]
+    pub fn file_name(&self) -> Option<&str> {
+        self.inner.file_name()
+    }
+
+    /// The `Content-Type` of the field, e.g. `image/png`.
+    #[must_use]
+    pub fn content_type(&self) -> Option<&str> {
+        self.inner.content_type().map(AsRef::as_ref)
+    }
+
+    /// The headers of the field.
+    #[must_use]
+    pub fn headers(&self) -> &HeaderMap {
+        self.inner.headers()
+    }
+
+    /// The next chunk of the contents, or `None` at the end.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `400 Bad Request` if the body is malformed, and with
+    /// `413 Payload Too Large` if the field exceeds its limit.
+    pub async fn chunk(&mut self) -> crate::Result<Option<Bytes>> {
+        self.inner.chunk().await.map_err(error)
+    }
+
+    /// The whole contents, in memory.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Field::chunk`].
+    pub async fn bytes(self) -> crate::Result<Bytes> {
+        self.inner.bytes().await.map_err(error)
+    }
+
+    /// The whole contents, as text.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Field::chunk`].
+    pub async fn text(self) -> crate::Result<String> {
+        self.inner.text().await.map_err(error)
+    }
+
+    /// The whole contents, kept in memory if they're smaller than the
+    /// [`in_memory`](Limits::in_memory) limit, or written to a temporary
+    /// file otherwise.
+    ///
+    /// # Errors
+    ///
+    /// Fails like [`Field::chunk`], or with `500 Internal Server Error` if
+    /// the temporary file can't be written.
+    pub async fn spool(mut self) -> crate::Result<Spooled> {
+        let mut buffer = Vec::new();
+        while let Some(chunk) = self.chunk().await? {
+            buffer.extend_from_slice(&chunk);
+            if buffer.len() > self.in_memory {
+                return self.spool_to_file(buffer).await.map(Spooled::File);
+            }
+        }
+        Ok(Spooled::Memory(buffer.into()))
+    }
+
+    async fn spool_to_file(mut self, buffer: Vec<u8>) -> crate::Result<NamedTempFile> {
+        let temp = NamedTempFile::new()?;
+        let mut file = tokio::fs::File::from_std(temp.as_file().try_clone()?);
+        file.write_all(&buffer).await?;
+        while let Some(chunk) = self.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(temp)
+    }
+}
+
+impl Stream for Field {
+    type Item = crate::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.inner)
+            .poll_next(cx)
+            .map(|chunk| chunk.map(|chunk| chunk.map_err(error)))
+    }
+}
+
+/// The contents of a field read with [`Field::spool`].
+#[derive(Debug)]
+pub enum Spooled {
+    /// A small field, in memory.
+    Memory(Bytes),
+    /// A large field, in a temporary file deleted when dropped unless it's
+    /// persisted.
+    File(NamedTempFile),
+}
+
+/// The boundary of a `multipart/form-data` body.
+pub(crate) fn boundary(headers: Option<&HeaderMap>) -> crate::Result<String> {
+    headers
+        .and_then(|headers| headers.get(CONTENT_TYPE))
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| multer::parse_boundary(value).ok())
+        .ok_or_else(|| {
+            Error::from_str(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "Expected a request with `Content-Type: multipart/form-data`",
+            )
+        })
+}
+
+/// Deserialize the text fields of a multipart body, skipping files.
+pub(crate) async fn form_data<T: DeserializeOwned>(mut multipart: Multipart) -> crate::Result<T> {
+    let mut fields = Vec::new();
+    while let Some(field) = multipart.next_field().await? {
+        if field.file_name().is_some() {
+            continue;
+        }
+        let name = field.name().unwrap_or_default().to_owned();
+        fields.push((name, field.text().await?));
+    }
+    let fields: Vec<(&str, &str)> = fields
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect();
+    T::deserialize(Params(&fields)).map_err(|err| Error::new(StatusCode::BAD_REQUEST, err))
+}
+
+fn error(err: multer::Error) -> Error {
+    let status = if exceeds_limit(&err) {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    Error::new(status, err)
+}
+
+/// Whether `err` is a size limit being exceeded. Exceeding the total limit,
+/// or a limit of a transformed body, is reported as a failure to read the
+/// stream.
+fn exceeds_limit(err: &multer::Error) -> bool {
+    match err {
+        multer::Error::FieldSizeExceeded { .. } | multer::Error::StreamSizeExceeded { .. } => true,
+        multer::Error::StreamReadFailed(source) => {
+            source.downcast_ref().is_some_and(exceeds_limit)
+                || stream::exceeds_limit(source.as_ref())
+        }
+        _ => false,
+    }
+}
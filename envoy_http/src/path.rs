@@ -0,0 +1,249 @@
+//! Path normalization applied before routing.
+
+use std::borrow::Cow;
+
+use hyper::http::uri::PathAndQuery;
+use hyper::{StatusCode, Uri};
+use routefinder::{Capture, Captures};
+
+/// How a [`Server`](crate::Server) treats a trailing slash in the request path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TrailingSlash {
+    /// `/foo` and `/foo/` match the same routes. This is the default.
+    #[default]
+    Ignore,
+    /// A path only matches routes registered with the same trailing slash, so
+    /// `/foo/` doesn't match a route registered at `/foo`.
+    Strict,
+    /// Like [`TrailingSlash::Strict`], but a request that would match if the
+    /// trailing slash was added or removed is redirected to that canonical
+    /// path, using the given status. This should be `301 Moved Permanently`
+    /// or `308 Permanent Redirect`.
+    Redirect(StatusCode),
+}
+
+/// Which percent-encoded characters are decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PercentDecoding {
+    /// Leave percent-encoded characters as they are.
+    None,
+    /// Decode percent-encoded unreserved characters (`A-Z a-z 0-9 - . _ ~`)
+    /// and uppercase the remaining escapes. This never changes the meaning of
+    /// the path, and is the default.
+    #[default]
+    Unreserved,
+    /// Decode unreserved characters before routing, and decode route params
+    /// completely once a route is selected: `/users/:name` matches
+    /// `/users/jane%20doe` with `name = "jane doe"`. As routing happens on
+    /// the encoded path, `%2F` never separates segments.
+    Params,
+}
+
+/// The normalization a [`Server`](crate::Server) applies to the request path
+/// before routing.
+///
+/// The normalized path replaces the request's path, so endpoints and nested
+/// servers see it as well. By default all normalizations are enabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Normalization {
+    /// Collapse runs of slashes, so `/a//b` is routed as `/a/b`.
+    pub merge_slashes: bool,
+    /// Resolve `.` and `..` segments, so `/a/./b/../c` is routed as `/a/c`.
+    pub dot_segments: bool,
+    /// Which percent-encoded characters are decoded.
+    pub percent_decoding: PercentDecoding,
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self {
+            merge_slashes: true,
+            dot_segments: true,
+            percent_decoding: PercentDecoding::default(),
+        }
+    }
+}
+
+impl Normalization {
+    /// Normalize the path of `uri`, returning `None` if it's unchanged.
+    pub(crate) fn apply(&self, uri: &Uri) -> Option<Uri> {
+        let path = self.normalize(uri.path());
+        if path == uri.path() {
+            return None;
+        }
+
+        let path_and_query = match uri.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.into_owned(),
+        };
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = Some(PathAndQuery::from_maybe_shared(path_and_query).ok()?);
+        Uri::from_parts(parts).ok()
+    }
+
+    fn normalize<'a>(&self, path: &'a str) -> Cow<'a, str> {
+        let mut path = Cow::Borrowed(path);
+        if self.percent_decoding != PercentDecoding::None && path.contains('%') {
+            path = Cow::Owned(decode_unreserved(&path));
+        }
+        if self.merge_slashes && path.contains("//") {
+            path = Cow::Owned(merge_slashes(&path));
+        }
+        if self.dot_segments && path.split('/').any(|s| s == "." || s == "..") {
+            path = Cow::Owned(remove_dot_segments(&path));
+        }
+        path
+    }
+
+    /// Decode the params selected by the router, if configured to.
+    pub(crate) fn decode_params(&self, params: Captures<'static, 'static>) -> Captures<'static, 'static> {
+        if self.percent_decoding != PercentDecoding::Params {
+            return params;
+        }
+
+        let mut decoded = Captures::new();
+        for (name, value) in params.iter() {
+            let value = percent_decode(value).unwrap_or_else(|| value.to_owned());
+            decoded.push(Capture::new(name.to_owned(), value));
+        }
+        if let Some(wildcard) = params.wildcard() {
+            decoded.set_wildcard(wildcard.to_owned());
+        }
+        decoded
+    }
+}
+
+/// Whether `path` ends with a slash, not counting the root path.
+pub(crate) fn has_trailing_slash(path: &str) -> bool {
+    path.len() > 1 && path.ends_with('/')
+}
+
+/// Add a trailing slash to `path`, or remove it if there is one.
+pub(crate) fn toggle_trailing_slash(path: &str) -> String {
+    if has_trailing_slash(path) {
+        path.trim_end_matches('/').to_owned()
+    } else {
+        format!("{}/", path)
+    }
+}
+
+fn hex(byte: u8) -> Option<u8> {
+    (byte as char).to_digit(16).map(|d| d as u8)
+}
+
+fn decode_unreserved(path: &str) -> String {
+    let bytes = path.as_bytes();
+    let mut out = String::with_capacity(path.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1).and_then(|b| hex(*b)), bytes.get(i + 2).and_then(|b| hex(*b))) {
+            (b'%', Some(hi), Some(lo)) => {
+                let c = (hi << 4 | lo) as char;
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~') {
+                    out.push(c);
+                } else {
+                    out.push_str(&format!("%{:02X}", hi << 4 | lo));
+                }
+                i += 3;
+            }
+            (byte, _, _) => {
+                out.push(byte as char);
+                i += 1;
+            }
+        }
+    }
+    out
+}
+
+pub(crate) fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1).and_then(|b| hex(*b)), bytes.get(i + 2).and_then(|b| hex(*b))) {
+            (b'%', Some(hi), Some(lo)) => {
+                out.push(hi << 4 | lo);
+                i += 3;
+            }
+            (byte, _, _) => {
+                out.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+fn merge_slashes(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for c in path.chars() {
+        if c != '/' || !out.ends_with('/') {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// Resolve `.` and `..` segments as described in RFC 3986, section 5.2.4.
+fn remove_dot_segments(path: &str) -> String {
+    let mut segments: Vec<&str> = Vec::new();
+    let mut trailing_slash = false;
+    for segment in path.split('/').skip(1) {
+        trailing_slash = matches!(segment, "" | "." | "..");
+        match segment {
+            "." => {}
+            ".." => {
+                segments.pop();
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if trailing_slash && segments.last() != Some(&"") {
+        segments.push("");
+    }
+    format!("/{}", segments.join("/"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn normalize(path: &str) -> String {
+        Normalization::default().normalize(path).into_owned()
+    }
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize("/a//b///c"), "/a/b/c");
+        assert_eq!(normalize("/a/./b/../c"), "/a/c");
+        assert_eq!(normalize("/a/b/.."), "/a/");
+        assert_eq!(normalize("/../../a"), "/a");
+        assert_eq!(normalize("/%7Euser/%2e%2E/x%2fy%c3%a9"), "/x%2Fy%C3%A9");
+        assert_eq!(normalize("/a/"), "/a/");
+        assert_eq!(normalize("/"), "/");
+    }
+
+    #[test]
+    fn keeps_query_when_rewriting() {
+        let uri: Uri = "http://example.com//a/../b?c=//d".parse().unwrap();
+        let normalized = Normalization::default().apply(&uri).unwrap();
+        assert_eq!(normalized, "http://example.com/b?c=//d");
+
+        let uri: Uri = "/a/b".parse().unwrap();
+        assert!(Normalization::default().apply(&uri).is_none());
+    }
+
+    #[test]
+    fn decodes_params() {
+        let normalization = Normalization {
+            percent_decoding: PercentDecoding::Params,
+            ..Normalization::default()
+        };
+        let mut params = Captures::new();
+        params.push(Capture::new("name", "jane%20doe%2F"));
+        params.push(Capture::new("raw", "%FF"));
+        let params = normalization.decode_params(params);
+        assert_eq!(params.get("name"), Some("jane doe/"));
+        assert_eq!(params.get("raw"), Some("%FF"));
+    }
+}
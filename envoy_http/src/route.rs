@@ -1,11 +1,25 @@
 use std::fmt::Debug;
+use std::io;
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
 
-use hyper::Uri;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::http::uri::PathAndQuery;
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, Request, Response, Uri};
 
+use crate::context::MountPath;
+use crate::embed::{EmbeddedDir, ServeEmbedded};
 use crate::endpoint::MiddlewareEndpoint;
-use crate::{router::Router, Endpoint, Middleware};
+use crate::fs::{ServeDir, ServeFile, Spa};
+use crate::host::HostPattern;
+use crate::meta::Metadata;
+use crate::middleware::Next;
+use crate::router::{Conditions, Router};
+use crate::service::ServiceEndpoint;
+use crate::version::{Deprecation, Selector};
+use crate::{ApiVersion, Endpoint, ErrorHandler, IntoEndpoint, Middleware};
 
 /// A handle to a route.
 ///
@@ -20,6 +34,14 @@ pub struct Route<'a> {
     router: &'a mut Router,
     path: String,
     middleware: Vec<Arc<dyn Middleware>>,
+    /// Error handlers, outermost first. They wrap the middleware chain.
+    error_handlers: Vec<Arc<dyn Middleware>>,
+    /// The host and headers a request has to match to be dispatched to the route.
+    conditions: Conditions,
+    /// Metadata made available to middleware and endpoints of the route.
+    meta: Metadata,
+    /// Prepended to the names given to the route and its sub-routes.
+    name_prefix: String,
     /// Indicates whether the path of current route is treated as a prefix. Set by
     /// [`strip_prefix`].
     ///
@@ -33,6 +55,10 @@ impl<'a> Route<'a> {
             router,
             path,
             middleware: Vec::new(),
+            error_handlers: Vec::new(),
+            conditions: Conditions::default(),
+            meta: Metadata::default(),
+            name_prefix: String::new(),
             prefix: false,
         }
     }
@@ -49,10 +75,22 @@ impl<'a> Route<'a> {
             p.push_str(path);
         }
 
+        let mut route = self.scope();
+        route.path = p;
+        route
+    }
+
+    /// A route at the same path, so changes to it are scoped to the routes
+    /// added through it.
+    fn scope(&mut self) -> Route<'_> {
         Route {
             router: self.router,
-            path: p,
+            path: self.path.clone(),
             middleware: self.middleware.clone(),
+            error_handlers: self.error_handlers.clone(),
+            conditions: self.conditions.clone(),
+            meta: self.meta.clone(),
+            name_prefix: self.name_prefix.clone(),
             prefix: false,
         }
     }
@@ -70,12 +108,177 @@ impl<'a> Route<'a> {
         self
     }
 
+    /// Group sub-routes under the given `prefix`.
+    ///
+    /// The closure receives the route at `prefix`, and everything applied to
+    /// it — middleware, error handlers, metadata, conditions and the name
+    /// prefix — is scoped to the routes added within the group. See
+    /// [`Server::group`](crate::Server::group).
+    pub fn group(&mut self, prefix: &str, group: impl FnOnce(&mut Route<'_>)) -> &mut Self {
+        group(&mut self.at(prefix));
+        self
+    }
+
+    /// Add the routes of an API version, selected by a path prefix, a request
+    /// header or a parameter of the `Accept` header.
+    ///
+    /// Like with [`Route::group`], everything applied to the route the
+    /// closure receives is scoped to the version. See [`ApiVersion`] for an
+    /// example.
+    pub fn version(&mut self, version: ApiVersion, routes: impl FnOnce(&mut Route<'_>)) -> &mut Self {
+        let mut route = match version.selector() {
+            Selector::Path(prefix) => self.at(prefix),
+            _ => self.scope(),
+        };
+        match version.selector() {
+            Selector::Path(_) => {}
+            Selector::Header(name, value) => {
+                route.header(name, value);
+            }
+            Selector::Accept(name, value) => {
+                route
+                    .conditions
+                    .accept_params
+                    .push((name.clone(), value.clone()));
+            }
+        }
+        if version.has_headers() {
+            route.with(Deprecation);
+        }
+        route.meta(version);
+        routes(&mut route);
+        self
+    }
+
+    /// Name the current route, so a URL for it can be generated with
+    /// [`Server::url_for`](crate::Server::url_for). The name is also
+    /// available to middleware through [`Context::route`](crate::Context::route).
+    ///
+    /// The name is prefixed with the name prefix of the route, if any.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a route with the same name has already been registered.
+    pub fn name(&mut self, name: &str) -> &mut Self {
+        let name = format!("{}{}", self.name_prefix, name);
+        self.router.add_name(name, &self.path);
+        self
+    }
+
+    /// Prefix the names of the current route and its sub-routes, e.g.
+    /// `admin.` to have `users` registered as `admin.users`.
+    pub fn name_prefix(&mut self, prefix: &str) -> &mut Self {
+        self.name_prefix.push_str(prefix);
+        self
+    }
+
+    /// Attach typed metadata to the current route and its sub-routes.
+    ///
+    /// Middleware and endpoints can read it through
+    /// [`Context::meta`](crate::Context::meta) once the route is selected,
+    /// including middleware applied to the whole server with
+    /// [`Server::with`](crate::Server::with), e.g. to check required
+    /// permissions. A value replaces any value of the same type attached
+    /// earlier, including by an enclosing group.
+    ///
+    /// Only endpoints added after the metadata see it.
+    pub fn meta<T: Send + Sync + 'static>(&mut self, value: T) -> &mut Self {
+        self.meta.insert(value);
+        self
+    }
+
+    /// Handle errors returned by the endpoints and middleware of the current
+    /// route and its sub-routes.
+    ///
+    /// The handler can turn the error into a response, or return an error
+    /// itself. Handlers of enclosing groups see the errors returned by
+    /// handlers of nested groups.
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Response};
+    ///
+    /// async fn users(_: &mut envoy::Context) -> envoy::Result {
+    ///     Ok(Response::new(Body::from("ann, bob")))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.group("/admin", |admin| {
+    ///     admin.on_error(|_ctx: &mut envoy::Context, err: envoy::Error| async move {
+    ///         let mut res = Response::new(Body::from(format!("admin error: {}", err)));
+    ///         *res.status_mut() = err.status();
+    ///         Ok(res)
+    ///     });
+    ///     admin.at("/users").get(users);
+    /// });
+    /// ```
+    pub fn on_error(&mut self, handler: impl ErrorHandler + 'static) -> &mut Self {
+        self.error_handlers
+            .push(Arc::new(ErrorHandlerMiddleware(Arc::new(handler))));
+        self
+    }
+
     /// Reset the middleware chain for the current route, if any.
     pub fn reset_middleware(&mut self) -> &mut Self {
         self.middleware.clear();
         self
     }
 
+    /// Only match requests for the given `host`.
+    ///
+    /// The pattern is a dot-separated list of labels, compared
+    /// case-insensitively. A label written as `:name` matches any label and
+    /// captures it as a param, and a leading `*` matches one or more labels
+    /// which are captured as the `subdomain` param:
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Response};
+    ///
+    /// async fn dashboard(ctx: &mut envoy::Context) -> envoy::Result {
+    ///     let tenant = ctx.param("subdomain")?;
+    ///     Ok(Response::new(Body::from(format!("dashboard of {}", tenant))))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.at("/dashboard")
+    ///     .host("*.tenant.example.com")
+    ///     .get(dashboard);
+    /// ```
+    ///
+    /// Routes bound to a host take precedence over host-agnostic routes,
+    /// which serve as the fallback for requests to any other host.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the pattern is malformed.
+    pub fn host(&mut self, host: &str) -> &mut Self {
+        let pattern = HostPattern::parse(host)
+            .unwrap_or_else(|err| panic!("Invalid host `{}`: {}", host, err));
+        self.conditions.host = Some(pattern);
+        self
+    }
+
+    /// Only match requests carrying a `name` header equal to `value`, e.g. an
+    /// `Accept-Version` header to dispatch between API versions.
+    ///
+    /// Can be called several times to require several headers. When a
+    /// request matches multiple routes for the same path, the route with the
+    /// most header conditions wins, so routes without conditions act as the
+    /// fallback.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` or `value` are not valid header names and values.
+    pub fn header(&mut self, name: &str, value: &str) -> &mut Self {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .unwrap_or_else(|err| panic!("Invalid header name `{}`: {}", name, err));
+        let value = HeaderValue::from_str(value)
+            .unwrap_or_else(|err| panic!("Invalid header value `{}`: {}", value, err));
+        self.conditions.headers.push((name, value));
+        self
+    }
+
     /// Nest a [`Server`] at the current path.
     ///
     /// # Note
@@ -94,22 +297,140 @@ impl<'a> Route<'a> {
         self
     }
 
+    /// Mount a `tower::Service` at the current path.
+    ///
+    /// Like with [`Route::nest`], the service handles the path and all paths
+    /// below it, for any method, and receives requests with the path the
+    /// service is mounted at stripped. Errors of the service become
+    /// `500 Internal Server Error` responses.
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Request, Response};
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.at("/legacy").service(tower::service_fn(|req: Request<Body>| async move {
+    ///     Ok::<_, std::convert::Infallible>(Response::new(Body::from(req.uri().to_string())))
+    /// }));
+    /// ```
+    pub fn service<S, B>(&mut self, service: S) -> &mut Self
+    where
+        S: tower_service::Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+        S::Future: Send,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        B: HttpBody<Data = Bytes> + Send + 'static,
+        B::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let prefix = self.prefix;
+
+        self.prefix = true;
+        self.all(ServiceEndpoint::new(service));
+        self.prefix = prefix;
+
+        self
+    }
+
+    /// Serve the files below the directory `dir` at the current path.
+    ///
+    /// `GET` and `HEAD` requests for the path and all paths below it are
+    /// answered with the file at the rest of the path, captured by a
+    /// wildcard appended to the route unless the route already ends in one.
+    /// See [`ServeDir`] for the details.
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// # fn main() -> std::io::Result<()> {
+    /// let mut app = envoy::Server::new();
+    /// app.at("/static").serve_dir("public/")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if `dir` doesn't exist or isn't a directory.
+    pub fn serve_dir(&mut self, dir: impl AsRef<Path>) -> io::Result<&mut Self> {
+        Ok(self.serve_dir_with(ServeDir::new(dir)?))
+    }
+
+    /// Like [`Route::serve_dir`], with a [`ServeDir`] configured beyond the
+    /// defaults, e.g. with directory listings.
+    pub fn serve_dir_with(&mut self, serve_dir: ServeDir) -> &mut Self {
+        self.tree(serve_dir)
+    }
+
+    /// Serve the file at `path` for `GET` and `HEAD` requests to the current
+    /// path. See [`ServeFile`] for the details.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `path` doesn't exist.
+    pub fn serve_file(&mut self, path: impl AsRef<Path>) -> io::Result<&mut Self> {
+        let serve_file = ServeFile::new(path)?;
+        self.get(serve_file.clone()).head(serve_file);
+        Ok(self)
+    }
+
+    /// Serve the files of a directory embedded in the binary at the current
+    /// path, like [`Route::serve_dir`]. See the [`embed`](crate::embed)
+    /// module for embedding a directory.
+    pub fn serve_embedded(&mut self, dir: &'static EmbeddedDir) -> &mut Self {
+        self.tree(ServeEmbedded::new(dir))
+    }
+
+    /// Host the single-page app in the directory `dir` at the current path,
+    /// answering navigations to paths without a file with its `index.html`.
+    /// See [`Spa`] for the details.
+    ///
+    /// Routes added to more specific paths, e.g. an API, take precedence.
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// # fn main() -> std::io::Result<()> {
+    /// async fn health(_: &mut envoy::Context) -> envoy::Result {
+    ///     Ok(envoy::Response::new(envoy::Body::from("ok")))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.at("/api/health").get(health);
+    /// app.at("/").serve_spa("dist/")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    /// # Errors
+    ///
+    /// Fails if `dir` isn't a directory or has no `index.html`.
+    pub fn serve_spa(&mut self, dir: impl AsRef<Path>) -> io::Result<&mut Self> {
+        Ok(self.tree(Spa::new(dir)?))
+    }
+
+    /// Remove the endpoints registered at the current path for any method,
+    /// including a server nested at it, together with their names.
+    ///
+    /// This is meant for [`Server::update_routes`](crate::Server::update_routes),
+    /// e.g. to replace a nested server while the outer server is running.
+    pub fn remove(&mut self) -> &mut Self {
+        self.router.remove(&self.path);
+        let nested = self.at("*").path;
+        self.router.remove(&nested);
+        self
+    }
+
     /// Add an endpoint for the given HTTP method
-    pub fn method(&mut self, method: hyper::Method, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn method<A>(&mut self, method: hyper::Method, ep: impl IntoEndpoint<A>) -> &mut Self {
+        let ep = ep.into_endpoint();
         if self.prefix {
             let ep = StripPrefixEndpoint::new(ep);
             let wildcard = self.at("*");
-            wildcard.router.add(
-                &wildcard.path,
-                method,
-                MiddlewareEndpoint::wrap_with_middleware(ep, wildcard.middleware),
-            );
+            let ep = wildcard.wrap(ep);
+            wildcard
+                .router
+                .add(&wildcard.path, method, ep, wildcard.conditions, wildcard.meta);
         } else {
-            self.router.add(
-                &self.path,
-                method,
-                MiddlewareEndpoint::wrap_with_middleware(ep, self.middleware.clone()),
-            );
+            let ep = self.wrap(ep);
+            self.router
+                .add(&self.path, method, ep, self.conditions.clone(), self.meta.clone());
         }
         self
     }
@@ -117,78 +438,174 @@ impl<'a> Route<'a> {
     /// Add an endpoint for all HTTP methods, as a fallback.
     ///
     /// Routes with specific HTTP methods will be tried first.
-    pub fn all(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn all<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
+        let ep = ep.into_endpoint();
         if self.prefix {
             let ep = StripPrefixEndpoint::new(ep);
             let wildcard = self.at("*");
-            wildcard.router.add_all(
-                &wildcard.path,
-                MiddlewareEndpoint::wrap_with_middleware(ep, wildcard.middleware),
-            );
+            let ep = wildcard.wrap(ep);
+            wildcard
+                .router
+                .add_all(&wildcard.path, ep, wildcard.conditions, wildcard.meta);
         } else {
-            self.router.add_all(
-                &self.path,
-                MiddlewareEndpoint::wrap_with_middleware(ep, self.middleware.clone()),
-            );
+            let ep = self.wrap(ep);
+            self.router
+                .add_all(&self.path, ep, self.conditions.clone(), self.meta.clone());
         }
         self
     }
 
+    /// Add `ep` for `GET` and `HEAD` requests to the path and all paths
+    /// below it, captured by a wildcard unless the path already ends in one.
+    fn tree(&mut self, ep: impl Endpoint + Clone + 'static) -> &mut Self {
+        if self.path.ends_with('*') {
+            self.get(ep.clone()).head(ep);
+        } else {
+            self.at("*").get(ep.clone()).head(ep);
+        }
+        self
+    }
+
+    /// Wrap the endpoint with the error handlers and middleware of the route.
+    fn wrap(&self, ep: impl Endpoint + 'static) -> Arc<dyn Endpoint + Send + Sync> {
+        let middleware = self
+            .error_handlers
+            .iter()
+            .chain(&self.middleware)
+            .cloned()
+            .collect();
+        MiddlewareEndpoint::wrap_with_middleware(ep, middleware)
+    }
+
     /// Add an endpoint for `GET` requests
-    pub fn get(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn get<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::GET, ep);
         self
     }
 
     /// Add an endpoint for `HEAD` requests
-    pub fn head(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn head<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::HEAD, ep);
         self
     }
 
     /// Add an endpoint for `PUT` requests
-    pub fn put(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn put<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::PUT, ep);
         self
     }
 
     /// Add an endpoint for `POST` requests
-    pub fn post(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn post<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::POST, ep);
         self
     }
 
     /// Add an endpoint for `DELETE` requests
-    pub fn delete(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn delete<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::DELETE, ep);
         self
     }
 
     /// Add an endpoint for `OPTIONS` requests
-    pub fn options(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn options<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::OPTIONS, ep);
         self
     }
 
     /// Add an endpoint for `CONNECT` requests
-    pub fn connect(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn connect<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::CONNECT, ep);
         self
     }
 
     /// Add an endpoint for `PATCH` requests
-    pub fn patch(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn patch<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::PATCH, ep);
         self
     }
 
     /// Add an endpoint for `TRACE` requests
-    pub fn trace(&mut self, ep: impl Endpoint + 'static) -> &mut Self {
+    pub fn trace<A>(&mut self, ep: impl IntoEndpoint<A>) -> &mut Self {
         self.method(hyper::Method::TRACE, ep);
         self
     }
 }
 
+/// The route selected to handle a request, as seen through
+/// [`Context::route`](crate::Context::route).
+///
+/// Middleware can use it to key off the route rather than the raw path, e.g.
+/// to label metrics with `/users/:id` instead of `/users/42`.
+#[derive(Debug, Clone)]
+pub struct MatchedRoute {
+    pattern: String,
+    name: Option<String>,
+    meta: Metadata,
+}
+
+impl MatchedRoute {
+    pub(crate) fn new(pattern: String, name: Option<String>, meta: Metadata) -> Self {
+        Self { pattern, name, meta }
+    }
+
+    /// The pattern the route was registered with, e.g. `/users/:id`.
+    ///
+    /// Inside a nested [`Server`](crate::Server) this is the full pattern,
+    /// including the path the server is nested at.
+    #[must_use]
+    pub fn pattern(&self) -> &str {
+        &self.pattern
+    }
+
+    /// The name given to the route with [`Route::name`], if any.
+    #[must_use]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Borrow the metadata of type `T` attached with [`Route::meta`].
+    #[must_use]
+    pub fn meta<T: 'static>(&self) -> Option<&T> {
+        self.meta.get::<T>()
+    }
+
+    pub(crate) fn set_name(&mut self, name: String) {
+        self.name = Some(name);
+    }
+
+    /// Combine the route a nested server is mounted at with the route
+    /// selected by the nested server. The inner route's name and metadata
+    /// take precedence.
+    pub(crate) fn nest(&self, inner: &MatchedRoute) -> MatchedRoute {
+        let prefix = self.pattern.trim_end_matches('*').trim_end_matches('/');
+        let pattern = match (prefix, inner.pattern.as_str()) {
+            ("", pattern) => pattern.to_owned(),
+            (prefix, "/") => prefix.to_owned(),
+            (prefix, pattern) => format!("{}{}", prefix, pattern),
+        };
+        let mut meta = self.meta.clone();
+        meta.extend(&inner.meta);
+        MatchedRoute {
+            pattern,
+            name: inner.name.clone().or_else(|| self.name.clone()),
+            meta,
+        }
+    }
+}
+
+struct ErrorHandlerMiddleware(Arc<dyn ErrorHandler>);
+
+#[async_trait::async_trait]
+impl Middleware for ErrorHandlerMiddleware {
+    async fn handle(&self, ctx: &mut crate::Context, next: Next) -> crate::Result {
+        match next.run(ctx).await {
+            Ok(res) => Ok(res),
+            Err(err) => self.0.handle(ctx, err).await,
+        }
+    }
+}
+
 #[derive(Debug)]
 struct StripPrefixEndpoint(std::sync::Arc<dyn Endpoint>);
 
@@ -214,12 +631,38 @@ impl Endpoint for StripPrefixEndpoint
             .iter()
             .rev()
             .find_map(|captures| captures.wildcard())
-            .unwrap_or_default();
-
-        let uri = Uri::from_str(rest)
+            .unwrap_or_default()
+            .trim_start_matches('/');
+
+        // `routefinder` ignores trailing slashes, so the wildcard is a suffix
+        // of the path without them.
+        let uri = ctx.borrow::<Uri>();
+        let prefix = uri
+            .path()
+            .trim_end_matches('/')
+            .strip_suffix(rest)
+            .unwrap_or_default()
+            .trim_end_matches('/');
+        let path = match &uri.path()[prefix.len()..] {
+            "" => "/",
+            path => path,
+        };
+        let mount_path = format!("{}{}", ctx.mount_path(), prefix);
+
+        let path_and_query = match uri.query() {
+            Some(query) => format!("{}?{}", path, query),
+            None => path.to_owned(),
+        };
+        let mut parts = uri.clone().into_parts();
+        parts.path_and_query = Some(
+            PathAndQuery::from_str(&path_and_query)
+                .map_err(|err| anyhow::anyhow!("InvalidUri: {:#?}", err))?,
+        );
+        let uri = Uri::from_parts(parts)
             .map_err(|err| anyhow::anyhow!("InvalidUri: {:#?}", err))?;
 
         ctx.insert(uri);
+        ctx.insert(MountPath(mount_path));
 
         self.0
             .call(ctx)
@@ -1,99 +1,573 @@
-use hyper::Response;
-use routefinder::{Captures, Router as MethodRouter};
+use hyper::header::{HeaderName, HeaderValue, ACCEPT, HOST};
+use hyper::{HeaderMap, Method, Response, Uri};
+use routefinder::{Capture, Captures, RouteSpec, Segment};
+use std::cmp::Ordering;
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, RwLock};
 
+use crate::constraint::Constraints;
 use crate::endpoint::DynEndpoint;
-use crate::{StatusCode};
+use crate::host::HostPattern;
+use crate::meta::Metadata;
+use crate::path::{self, TrailingSlash};
+use crate::tree::{Found, Tree};
+use crate::version;
+use crate::{MatchedRoute, StatusCode};
 
 /// The routing table used by `Server`
 ///
-/// Internally, we have a separate state machine per http method; indexing
-/// by the method first allows the table itself to be more efficient.
-#[allow(missing_debug_implementations)]
+/// All routes live in a single radix tree, regardless of their method. A
+/// lookup walks the tree once and yields every route matching the path; the
+/// method, the `405 Method Not Allowed` check and the fallback from `HEAD` to
+/// `GET` are then resolved among those matches.
+#[derive(Clone)]
 pub(crate) struct Router {
-    method_map: HashMap<hyper::Method, MethodRouter<Arc<DynEndpoint>>>,
-    all_method_router: MethodRouter<Arc<DynEndpoint>>,
+    tree: Tree<usize>,
+    /// The routes in the order they were added, indexed by the tree.
+    entries: Vec<Entry>,
+    trailing_slash: TrailingSlash,
+    /// Route patterns by name, for generating URLs.
+    names: HashMap<String, String>,
 }
 
 impl std::fmt::Debug for Router {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let routes: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| match &entry.method {
+                Some(method) => format!("{} {}", method, entry.route.pattern()),
+                None => format!("* {}", entry.route.pattern()),
+            })
+            .collect();
         f.debug_struct("Router")
-            .field("method_map", &self.method_map)
-            .field("all_method_router", &self.all_method_router)
+            .field("routes", &routes)
+            .field("trailing_slash", &self.trailing_slash)
+            .field("names", &self.names)
             .finish()
     }
 }
 
+/// A routing table that can be replaced while the server is running.
+///
+/// Each request routes on a snapshot of the table, so replacing the table
+/// doesn't affect requests that are already in flight.
+#[derive(Debug)]
+pub(crate) struct SharedRouter {
+    current: RwLock<Arc<Router>>,
+    /// Held while a new table is built, so concurrent updates don't
+    /// overwrite each other.
+    update: Mutex<()>,
+}
+
+impl SharedRouter {
+    pub(crate) fn new(router: Router) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(router)),
+            update: Mutex::new(()),
+        }
+    }
+
+    /// The current routing table.
+    pub(crate) fn load(&self) -> Arc<Router> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replace the routing table.
+    pub(crate) fn store(&self, router: Arc<Router>) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = router;
+    }
+
+    /// Block other updates until the guard is dropped.
+    pub(crate) fn lock(&self) -> MutexGuard<'_, ()> {
+        self.update.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Mutable access to the table, as long as no request holds a snapshot.
+    pub(crate) fn get_mut(&mut self) -> Option<&mut Router> {
+        Arc::get_mut(self.current.get_mut().unwrap_or_else(PoisonError::into_inner))
+    }
+}
+
+/// An endpoint in the routing table, together with the constraints its
+/// params have to satisfy and the conditions the request has to meet.
+#[derive(Debug, Clone)]
+struct Entry {
+    spec: RouteSpec,
+    /// The method the endpoint handles, or `None` for all methods.
+    method: Option<Method>,
+    endpoint: Arc<DynEndpoint>,
+    constraints: Constraints,
+    conditions: Conditions,
+    /// Whether the route was registered with a trailing slash, or `None` if
+    /// it ends in a wildcard.
+    trailing_slash: Option<bool>,
+    /// The pattern, name and metadata of the route, handed to the request.
+    route: Arc<MatchedRoute>,
+}
+
+/// Request conditions besides path and method a route is dispatched on.
+///
+/// Routes without conditions match any request; they serve as the fallback
+/// when no route with matching conditions exists.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Conditions {
+    pub(crate) host: Option<HostPattern>,
+    pub(crate) headers: Vec<(HeaderName, HeaderValue)>,
+    /// Parameters one of the media types in the `Accept` header must have.
+    pub(crate) accept_params: Vec<(String, String)>,
+}
+
+/// The parts of a request the router dispatches on.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Target<'a> {
+    pub(crate) path: &'a str,
+    pub(crate) method: &'a Method,
+    pub(crate) host: Option<&'a str>,
+    pub(crate) headers: &'a HeaderMap,
+}
+
 /// The result of routing a URL
 pub(crate) struct Selection {
     pub(crate) endpoint: Arc<DynEndpoint>,
     pub(crate) params: Captures<'static, 'static>,
+    /// The params captured from the host, kept apart from the ones of the
+    /// path.
+    pub(crate) host_params: Captures<'static, 'static>,
+    /// The request headers the routes at the path are dispatched on, for
+    /// the `Vary` header of the response.
+    pub(crate) vary: Vec<HeaderName>,
+    /// The route that was selected, or `None` if no route matched.
+    pub(crate) route: Option<Arc<MatchedRoute>>,
 }
 
 impl Router {
     pub(crate) fn new() -> Self {
         Router {
-            method_map: HashMap::default(),
-            all_method_router: MethodRouter::new(),
+            tree: Tree::new(),
+            entries: Vec::new(),
+            trailing_slash: TrailingSlash::default(),
+            names: HashMap::default(),
         }
     }
 
+    pub(crate) fn set_trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
     pub(crate) fn add(
         &mut self,
         path: &str,
         method: hyper::Method,
         ep: Arc<DynEndpoint>,
+        conditions: Conditions,
+        meta: Metadata,
+    ) {
+        let name = self.name_of(path);
+        self.insert(Entry::parse(path, Some(method), ep, conditions, meta, name));
+    }
+
+    pub(crate) fn add_all(
+        &mut self,
+        path: &str,
+        ep: Arc<DynEndpoint>,
+        conditions: Conditions,
+        meta: Metadata,
     ) {
-        self.method_map
-            .entry(method)
-            .or_insert_with(MethodRouter::new)
-            .add(path, ep)
-            .unwrap()
-    }
-
-    pub(crate) fn add_all(&mut self, path: &str, ep: Arc<DynEndpoint>) {
-        self.all_method_router.add(path, ep).unwrap()
-    }
-
-    pub(crate) fn route(&self, path: &str, method: hyper::Method) -> Selection {
-        if let Some(m) = self
-            .method_map
-            .get(&method)
-            .and_then(|r| r.best_match(path))
-        {
-            Selection {
-                endpoint: m.handler().clone(),
-                params: m.captures().into_owned(),
+        let name = self.name_of(path);
+        self.insert(Entry::parse(path, None, ep, conditions, meta, name));
+    }
+
+    fn insert(&mut self, entry: Entry) {
+        self.tree.insert(&entry.spec, self.entries.len());
+        self.entries.push(entry);
+    }
+
+    pub(crate) fn add_name(&mut self, name: String, path: &str) {
+        if let Some(existing) = self.names.insert(name.clone(), path.to_owned()) {
+            panic!("Route name `{}` is already registered for `{}`", name, existing);
+        }
+
+        // Routes added before they were named.
+        for entry in &mut self.entries {
+            if entry.route.pattern() == path {
+                Arc::make_mut(&mut entry.route).set_name(name.clone());
             }
-        } else if let Some(m) = self.all_method_router.best_match(path) {
-            Selection {
-                endpoint: m.handler().clone(),
-                params: m.captures().into_owned(),
+        }
+    }
+
+    /// Remove the routes registered with the given pattern for any method,
+    /// together with their names.
+    pub(crate) fn remove(&mut self, pattern: &str) {
+        let entries = std::mem::take(&mut self.entries);
+        self.tree = Tree::new();
+        for entry in entries {
+            if entry.route.pattern() != pattern {
+                self.insert(entry);
             }
-        } else if method == hyper::Method::HEAD {
-            // If it is a HTTP HEAD request then check if there is a callback in the endpoints map
-            // if not then fallback to the behavior of HTTP GET else proceed as usual
+        }
+        self.names.retain(|_, path| path != pattern);
+    }
 
-            self.route(path, hyper::Method::GET)
-        } else if self
-            .method_map
+    /// The name registered for a route pattern, if any.
+    fn name_of(&self, path: &str) -> Option<String> {
+        self.names
             .iter()
-            .filter(|(k, _)| **k != method)
-            .any(|(_, r)| r.best_match(path).is_some())
-        {
+            .find(|(_, pattern)| *pattern == path)
+            .map(|(name, _)| name.clone())
+    }
+
+    /// Fill in the params of the route with the given name.
+    pub(crate) fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        let (path, constraints) = Constraints::parse(self.names.get(name)?).ok()?;
+        let spec: RouteSpec = path.parse().ok()?;
+        let captures: Captures<'_, '_> = params.iter().collect();
+        if !constraints.matches(&captures) {
+            return None;
+        }
+
+        let mut url = String::from("/");
+        for segment in spec.segments() {
+            match segment {
+                Segment::Slash => url.push('/'),
+                Segment::Dot => url.push('.'),
+                Segment::Exact(exact) => url.push_str(exact.as_str()),
+                Segment::Param(param) => url.push_str(captures.get(param.as_str())?),
+                Segment::Wildcard => url.push_str(captures.get("*").unwrap_or_default()),
+            }
+        }
+        if path::has_trailing_slash(&path) && !url.ends_with('/') {
+            url.push('/');
+        }
+        Some(url)
+    }
+
+    pub(crate) fn route(&self, target: Target<'_>) -> Selection {
+        let strict = self.trailing_slash != TrailingSlash::Ignore;
+        let (selection, other_method, vary) = self.select(target, strict);
+        let mut selection = selection.unwrap_or_else(|| self.fallback(target, other_method));
+        // Whichever route handles the request, the response depends on the
+        // headers the routes at the path are dispatched on.
+        selection.vary = vary;
+        selection
+    }
+
+    /// The endpoint for requests no route accepts.
+    fn fallback(&self, target: Target<'_>, other_method: bool) -> Selection {
+        let endpoint: Arc<DynEndpoint> = match self.trailing_slash {
+            TrailingSlash::Redirect(status) if self.has_canonical(target) => {
+                Arc::new(TrailingSlashRedirect(status))
+            }
             // If this `path` can be handled by a callback registered with a different HTTP method
             // should return 405 Method Not Allowed
-            Selection {
-                endpoint: Arc::new(method_not_allowed),
-                params: Captures::default(),
+            _ if other_method => Arc::new(method_not_allowed),
+            _ => Arc::new(not_found_endpoint),
+        };
+        Selection {
+            endpoint,
+            params: Captures::default(),
+            host_params: Captures::default(),
+            vary: Vec::new(),
+            route: None,
+        }
+    }
+
+    /// Whether a route accepts the request with the trailing slash of its
+    /// path toggled.
+    fn has_canonical(&self, target: Target<'_>) -> bool {
+        let canonical = path::toggle_trailing_slash(target.path);
+        let canonical = Target {
+            path: &canonical,
+            ..target
+        };
+        self.select(canonical, true).0.is_some()
+    }
+
+    /// Select the best route for the request, also returning whether a route
+    /// for another method matches, and the request headers the routes at the
+    /// path are dispatched on.
+    ///
+    /// Routes registered for the request method are preferred over routes for
+    /// all methods, and for `HEAD` requests both are preferred over `GET`
+    /// routes, which handle them otherwise.
+    fn select(&self, target: Target<'_>, strict: bool) -> (Option<Selection>, bool, Vec<HeaderName>) {
+        let trailing_slash = path::has_trailing_slash(target.path);
+        let mut best: Option<Candidate<'_>> = None;
+        let mut other_method = false;
+        let mut vary: Vec<HeaderName> = Vec::new();
+
+        for Found { value, captures } in self.tree.find(target.path) {
+            let entry = &self.entries[*value];
+            for name in entry.conditions.vary() {
+                if !vary.contains(name) {
+                    vary.push(name.clone());
+                }
             }
+            let tier = match entry.tier(target.method) {
+                Some(tier) => tier,
+                None => {
+                    // A route for another method only matters for `405`.
+                    other_method = other_method
+                        || entry.accepts(target, &captures, strict, trailing_slash).is_some();
+                    continue;
+                }
+            };
+            if best.as_ref().is_some_and(|best| best.tier < tier) {
+                continue;
+            }
+            let (params, host_params) = match entry.accepts(target, &captures, strict, trailing_slash) {
+                Some(params) => params,
+                None => continue,
+            };
+
+            let candidate = Candidate {
+                index: *value,
+                entry,
+                tier,
+                params,
+                host_params,
+            };
+            best = match best {
+                Some(best) if best.tier == tier && rank(&candidate, &best) != Ordering::Greater => {
+                    Some(best)
+                }
+                _ => Some(candidate),
+            };
+        }
+
+        let selection = best.map(|best| Selection {
+            endpoint: best.entry.endpoint.clone(),
+            params: best.params,
+            host_params: best.host_params,
+            vary: Vec::new(),
+            route: Some(best.entry.route.clone()),
+        });
+        (selection, other_method, vary)
+    }
+}
+
+/// A route matching the request, while looking for the best one.
+struct Candidate<'a> {
+    index: usize,
+    entry: &'a Entry,
+    /// How well the method of the route fits the request, lower is better.
+    tier: u8,
+    params: Captures<'static, 'static>,
+    host_params: Captures<'static, 'static>,
+}
+
+impl Entry {
+    fn parse(
+        pattern: &str,
+        method: Option<Method>,
+        endpoint: Arc<DynEndpoint>,
+        conditions: Conditions,
+        meta: Metadata,
+        name: Option<String>,
+    ) -> Self {
+        let (path, constraints) = Constraints::parse(pattern)
+            .unwrap_or_else(|err| panic!("Invalid route `{}`: {}", pattern, err));
+        let spec = path
+            .parse()
+            .unwrap_or_else(|err| panic!("Invalid route `{}`: {}", pattern, err));
+        let trailing_slash = if pattern.ends_with('*') {
+            None
         } else {
-            Selection {
-                endpoint: Arc::new(not_found_endpoint),
-                params: Captures::default(),
+            Some(path::has_trailing_slash(pattern))
+        };
+        let route = Arc::new(MatchedRoute::new(pattern.to_owned(), name, meta));
+        Self {
+            spec,
+            method,
+            endpoint,
+            constraints,
+            conditions,
+            trailing_slash,
+            route,
+        }
+    }
+
+    /// How well the route fits the request method, lower is better, or
+    /// `None` if it's registered for another method.
+    fn tier(&self, method: &Method) -> Option<u8> {
+        match &self.method {
+            Some(m) if m == method => Some(0),
+            None => Some(1),
+            Some(m) if m == Method::GET && method == Method::HEAD => Some(2),
+            Some(_) => None,
+        }
+    }
+
+    /// Check the request against the route, returning the params of the
+    /// path and the host if the route accepts it.
+    ///
+    /// With `strict` set, the trailing slash of the path has to agree with
+    /// the one the route was registered with.
+    fn accepts(
+        &self,
+        target: Target<'_>,
+        captures: &[&str],
+        strict: bool,
+        trailing_slash: bool,
+    ) -> Option<(Captures<'static, 'static>, Captures<'static, 'static>)> {
+        if strict && self.trailing_slash.is_some_and(|ts| ts != trailing_slash) {
+            return None;
+        }
+
+        let mut params = Captures::new();
+        let segments = self
+            .spec
+            .segments()
+            .iter()
+            .filter(|s| matches!(s, Segment::Param(_) | Segment::Wildcard));
+        for (segment, capture) in segments.zip(captures) {
+            match segment {
+                Segment::Param(name) => {
+                    params.push(Capture::new(name.as_str().to_owned(), (*capture).to_owned()))
+                }
+                _ => params.set_wildcard((*capture).to_owned()),
             }
         }
+        if !self.constraints.matches(&params) {
+            return None;
+        }
+
+        let host_params = self
+            .conditions
+            .matches(target)?
+            .into_iter()
+            .map(|(name, value)| Capture::new(name, value))
+            .collect();
+        Some((params, host_params))
+    }
+}
+
+impl Conditions {
+    /// Check the request against the conditions, returning the params
+    /// captured from the host.
+    fn matches(&self, target: Target<'_>) -> Option<Vec<(String, String)>> {
+        let captures = match &self.host {
+            Some(pattern) => pattern.matches(target.host?)?,
+            None => Vec::new(),
+        };
+
+        let headers_match = self
+            .headers
+            .iter()
+            .all(|(name, value)| target.headers.get_all(name).iter().any(|v| v == value));
+        let accept_match = self
+            .accept_params
+            .iter()
+            .all(|(name, value)| version::accepts_param(target.headers, name, value));
+
+        (headers_match && accept_match).then_some(captures)
+    }
+
+    /// The request headers the conditions depend on.
+    fn vary(&self) -> impl Iterator<Item = &HeaderName> {
+        let accept = self.accept_params.first().map(|_| &ACCEPT);
+        self.headers.iter().map(|(name, _)| name).chain(accept)
+    }
+
+    /// The number of header conditions, including the `Accept` parameters.
+    fn header_count(&self) -> usize {
+        self.headers.len() + self.accept_params.len()
+    }
+
+    fn specificity(&self) -> u8 {
+        self.host.as_ref().map_or(0, HostPattern::specificity)
+    }
+}
+
+impl<'a> Target<'a> {
+    pub(crate) fn new(uri: &'a Uri, method: &'a Method, headers: &'a HeaderMap) -> Self {
+        let host = uri.host().or_else(|| {
+            headers
+                .get(HOST)
+                .and_then(|host| host.to_str().ok())
+                .map(|host| match host.rsplit_once(':') {
+                    Some((host, port)) if port.bytes().all(|b| b.is_ascii_digit()) => host,
+                    _ => host,
+                })
+        });
+
+        Self {
+            path: uri.path(),
+            method,
+            host,
+            headers,
+        }
+    }
+}
+
+/// Compare two routes matching the same request.
+///
+/// Routes bound to a host are preferred over host-agnostic ones. Then
+/// segments rank `Exact > Param > Wildcard`; on top of that a constrained
+/// param outranks an unconstrained one in the same position, so
+/// `/users/:id(\d+)` is preferred over `/users/:name` for `/users/42`.
+/// Next, a route with more header conditions wins. Remaining ties are broken
+/// like `routefinder` does, and finally the route added first wins.
+fn rank(a: &Candidate<'_>, b: &Candidate<'_>) -> Ordering {
+    let (x, y) = (a.entry, b.entry);
+    x.conditions
+        .specificity()
+        .cmp(&y.conditions.specificity())
+        .then_with(|| rank_segments(x, y))
+        .then_with(|| x.conditions.header_count().cmp(&y.conditions.header_count()))
+        .then_with(|| dots(x).cmp(&dots(y)))
+        .then_with(|| y.spec.segments().len().cmp(&x.spec.segments().len()))
+        .then_with(|| b.index.cmp(&a.index))
+}
+
+fn rank_segments(a: &Entry, b: &Entry) -> Ordering {
+    a.spec
+        .segments()
+        .iter()
+        .zip(b.spec.segments())
+        .map(|(x, y)| match (x, y) {
+            (Segment::Param(p), Segment::Param(q)) => a
+                .constraints
+                .contains(p)
+                .cmp(&b.constraints.contains(q)),
+            _ => x.cmp(y),
+        })
+        .find(|ordering| *ordering != Ordering::Equal)
+        .unwrap_or(Ordering::Equal)
+}
+
+fn dots(entry: &Entry) -> usize {
+    entry
+        .spec
+        .segments()
+        .iter()
+        .filter(|s| matches!(s, Segment::Dot))
+        .count()
+}
+
+/// Redirects to the path with the trailing slash added or removed.
+#[derive(Debug)]
+struct TrailingSlashRedirect(StatusCode);
+
+#[async_trait::async_trait]
+impl crate::Endpoint for TrailingSlashRedirect {
+    async fn call(&self, ctx: &mut crate::Context) -> crate::Result {
+        let uri = ctx.borrow::<Uri>();
+        let mut location = ctx.mount_path().to_owned();
+        location.push_str(&path::toggle_trailing_slash(uri.path()));
+        if let Some(query) = uri.query() {
+            location.push('?');
+            location.push_str(query);
+        }
+
+        let res = Response::builder()
+            .status(self.0)
+            .header(hyper::header::LOCATION, location)
+            .body(hyper::Body::empty())?;
+        Ok(res)
     }
 }
 
@@ -107,4 +581,4 @@ async fn method_not_allowed(_ctx: &mut crate::Context) -> crate::Result {
     let mut res = Response::new("Method Not Allowed".into());
     *res.status_mut() = StatusCode::METHOD_NOT_ALLOWED;
     Ok(res)
-}
\ No newline at end of file
+}
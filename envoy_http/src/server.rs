@@ -3,11 +3,12 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
 
-use hyper::{Uri, Method};
+use hyper::{HeaderMap, Method, Uri};
 
+use crate::channels::Channels;
 use crate::middleware::{Middleware, Next};
-use crate::router::{Router, Selection};
-use crate::{Endpoint, Route};
+use crate::router::{Router, Selection, SharedRouter, Target};
+use crate::{CookieKeys, Endpoint, Normalization, Route, TrailingSlash};
 
 /// An HTTP server.
 ///
@@ -25,7 +26,9 @@ use crate::{Endpoint, Route};
 /// response processing, such as compression, default headers, or logging. To
 /// add middleware to an app, use the [`Server::with`] method.
 pub struct Server {
-    router: Arc<Router>,
+    /// The routing table, shared with the clones of the server so it can be
+    /// replaced while they serve requests.
+    router: Arc<SharedRouter>,
     /// Holds the middleware stack.
     ///
     /// Note(Fishrock123): We do actually want this structure.
@@ -35,6 +38,7 @@ pub struct Server {
     /// We don't use a Mutex around the Vec here because adding a middleware during execution should be an error.
     #[allow(clippy::rc_buffer)]
     middleware: Arc<Vec<Arc<dyn Middleware>>>,
+    normalization: Normalization,
 }
 
 impl Server {
@@ -42,8 +46,9 @@ impl Server {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            router: Arc::new(Router::new()),
+            router: Arc::new(SharedRouter::new(Router::new())),
             middleware: Arc::new(Vec::new()),
+            normalization: Normalization::default(),
         }
     }
 }
@@ -83,6 +88,11 @@ impl Server {
     /// the required structure, but where the parameters are not required.
     /// `:` will match a segment, and `*` will match an entire path.
     ///
+    /// A named parameter can be constrained by following it with a regular
+    /// expression in parentheses, e.g. `:id(\d+)`. The expression has to match
+    /// the entire segment; requests that don't satisfy it fall through to
+    /// other routes, or result in a 404.
+    ///
     /// Here are some examples omitting the HTTP verb based endpoint selection:
     ///
     /// ```rust,no_run
@@ -93,17 +103,188 @@ impl Server {
     /// app.at("files/:user/*");
     /// app.at("static/*path");
     /// app.at("static/:context/:");
+    /// app.at("users/:id(\\d+)");
+    /// app.at("posts/:kind(draft|published)");
     /// ```
     ///
-    /// There is no fallback route matching, i.e. either a resource is a full
-    /// match or not, which means that the order of adding resources has no
-    /// effect.
+    /// When several routes match a request, the most specific one is
+    /// selected. Routes bound to a host come first; then segments rank exact
+    /// over parameter over wildcard, with a constrained parameter ahead of an
+    /// unconstrained one in the same position, so `users/:id(\d+)` wins over
+    /// `users/:name` for `/users/42`; then routes with more header conditions
+    /// come first. Routes that are still tied, e.g. `users/:id(\d+)` and
+    /// `users/:id([0-9]+)`, go to the one added first.
     pub fn at<'a>(&'a mut self, path: &str) -> Route<'a> {
         let router = Arc::get_mut(&mut self.router)
-            .expect("Registering routes is not possible after the Server has started");
+            .and_then(SharedRouter::get_mut)
+            .expect("Registering routes is not possible after the Server has started, use `Server::update_routes` instead");
         Route::new(router, path.to_owned())
     }
 
+    /// Add routes that only match requests for the given `host`.
+    ///
+    /// The returned [`Route`] is rooted at `/`, and further paths are added
+    /// through [`Route::at`]. Requests for other hosts fall back to the routes
+    /// that aren't bound to a host. See [`Route::host`] for the pattern syntax.
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Response};
+    ///
+    /// async fn users(ctx: &mut envoy::Context) -> envoy::Result {
+    ///     let tenant = ctx.param("tenant").unwrap_or("api");
+    ///     Ok(Response::new(Body::from(format!("users of {}", tenant))))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.host("api.example.com").at("/users").get(users);
+    /// app.host(":tenant.example.com").at("/users").get(users);
+    /// ```
+    pub fn host<'a>(&'a mut self, host: &str) -> Route<'a> {
+        let mut route = self.at("");
+        route.host(host);
+        route
+    }
+
+    /// Group routes under the given `prefix`.
+    ///
+    /// The closure receives the [`Route`] at `prefix`. Middleware, error
+    /// handlers, metadata, conditions and name prefixes applied to it are
+    /// scoped to the routes added within the group:
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Response};
+    ///
+    /// struct RequiredPermission(&'static str);
+    ///
+    /// async fn auth(ctx: &mut envoy::Context, next: envoy::Next) -> envoy::Result {
+    ///     if let Some(RequiredPermission(permission)) = ctx.meta() {
+    ///         // check the permission
+    ///     }
+    ///     next.run(ctx).await
+    /// }
+    ///
+    /// async fn list_users(_: &mut envoy::Context) -> envoy::Result {
+    ///     Ok(Response::new(Body::from("ann, bob")))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.group("/admin", |admin| {
+    ///     admin.with(auth).name_prefix("admin.");
+    ///     admin.meta(RequiredPermission("admin"));
+    ///     admin.at("/users").name("users").get(list_users);
+    /// });
+    /// assert_eq!(app.url_for("admin.users", &[]).unwrap(), "/admin/users");
+    /// ```
+    pub fn group(&mut self, prefix: &str, group: impl FnOnce(&mut Route<'_>)) -> &mut Self {
+        group(&mut self.at(prefix));
+        self
+    }
+
+    /// Generate the path of the route registered with the given `name`,
+    /// filling in its params from `params`. A wildcard is filled in from the
+    /// param named `*`.
+    ///
+    /// Returns `None` if there is no such route, a param is missing, or a
+    /// param doesn't satisfy its constraint. Values are inserted as given, so
+    /// they have to be percent-encoded already.
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Response};
+    ///
+    /// async fn show_user(ctx: &mut envoy::Context) -> envoy::Result {
+    ///     Ok(Response::new(Body::from(format!("user {}", ctx.param("id")?))))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.at("/users/:id(\\d+)").name("user").get(show_user);
+    /// assert_eq!(app.url_for("user", &[("id", "42")]).unwrap(), "/users/42");
+    /// assert!(app.url_for("user", &[("id", "nori")]).is_none());
+    /// ```
+    #[must_use]
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Option<String> {
+        self.router.load().url_for(name, params)
+    }
+
+    /// Set how a trailing slash in the request path is treated.
+    ///
+    /// By default `/foo` and `/foo/` match the same routes. With
+    /// [`TrailingSlash::Strict`] a path only matches routes registered with
+    /// the same trailing slash, and with [`TrailingSlash::Redirect`] requests
+    /// for the other form are redirected to the registered one:
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Response, StatusCode, TrailingSlash};
+    ///
+    /// async fn users(_: &mut envoy::Context) -> envoy::Result {
+    ///     Ok(Response::new(Body::from("ann, bob")))
+    /// }
+    ///
+    /// let mut app = envoy::Server::new();
+    /// app.trailing_slash(TrailingSlash::Redirect(StatusCode::PERMANENT_REDIRECT));
+    /// app.at("/users/").get(users); // `/users` redirects to `/users/`
+    /// ```
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) -> &mut Self {
+        let router = Arc::get_mut(&mut self.router)
+            .and_then(SharedRouter::get_mut)
+            .expect("Configuring routing is not possible after the Server has started");
+        router.set_trailing_slash(policy);
+        self
+    }
+
+    /// Change the routes of a server that may already be running.
+    ///
+    /// The closure receives a copy of the server whose routes can be changed
+    /// as usual, e.g. with [`Server::at`] and [`Route::remove`]. Once it
+    /// returns, the new routing table atomically replaces the current one for
+    /// this server and all its clones. Requests that are in flight finish
+    /// with the routes they were dispatched with.
+    ///
+    /// Only the routes are updated: middleware can't be added, and the path
+    /// normalization can't be changed through the copy.
+    ///
+    /// ```rust,no_run
+    /// # use envoy_http as envoy;
+    /// use envoy::{Body, Response};
+    ///
+    /// async fn hello(_: &mut envoy::Context) -> envoy::Result {
+    ///     Ok(Response::new(Body::from("Hello, world!")))
+    /// }
+    ///
+    /// let app = envoy::Server::new();
+    /// # let plugin = envoy::Server::new();
+    /// let running = app.clone();
+    /// // ... later, while `running` serves requests:
+    /// app.update_routes(|app| {
+    ///     app.at("/hello").get(hello);
+    ///     // Replace the server nested at `/plugin`, if any.
+    ///     app.at("/plugin").remove().nest(plugin);
+    /// });
+    /// ```
+    pub fn update_routes(&self, update: impl FnOnce(&mut Server)) {
+        let _guard = self.router.lock();
+        let mut staging = Server {
+            router: Arc::new(SharedRouter::new(Router::clone(&self.router.load()))),
+            middleware: self.middleware.clone(),
+            normalization: self.normalization,
+        };
+        update(&mut staging);
+        self.router.store(staging.router.load());
+    }
+
+    /// Set the normalization applied to the request path before routing.
+    ///
+    /// By default duplicate slashes are merged, `.` and `..` segments are
+    /// resolved, and percent-encoded unreserved characters are decoded. The
+    /// normalized path replaces the path of the request.
+    pub fn normalize_path(&mut self, normalization: Normalization) -> &mut Self {
+        self.normalization = normalization;
+        self
+    }
+
     /// Add middleware to an application.
     ///
     /// Middleware provides customization of the request/response cycle, such as compression,
@@ -122,6 +303,40 @@ impl Server {
         self
     }
 
+    /// Add state to the application.
+    ///
+    /// A clone of the state is inserted into the [`Context`](crate::Context)
+    /// of every request before any middleware runs, where endpoints can get
+    /// it with [`Context::borrow`](crate::Context::borrow) or the
+    /// [`State`](crate::extract::State) extractor. Calling this again with a
+    /// value of the same type replaces the state.
+    pub fn with_state<T>(&mut self, state: T) -> &mut Self
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        let m = Arc::get_mut(&mut self.middleware)
+            .expect("Registering state is not possible after the Server has started");
+        let name = std::any::type_name::<StateMiddleware<T>>();
+        m.retain(|middleware| middleware.name() != name);
+        m.insert(0, Arc::new(StateMiddleware(state)));
+        self
+    }
+
+    /// Set the keys signing and encrypting cookies, for
+    /// [`Context::signed_cookies`](crate::Context::signed_cookies) and
+    /// [`Context::private_cookies`](crate::Context::private_cookies).
+    pub fn cookie_keys(&mut self, keys: CookieKeys) -> &mut Self {
+        self.with_state(keys)
+    }
+
+    /// Add channels to publish to and subscribe from handlers, with
+    /// [`Context::channels`](crate::Context::channels) and
+    /// [`Context::subscribe`](crate::Context::subscribe). Channels carrying
+    /// different types of messages can be added side by side.
+    pub fn channels<T: Clone + Send + Sync + 'static>(&mut self, channels: Channels<T>) -> &mut Self {
+        self.with_state(channels)
+    }
+
     /// Respond to a `Request` with a `Response`.
     ///
     /// This method is useful for testing endpoints directly,
@@ -131,39 +346,60 @@ impl Server {
         Req: Into<hyper::Request<hyper::Body>>,
         Res: From<hyper::Response<hyper::Body>>,
     {
-        let req: hyper::Request<hyper::Body> = req.into();
+        let mut req: hyper::Request<hyper::Body> = req.into();
         let Self {
             router,
             middleware,
+            normalization,
         } = self.clone();
+        let router = router.load();
+
+        if let Some(uri) = normalization.apply(req.uri()) {
+            *req.uri_mut() = uri;
+        }
 
-        let method = req.method().to_owned();
-        let Selection { endpoint, params } = router.route(req.uri().path(), method);
-        let route_params = vec![params];
+        let target = Target::new(req.uri(), req.method(), req.headers());
+        let Selection {
+            endpoint,
+            params,
+            host_params,
+            vary,
+            route,
+        } = router.route(target);
+        let route_params = vec![host_params, normalization.decode_params(params)];
         let mut ctx = crate::Context::new(req, route_params);
+        ctx.set_route(route);
+        ctx.vary(vary);
 
         let next = Next::new(endpoint, middleware);
 
-        match next.run(&mut ctx).await {
-            Ok(res) => Ok(res.into()),
-            Err(e) => {
-                let res = hyper::Response::builder()
-                    .status(hyper::StatusCode::INTERNAL_SERVER_ERROR)
-                    .body(hyper::Body::from(format!("{}", e)))
-                    .unwrap();
-                Ok(res.into())
-            }
-        }
+        let mut res = match next.run(&mut ctx).await {
+            Ok(res) => res,
+            Err(e) => hyper::Response::builder()
+                .status(e.status())
+                .body(hyper::Body::from(format!("{}", e)))
+                .unwrap(),
+        };
+        ctx.apply_response_headers(&mut res);
+        ctx.apply_cookies(&mut res);
+        Ok(res.into())
+    }
+
+    /// Select the route for a request without handling it. Only meant for
+    /// the router benchmark.
+    #[cfg(feature = "bench")]
+    #[doc(hidden)]
+    pub fn __route(&self, req: &hyper::Request<hyper::Body>) -> Option<Arc<crate::MatchedRoute>> {
+        let router = self.router.load();
+        router.route(Target::new(req.uri(), req.method(), req.headers())).route
     }
 
     /// Start the server.
     pub async fn listen(self, addr: SocketAddr) -> Result<(), crate::Error> {
 
-        let make_svc = hyper::service::make_service_fn(move |_conn|{
+        let make_svc = hyper::service::make_service_fn(move |_conn| {
             let server = self.clone();
-            async move {
-                Ok::<_, hyper::Error>(hyper::service::service_fn(move |req| server.clone().respond(req)))
-            }
+            async move { Ok::<_, hyper::Error>(server) }
         });
 
         let server = hyper::Server::bind(&addr).serve(make_svc);
@@ -186,21 +422,51 @@ impl Clone for Server {
         Self {
             router: self.router.clone(),
             middleware: self.middleware.clone(),
+            normalization: self.normalization,
         }
     }
 }
 
+/// Inserts a clone of the state of a server into the context.
+struct StateMiddleware<T>(T);
+
+impl<T> std::fmt::Debug for StateMiddleware<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "StateMiddleware<{}>", std::any::type_name::<T>())
+    }
+}
+
+#[async_trait::async_trait]
+impl<T: Clone + Send + Sync + 'static> Middleware for StateMiddleware<T> {
+    async fn handle(&self, ctx: &mut crate::Context, next: Next) -> crate::Result {
+        ctx.insert(self.0.clone());
+        next.run(ctx).await
+    }
+}
+
 #[async_trait::async_trait]
 impl Endpoint for Server
 {
     async fn call(&self, ctx: &mut crate::Context) -> crate::Result {
-        let path = ctx.borrow::<Uri>().path();
-        let method = ctx.borrow::<Method>().to_owned();
-        let router = self.router.clone();
+        let router = self.router.load();
         let middleware = self.middleware.clone();
 
-        let Selection { endpoint, params } = router.route(&path, method);
-        ctx.params.push(params);
+        if let Some(uri) = self.normalization.apply(ctx.borrow::<Uri>()) {
+            ctx.insert(uri);
+        }
+
+        let target = Target::new(ctx.borrow::<Uri>(), ctx.borrow::<Method>(), ctx.borrow::<HeaderMap>());
+        let Selection {
+            endpoint,
+            params,
+            host_params,
+            vary,
+            route,
+        } = router.route(target);
+        ctx.params.push(host_params);
+        ctx.params.push(self.normalization.decode_params(params));
+        ctx.set_route(route);
+        ctx.vary(vary);
 
         let next = Next::new(endpoint, middleware);
 
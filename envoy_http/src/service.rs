@@ -0,0 +1,258 @@
+//! Interoperability with `tower`.
+//!
+//! [`Server`] implements `tower::Service`, so it can be wrapped in `tower`
+//! layers and served by any hyper-compatible tooling. The other way around,
+//! [`Route::service`](crate::Route::service) mounts a `tower::Service` at a
+//! path, and [`TowerLayer`] applies a `tower::Layer` as middleware.
+
+use std::any::Any;
+use std::error::Error as StdError;
+use std::fmt::{self, Debug};
+use std::future::{poll_fn, Future};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context as TaskContext, Poll};
+
+use futures_util::stream;
+use hyper::body::{Bytes, HttpBody};
+use hyper::http::Extensions;
+use hyper::Method;
+use tower_layer::Layer;
+use tower_service::Service;
+
+use crate::middleware::Next;
+use crate::{Body, Context, Endpoint, Error, Middleware, Request, Response, Server, StatusCode};
+
+type BoxError = Box<dyn StdError + Send + Sync>;
+
+impl Service<Request<Body>> for Server {
+    type Response = Response<Body>;
+    type Error = hyper::Error;
+    type Future = Pin<Box<dyn Future<Output = hyper::Result<Response<Body>>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        Box::pin(self.clone().respond(req))
+    }
+}
+
+/// Convert any response body into a hyper [`Body`].
+fn into_body<B>(body: B) -> Body
+where
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    let mut body = Some(body);
+    if let Some(body) = (&mut body as &mut dyn Any).downcast_mut::<Option<Body>>() {
+        return body.take().unwrap();
+    }
+
+    let body = Box::pin(body.unwrap());
+    Body::wrap_stream(stream::unfold(body, |mut body| async move {
+        let chunk = body.data().await?;
+        Some((chunk.map_err(Into::into), body))
+    }))
+}
+
+fn service_error(err: impl Into<BoxError>) -> Error {
+    Error::from_str(StatusCode::INTERNAL_SERVER_ERROR, err.into().to_string())
+}
+
+/// An endpoint handing requests to a `tower::Service`.
+pub(crate) struct ServiceEndpoint<S>(Mutex<S>);
+
+impl<S> ServiceEndpoint<S> {
+    pub(crate) fn new(service: S) -> Self {
+        Self(Mutex::new(service))
+    }
+}
+
+impl<S> Debug for ServiceEndpoint<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ServiceEndpoint<{}>", std::any::type_name::<S>())
+    }
+}
+
+#[async_trait::async_trait]
+impl<S, B> Endpoint for ServiceEndpoint<S>
+where
+    S: Service<Request<Body>, Response = Response<B>> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError>,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    async fn call(&self, ctx: &mut Context) -> crate::Result {
+        let mut service = self.0.lock().unwrap_or_else(PoisonError::into_inner).clone();
+        poll_fn(|cx| service.poll_ready(cx)).await.map_err(service_error)?;
+        let res = service.call(ctx.take_request()).await.map_err(service_error)?;
+        Ok(res.map(into_body))
+    }
+}
+
+/// Applies a `tower::Layer` as [`Middleware`].
+///
+/// The layer wraps the remaining middleware chain, which it sees as a
+/// [`NextService`]. Changes the layer makes to the request are visible to the
+/// rest of the chain, and changes to the response to the middleware before
+/// it:
+///
+/// ```rust,no_run
+/// # use envoy_http as envoy;
+/// use envoy::http::header::{HeaderValue, SERVER};
+/// use tower_http::set_header::SetResponseHeaderLayer;
+///
+/// let mut app = envoy::Server::new();
+/// app.with(envoy::TowerLayer::new(SetResponseHeaderLayer::overriding(
+///     SERVER,
+///     HeaderValue::from_static("envoy"),
+/// )));
+/// ```
+///
+/// Errors returned by the rest of the chain are passed on unchanged, while
+/// the layer sees a response with the status and message of the error in
+/// their place.
+/// Errors of the layer itself become `500 Internal Server Error`.
+pub struct TowerLayer<L>(L);
+
+impl<L> TowerLayer<L> {
+    /// Wrap a `tower::Layer`.
+    pub fn new(layer: L) -> Self {
+        Self(layer)
+    }
+}
+
+impl<L> Debug for TowerLayer<L> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "TowerLayer<{}>", std::any::type_name::<L>())
+    }
+}
+
+/// The state a request leaves behind while it passes through a layer.
+struct Slot {
+    ctx: Option<Context>,
+    next: Option<Next>,
+    error: Option<Error>,
+}
+
+/// The remaining middleware chain as a `tower::Service`, for layers applied
+/// with [`TowerLayer`].
+#[derive(Clone)]
+pub struct NextService(Arc<Mutex<Slot>>);
+
+impl Debug for NextService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NextService")
+    }
+}
+
+impl NextService {
+    fn slot(&self) -> std::sync::MutexGuard<'_, Slot> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Puts the context back into the slot once the rest of the chain is done
+/// with it, or when the layer drops the call before, e.g. on a timeout.
+struct Restore(NextService, Context);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let ctx = self.1.take_all();
+        self.0.slot().ctx = Some(ctx);
+    }
+}
+
+impl Service<Request<Body>> for NextService {
+    type Response = Response<Body>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    /// Run the rest of the chain. Fails if it already ran, as layers
+    /// retrying requests would do.
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let this = self.clone();
+        Box::pin(async move {
+            let (ctx, next) = {
+                let mut slot = this.slot();
+                match (slot.ctx.take(), slot.next.take()) {
+                    (Some(ctx), Some(next)) => (ctx, next),
+                    (ctx, next) => {
+                        slot.ctx = ctx;
+                        slot.next = next;
+                        return Err("The rest of the middleware chain can only be called once".into());
+                    }
+                }
+            };
+
+            let mut restore = Restore(this.clone(), ctx);
+            restore.1.insert_request(req);
+            let res = next.run(&mut restore.1).await;
+            drop(restore);
+
+            Ok(res.unwrap_or_else(|err| {
+                let res = Response::builder()
+                    .status(err.status())
+                    .body(Body::from(err.to_string()))
+                    .unwrap();
+                this.slot().error = Some(err);
+                res
+            }))
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl<L, B> Middleware for TowerLayer<L>
+where
+    L: Layer<NextService> + Send + Sync + 'static,
+    L::Service: Service<Request<Body>, Response = Response<B>> + Send,
+    <L::Service as Service<Request<Body>>>::Future: Send,
+    <L::Service as Service<Request<Body>>>::Error: Into<BoxError>,
+    B: HttpBody<Data = Bytes> + Send + 'static,
+    B::Error: Into<BoxError>,
+{
+    async fn handle(&self, ctx: &mut Context, next: Next) -> crate::Result {
+        let req = ctx.take_request();
+        // Kept to restore the context if the layer answers without passing
+        // the request on.
+        let parts = (req.method().clone(), req.uri().clone(), req.version(), req.headers().clone());
+        let inner = NextService(Arc::new(Mutex::new(Slot {
+            ctx: Some(ctx.take_all()),
+            next: Some(next),
+            error: None,
+        })));
+
+        let mut service = self.0.layer(inner.clone());
+        // The context is restored below, so errors must not return early.
+        let res = match poll_fn(|cx| service.poll_ready(cx)).await.map_err(service_error) {
+            Ok(()) => service.call(req).await.map_err(service_error),
+            Err(err) => Err(err),
+        };
+
+        let mut slot = inner.slot();
+        if let Some(inner_ctx) = slot.ctx.take() {
+            *ctx = inner_ctx;
+        }
+        if ctx.try_borrow::<Method>().is_none() {
+            // The extensions went with the request and can't be cloned.
+            let (method, uri, version, headers) = parts;
+            ctx.insert(method);
+            ctx.insert(uri);
+            ctx.insert(version);
+            ctx.insert(headers);
+            ctx.insert(Extensions::default());
+        }
+        if let Some(err) = slot.error.take() {
+            return Err(err);
+        }
+        Ok(res?.map(into_body))
+    }
+}
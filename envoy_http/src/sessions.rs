@@ -0,0 +1,549 @@
+//! Server-side sessions.
+//!
+//! The [`Sessions`] middleware identifies the session of a request by a
+//! cookie, loads it from a [`SessionStore`] before the rest of the chain
+//! runs, and saves the changes afterwards. Endpoints read and change the
+//! session through [`Context::session`] and [`Context::session_mut`]:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use std::time::Duration;
+//!
+//! use envoy::sessions::{MemoryStore, Sessions};
+//! use envoy::{Body, Response};
+//!
+//! async fn visits(ctx: &mut envoy::Context) -> envoy::Result {
+//!     let visits = ctx.session().get::<u32>("visits").unwrap_or(0) + 1;
+//!     ctx.session_mut().insert("visits", visits)?;
+//!     Ok(Response::new(Body::from(format!("visit {}", visits))))
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.with(Sessions::new(MemoryStore::new()).idle_timeout(Duration::from_secs(30 * 60)));
+//! app.at("/").get(visits);
+//! ```
+//!
+//! Sessions end after a period without requests, and optionally a fixed time
+//! after they were created, whichever comes first. After a user logs in, call
+//! [`Session::regenerate`] so a session ID obtained before can't be used to
+//! act as the user.
+//!
+//! [`Context::session`]: crate::Context::session
+//! [`Context::session_mut`]: crate::Context::session_mut
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{Duration, SystemTime};
+
+use async_trait::async_trait;
+use cookie::{Cookie, SameSite};
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::middleware::Next;
+use crate::{Context, Middleware};
+
+const ID_LEN: usize = 48;
+
+/// How often the [`MemoryStore`] looks for expired sessions.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+fn generate_id() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(ID_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// Whether `id` could have been generated by [`generate_id`]. Anything else
+/// is never handed to a store.
+fn is_valid_id(id: &str) -> bool {
+    id.len() == ID_LEN && id.bytes().all(|b| b.is_ascii_alphanumeric())
+}
+
+/// The data of a session, as JSON values by key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Session {
+    id: String,
+    data: HashMap<String, Value>,
+    created: SystemTime,
+    accessed: SystemTime,
+    #[serde(skip)]
+    changes: Changes,
+}
+
+/// What happened to a session while handling a request.
+#[derive(Debug, Clone, Default)]
+struct Changes {
+    /// The session isn't in the store yet.
+    new: bool,
+    changed: bool,
+    destroyed: bool,
+    /// The ID the session had in the store before it was regenerated.
+    previous_id: Option<String>,
+}
+
+impl Session {
+    fn new(now: SystemTime) -> Self {
+        Self {
+            id: generate_id(),
+            data: HashMap::new(),
+            created: now,
+            accessed: now,
+            changes: Changes {
+                new: true,
+                ..Changes::default()
+            },
+        }
+    }
+
+    /// The ID of the session, which its cookie holds.
+    #[must_use]
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// When the session was created.
+    #[must_use]
+    pub fn created(&self) -> SystemTime {
+        self.created
+    }
+
+    /// Whether the session holds no data.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// The value stored under `key`, or `None` if there is none or it isn't
+    /// a `T`.
+    #[must_use]
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.data
+            .get(key)
+            .and_then(|value| T::deserialize(value).ok())
+    }
+
+    /// Store a value under `key`, replacing the previous one.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the value can't be serialized to JSON.
+    pub fn insert<T: Serialize>(&mut self, key: &str, value: T) -> crate::Result<()> {
+        self.data.insert(key.to_owned(), serde_json::to_value(value)?);
+        self.changes.changed = true;
+        Ok(())
+    }
+
+    /// Remove the value stored under `key`.
+    pub fn remove(&mut self, key: &str) {
+        if self.data.remove(key).is_some() {
+            self.changes.changed = true;
+        }
+    }
+
+    /// Remove all values.
+    pub fn clear(&mut self) {
+        if !self.data.is_empty() {
+            self.data.clear();
+            self.changes.changed = true;
+        }
+    }
+
+    /// Move the session to a new ID, keeping its data. The session can't be
+    /// found under the old ID anymore.
+    ///
+    /// Call this when the privileges of a session change, e.g. on login, to
+    /// prevent session fixation.
+    pub fn regenerate(&mut self) {
+        let previous = std::mem::replace(&mut self.id, generate_id());
+        if !self.changes.new && self.changes.previous_id.is_none() {
+            self.changes.previous_id = Some(previous);
+        }
+        self.changes.changed = true;
+    }
+
+    /// End the session, e.g. on logout. Its data is removed from the store,
+    /// and its cookie from the client.
+    pub fn destroy(&mut self) {
+        self.data.clear();
+        self.changes.destroyed = true;
+    }
+}
+
+/// Where sessions are kept between requests.
+///
+/// Stores for databases such as Redis or SQL implement this trait, usually
+/// storing sessions serialized with `serde` and relying on the database to
+/// drop them once they expire.
+#[async_trait]
+pub trait SessionStore: Send + Sync + 'static {
+    /// Load the session with the given ID, or `None` if there is none or it
+    /// expired.
+    async fn load(&self, id: &str) -> crate::Result<Option<Session>>;
+
+    /// Save a session, to be kept at least until `expires`.
+    async fn save(&self, session: &Session, expires: SystemTime) -> crate::Result<()>;
+
+    /// Delete the session with the given ID, if there is one.
+    async fn destroy(&self, id: &str) -> crate::Result<()>;
+}
+
+/// Middleware loading and saving the session of each request.
+///
+/// The session cookie is `HttpOnly`, `SameSite=Lax` and valid for all paths
+/// by default, and is only sent once the session holds data.
+pub struct Sessions<S> {
+    store: S,
+    cookie: Cookie<'static>,
+    idle_timeout: Duration,
+    absolute_timeout: Option<Duration>,
+}
+
+impl<S> Debug for Sessions<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sessions")
+            .field("store", &std::any::type_name::<S>())
+            .field("cookie", &self.cookie.name())
+            .field("idle_timeout", &self.idle_timeout)
+            .field("absolute_timeout", &self.absolute_timeout)
+            .finish()
+    }
+}
+
+impl<S: SessionStore> Sessions<S> {
+    /// Keep sessions in `store`. Sessions end after a day without requests.
+    #[must_use]
+    pub fn new(store: S) -> Self {
+        let cookie = Cookie::build(("envoy.sid", ""))
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .build();
+        Self {
+            store,
+            cookie,
+            idle_timeout: Duration::from_secs(24 * 60 * 60),
+            absolute_timeout: None,
+        }
+    }
+
+    /// Set the name of the session cookie, `envoy.sid` by default.
+    #[must_use]
+    pub fn cookie_name(mut self, name: &str) -> Self {
+        self.cookie.set_name(name.to_owned());
+        self
+    }
+
+    /// Only send the session cookie to the given path and below.
+    #[must_use]
+    pub fn cookie_path(mut self, path: &str) -> Self {
+        self.cookie.set_path(path.to_owned());
+        self
+    }
+
+    /// Send the session cookie to the given domain and its subdomains.
+    #[must_use]
+    pub fn cookie_domain(mut self, domain: &str) -> Self {
+        self.cookie.set_domain(domain.to_owned());
+        self
+    }
+
+    /// Only send the session cookie over HTTPS.
+    #[must_use]
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.cookie.set_secure(secure);
+        self
+    }
+
+    /// Set the `SameSite` attribute of the session cookie.
+    #[must_use]
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.cookie.set_same_site(same_site);
+        self
+    }
+
+    /// End sessions after a period without requests.
+    #[must_use]
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = timeout;
+        self
+    }
+
+    /// End sessions a fixed time after they were created, however active
+    /// they are.
+    #[must_use]
+    pub fn absolute_timeout(mut self, timeout: Duration) -> Self {
+        self.absolute_timeout = Some(timeout);
+        self
+    }
+
+    /// When a session accessed at `now` ends.
+    fn expires(&self, session: &Session, now: SystemTime) -> SystemTime {
+        let idle = now + self.idle_timeout;
+        match self.absolute_timeout {
+            Some(timeout) => idle.min(session.created + timeout),
+            None => idle,
+        }
+    }
+
+    async fn load(&self, ctx: &Context, now: SystemTime) -> crate::Result<Option<Session>> {
+        let id = match ctx.cookie(self.cookie.name()) {
+            Some(cookie) if is_valid_id(cookie.value()) => cookie.value().to_owned(),
+            _ => return Ok(None),
+        };
+        match self.store.load(&id).await? {
+            Some(session) if self.expires(&session, session.accessed) <= now => {
+                self.store.destroy(&id).await?;
+                Ok(None)
+            }
+            session => Ok(session),
+        }
+    }
+
+    async fn save(&self, ctx: &mut Context, mut session: Session, now: SystemTime) -> crate::Result<()> {
+        let changes = std::mem::take(&mut session.changes);
+        if let Some(previous_id) = &changes.previous_id {
+            self.store.destroy(previous_id).await?;
+        }
+
+        if changes.destroyed {
+            if !changes.new {
+                self.store.destroy(&session.id).await?;
+            }
+            if ctx.cookie(self.cookie.name()).is_some() {
+                ctx.remove_cookie(self.cookie.clone());
+            }
+            return Ok(());
+        }
+        if changes.new && !changes.changed {
+            return Ok(());
+        }
+
+        session.accessed = now;
+        let expires = self.expires(&session, now);
+        self.store.save(&session, expires).await?;
+
+        let max_age = expires.duration_since(now).unwrap_or_default();
+        let mut cookie = self.cookie.clone();
+        cookie.set_value(session.id);
+        cookie.set_max_age(cookie::time::Duration::seconds(max_age.as_secs() as i64));
+        ctx.set_cookie(cookie);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<S: SessionStore> Middleware for Sessions<S> {
+    async fn handle(&self, ctx: &mut Context, next: Next) -> crate::Result {
+        let now = SystemTime::now();
+        let session = self.load(ctx, now).await?;
+        ctx.insert(session.unwrap_or_else(|| Session::new(now)));
+
+        let result = next.run(ctx).await;
+        let saved = match ctx.try_take::<Session>() {
+            Some(session) => self.save(ctx, session, now).await,
+            None => Ok(()),
+        };
+        let res = result?;
+        saved?;
+        Ok(res)
+    }
+}
+
+/// A [`SessionStore`] keeping sessions in memory, for development and
+/// single-process deployments. Sessions are lost when the process exits.
+///
+/// Expired sessions are dropped when they are loaded, and every minute when
+/// a session is saved.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    inner: Arc<Mutex<MemoryInner>>,
+}
+
+#[derive(Debug, Default)]
+struct MemoryInner {
+    sessions: HashMap<String, (Session, SystemTime)>,
+    next_sweep: Option<SystemTime>,
+}
+
+impl MemoryStore {
+    /// An empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of sessions in the store, including expired ones that
+    /// weren't dropped yet.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.lock().sessions.len()
+    }
+
+    /// Whether the store holds no sessions.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop all expired sessions.
+    pub fn cleanup(&self) {
+        let now = SystemTime::now();
+        self.lock().sessions.retain(|_, (_, expires)| *expires > now);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, MemoryInner> {
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl SessionStore for MemoryStore {
+    async fn load(&self, id: &str) -> crate::Result<Option<Session>> {
+        let mut inner = self.lock();
+        match inner.sessions.get(id) {
+            Some((_, expires)) if *expires <= SystemTime::now() => {
+                inner.sessions.remove(id);
+                Ok(None)
+            }
+            entry => Ok(entry.map(|(session, _)| session.clone())),
+        }
+    }
+
+    async fn save(&self, session: &Session, expires: SystemTime) -> crate::Result<()> {
+        let now = SystemTime::now();
+        let mut inner = self.lock();
+        if inner.next_sweep.is_none_or(|sweep| sweep <= now) {
+            inner.sessions.retain(|_, (_, expires)| *expires > now);
+            inner.next_sweep = Some(now + SWEEP_INTERVAL);
+        }
+        inner
+            .sessions
+            .insert(session.id.clone(), (session.clone(), expires));
+        Ok(())
+    }
+
+    async fn destroy(&self, id: &str) -> crate::Result<()> {
+        self.lock().sessions.remove(id);
+        Ok(())
+    }
+}
+
+/// A [`SessionStore`] keeping each session in a JSON file in a directory.
+///
+/// Expired sessions are deleted when they are loaded; call
+/// [`FileStore::cleanup`] periodically to delete the ones never loaded
+/// again.
+#[derive(Debug, Clone)]
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+#[derive(Serialize)]
+struct FileRecordRef<'a> {
+    expires: SystemTime,
+    session: &'a Session,
+}
+
+#[derive(Deserialize)]
+struct FileRecord {
+    expires: SystemTime,
+    session: Session,
+}
+
+impl FileStore {
+    /// Keep sessions in `dir`, which is created when the first session is
+    /// saved.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    async fn read(&self, id: &str) -> crate::Result<Option<FileRecord>> {
+        let path = self.path(id);
+        let bytes = match tokio::fs::read(&path).await {
+            Ok(bytes) => bytes,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err.into()),
+        };
+        match serde_json::from_slice::<FileRecord>(&bytes) {
+            Ok(record) if record.expires > SystemTime::now() => Ok(Some(record)),
+            Ok(_) => {
+                self.destroy(id).await?;
+                Ok(None)
+            }
+            Err(err) => {
+                tracing::warn!("Deleting unreadable session file {}: {}", path.display(), err);
+                self.destroy(id).await?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Delete the files of all expired sessions.
+    ///
+    /// # Errors
+    ///
+    /// An error is returned if the directory can't be read.
+    pub async fn cleanup(&self) -> crate::Result<()> {
+        let mut entries = match tokio::fs::read_dir(&self.dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let name = entry.file_name();
+            let id = name.to_str().and_then(|name| name.strip_suffix(".json"));
+            if let Some(id) = id.filter(|id| is_valid_id(id)) {
+                self.read(id).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileStore {
+    async fn load(&self, id: &str) -> crate::Result<Option<Session>> {
+        if !is_valid_id(id) {
+            return Ok(None);
+        }
+        Ok(self.read(id).await?.map(|record| record.session))
+    }
+
+    async fn save(&self, session: &Session, expires: SystemTime) -> crate::Result<()> {
+        if !is_valid_id(&session.id) {
+            return Err(anyhow::anyhow!("Invalid session ID").into());
+        }
+        tokio::fs::create_dir_all(&self.dir).await?;
+
+        let json = serde_json::to_vec(&FileRecordRef { expires, session })?;
+        // Write to a temporary file first, so concurrent loads never see a
+        // partially written session.
+        let temp = self.dir.join(format!(".{}.{}.tmp", session.id, generate_id()));
+        tokio::fs::write(&temp, json).await?;
+        tokio::fs::rename(&temp, self.path(&session.id)).await?;
+        Ok(())
+    }
+
+    async fn destroy(&self, id: &str) -> crate::Result<()> {
+        if !is_valid_id(id) {
+            return Ok(());
+        }
+        match tokio::fs::remove_file(self.path(id)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
+}
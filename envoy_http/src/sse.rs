@@ -0,0 +1,333 @@
+//! Server-Sent Events.
+//!
+//! [`endpoint`] turns a handler into an endpoint streaming events to the
+//! client. The handler runs in its own task with the [`Context`] of the
+//! request and an [`SseSender`], and the stream ends when it returns:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use std::time::Duration;
+//!
+//! use envoy::sse::{self, Event, SseSender};
+//!
+//! async fn ticks(_ctx: envoy::Context, sender: SseSender) -> envoy::Result<()> {
+//!     let mut tick: u64 = sender.last_event_id().and_then(|id| id.parse().ok()).unwrap_or(0);
+//!     loop {
+//!         tick += 1;
+//!         let event = Event::new().id(tick.to_string()).event("tick").data(tick.to_string());
+//!         if sender.send(event).await.is_err() {
+//!             return Ok(());
+//!         }
+//!         tokio::time::sleep(Duration::from_secs(1)).await;
+//!     }
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.at("/ticks").get(sse::endpoint(ticks));
+//! ```
+//!
+//! Clients reconnecting after a dropped connection send the ID of the last
+//! event they received, available as [`SseSender::last_event_id`], so the
+//! handler can resume from there.
+
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use futures_util::Stream;
+use hyper::body::Bytes;
+use hyper::header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE};
+use hyper::{Body, Response, StatusCode};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant, Interval, MissedTickBehavior};
+
+use crate::{Context, Endpoint, Error};
+
+/// The name of the `Last-Event-ID` request header.
+const LAST_EVENT_ID: &str = "last-event-id";
+
+/// An endpoint streaming Server-Sent Events, see [`endpoint`].
+pub struct SseEndpoint<F> {
+    handler: Arc<F>,
+    keep_alive: Option<Duration>,
+    buffer: usize,
+}
+
+/// An endpoint streaming the events `handler` sends.
+///
+/// The response is sent as soon as the endpoint is called, with the headers
+/// and cookies staged on the context so far. Middleware running after the
+/// endpoint sees an empty context, as the handler takes it over.
+pub fn endpoint<F, Fut>(handler: F) -> SseEndpoint<F>
+where
+    F: Fn(Context, SseSender) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    SseEndpoint {
+        handler: Arc::new(handler),
+        keep_alive: Some(Duration::from_secs(15)),
+        buffer: 16,
+    }
+}
+
+impl<F> SseEndpoint<F> {
+    /// Send a comment when no event was sent for `interval`, so proxies
+    /// don't close the connection. Defaults to 15 seconds.
+    #[must_use]
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    /// Don't send keep-alive comments.
+    #[must_use]
+    pub fn without_keep_alive(mut self) -> Self {
+        self.keep_alive = None;
+        self
+    }
+
+    /// The number of events that can be waiting to be sent before
+    /// [`SseSender::send`] waits for the client. Defaults to 16.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `events` is 0.
+    #[must_use]
+    pub fn buffer(mut self, events: usize) -> Self {
+        assert!(events > 0, "The buffer must hold at least one event");
+        self.buffer = events;
+        self
+    }
+}
+
+impl<F> Debug for SseEndpoint<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SseEndpoint")
+            .field("keep_alive", &self.keep_alive)
+            .field("buffer", &self.buffer)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<F, Fut> Endpoint for SseEndpoint<F>
+where
+    F: Fn(Context, SseSender) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    async fn call(&self, ctx: &mut Context) -> crate::Result {
+        let (sender, receiver) = mpsc::channel(self.buffer);
+        let last_event_id = ctx
+            .header(LAST_EVENT_ID)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_owned);
+        let sender = SseSender {
+            sender,
+            last_event_id,
+        };
+
+        let mut res = Response::new(Body::wrap_stream(EventStream::new(receiver, self.keep_alive)));
+        let headers = res.headers_mut();
+        headers.insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        ctx.apply_response_headers(&mut res);
+        ctx.apply_cookies(&mut res);
+
+        let handler = self.handler.clone();
+        let ctx = ctx.take_all();
+        tokio::spawn(async move {
+            if let Err(err) = handler(ctx, sender).await {
+                tracing::error!("Server-Sent Events handler failed: {}", err);
+            }
+        });
+        Ok(res)
+    }
+}
+
+/// An event sent to the client.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    id: Option<String>,
+    event: Option<String>,
+    data: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl Event {
+    /// An event without data. Clients only dispatch events with data.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the ID of the event, which clients send back as `Last-Event-ID`
+    /// when they reconnect. Line breaks and NUL characters are removed.
+    #[must_use]
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        let mut id = id.into();
+        id.retain(|c| !matches!(c, '\r' | '\n' | '\0'));
+        self.id = Some(id);
+        self
+    }
+
+    /// Set the name of the event, `message` when it's not set. Line breaks
+    /// are removed.
+    #[must_use]
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        let mut event = event.into();
+        event.retain(|c| !matches!(c, '\r' | '\n'));
+        self.event = Some(event);
+        self
+    }
+
+    /// Set the data of the event. It may span several lines.
+    #[must_use]
+    pub fn data(mut self, data: impl Into<String>) -> Self {
+        self.data = Some(data.into());
+        self
+    }
+
+    /// Set the data of the event to `value`, serialized as JSON.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `value` can't be serialized.
+    pub fn json_data<T: Serialize>(self, value: &T) -> crate::Result<Self> {
+        Ok(self.data(serde_json::to_string(value)?))
+    }
+
+    /// Tell the client how long to wait before reconnecting.
+    #[must_use]
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    fn encode(&self) -> Bytes {
+        let mut out = String::new();
+        if let Some(event) = &self.event {
+            out.push_str(&format!("event: {}\n", event));
+        }
+        if let Some(data) = &self.data {
+            for line in data.split("\r\n").flat_map(|line| line.split(['\r', '\n'])) {
+                out.push_str(&format!("data: {}\n", line));
+            }
+        }
+        if let Some(id) = &self.id {
+            out.push_str(&format!("id: {}\n", id));
+        }
+        if let Some(retry) = self.retry {
+            out.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        out.push('\n');
+        out.into()
+    }
+}
+
+/// Sends events to the client of a Server-Sent Events endpoint.
+#[derive(Debug, Clone)]
+pub struct SseSender {
+    sender: mpsc::Sender<Bytes>,
+    last_event_id: Option<String>,
+}
+
+impl SseSender {
+    /// The `Last-Event-ID` of the request: the ID of the last event a
+    /// reconnecting client received.
+    #[must_use]
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+
+    /// Send an event, waiting if the client is slower than the handler.
+    ///
+    /// # Errors
+    ///
+    /// Fails once the client disconnected.
+    pub async fn send(&self, event: Event) -> crate::Result<()> {
+        self.send_bytes(event.encode()).await
+    }
+
+    /// Send an event with only data.
+    ///
+    /// # Errors
+    ///
+    /// Fails once the client disconnected.
+    pub async fn send_data(&self, data: impl Into<String>) -> crate::Result<()> {
+        self.send(Event::new().data(data)).await
+    }
+
+    /// Send a comment, which clients ignore.
+    ///
+    /// # Errors
+    ///
+    /// Fails once the client disconnected.
+    pub async fn send_comment(&self, comment: &str) -> crate::Result<()> {
+        let mut out = String::new();
+        for line in comment.lines() {
+            out.push_str(&format!(": {}\n", line));
+        }
+        out.push('\n');
+        self.send_bytes(out.into()).await
+    }
+
+    /// Whether the client disconnected.
+    #[must_use]
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// Wait until the client disconnects.
+    pub async fn closed(&self) {
+        self.sender.closed().await;
+    }
+
+    async fn send_bytes(&self, bytes: Bytes) -> crate::Result<()> {
+        self.sender
+            .send(bytes)
+            .await
+            .map_err(|_| Error::from_str(StatusCode::INTERNAL_SERVER_ERROR, "The client disconnected"))
+    }
+}
+
+/// The body of a Server-Sent Events response: the events of the handler,
+/// with keep-alive comments in between.
+struct EventStream {
+    receiver: mpsc::Receiver<Bytes>,
+    keep_alive: Option<Interval>,
+}
+
+impl EventStream {
+    fn new(receiver: mpsc::Receiver<Bytes>, keep_alive: Option<Duration>) -> Self {
+        let keep_alive = keep_alive.map(|period| {
+            let mut interval = time::interval_at(Instant::now() + period, period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            interval
+        });
+        Self { receiver, keep_alive }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<Bytes, std::convert::Infallible>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        if let Poll::Ready(event) = self.receiver.poll_recv(cx) {
+            if let Some(keep_alive) = &mut self.keep_alive {
+                keep_alive.reset();
+            }
+            return Poll::Ready(event.map(Ok));
+        }
+        match &mut self.keep_alive {
+            Some(keep_alive) => keep_alive
+                .poll_tick(cx)
+                .map(|_| Some(Ok(Bytes::from_static(b": keep-alive\n\n")))),
+            None => Poll::Pending,
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Streaming request and response bodies.
+//!
+//! [`Context::body_stream`] reads the request body chunk by chunk, and the
+//! functions of this module build responses whose body is produced while
+//! it's sent, so large bodies never have to fit in memory. Chunks are only
+//! read, or produced, as fast as the other side takes them.
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::stream;
+//! use serde::Serialize;
+//!
+//! #[derive(Serialize)]
+//! struct Order {
+//!     id: u64,
+//! }
+//!
+//! async fn export(_: &mut envoy::Context) -> envoy::Result {
+//!     let (mut sender, res) = stream::channel();
+//!     tokio::spawn(async move {
+//!         for id in 0..1_000_000 {
+//!             if sender.send_json(&Order { id }).await.is_err() {
+//!                 break;
+//!             }
+//!         }
+//!     });
+//!     Ok(res)
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.at("/orders.ndjson").get(export);
+//! ```
+//!
+//! [`Context::body_stream`]: crate::Context::body_stream
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+
+use futures_util::{ready, Stream};
+use hyper::body::{Bytes, HttpBody};
+use hyper::{Body, Response, StatusCode};
+use serde::Serialize;
+use tokio::io::AsyncRead;
+use tokio_util::io::ReaderStream;
+
+use crate::Error;
+
+/// The chunks of a request body, returned by
+/// [`Context::body_stream`](crate::Context::body_stream).
+#[derive(Debug)]
+pub struct BodyStream {
+    body: Body,
+    read: u64,
+    limit: Option<u64>,
+    /// The `Content-Length` of the request, if any.
+    length: Option<u64>,
+}
+
+impl BodyStream {
+    pub(crate) fn new(body: Body, length: Option<u64>) -> Self {
+        Self {
+            body,
+            read: 0,
+            limit: None,
+            length,
+        }
+    }
+
+    /// Fail with `413 Payload Too Large` once the body exceeds `limit`
+    /// bytes. A request whose `Content-Length` exceeds it fails before
+    /// anything is read.
+    #[must_use]
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// The number of bytes read so far.
+    #[must_use]
+    pub fn read(&self) -> u64 {
+        self.read
+    }
+
+    fn too_large(&mut self) -> Error {
+        self.limit = None;
+        self.body = Body::empty();
+        Error::from_str(StatusCode::PAYLOAD_TOO_LARGE, "Request body is too large")
+    }
+}
+
+impl Stream for BodyStream {
+    type Item = crate::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(limit) = self.limit {
+            if self.length.is_some_and(|length| length > limit) {
+                return Poll::Ready(Some(Err(self.too_large())));
+            }
+        }
+
+        let chunk = match ready!(Pin::new(&mut self.body).poll_data(cx)) {
+            Some(Ok(chunk)) => chunk,
+            Some(Err(err)) => return Poll::Ready(Some(Err(body_error(err)))),
+            None => return Poll::Ready(None),
+        };
+        self.read += chunk.len() as u64;
+        if self.limit.is_some_and(|limit| self.read > limit) {
+            return Poll::Ready(Some(Err(self.too_large())));
+        }
+        Poll::Ready(Some(Ok(chunk)))
+    }
+}
+
+/// The error of a request body that grew past a limit while it was
+/// transformed, e.g. by the [`Decompression`](crate::compression::Decompression)
+/// middleware.
+#[derive(Debug)]
+pub(crate) struct BodyTooLarge;
+
+impl fmt::Display for BodyTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Request body is too large")
+    }
+}
+
+impl StdError for BodyTooLarge {}
+
+/// The error of reading a request body: `413 Payload Too Large` if it grew
+/// past a limit, and `400 Bad Request` otherwise.
+pub(crate) fn body_error(err: hyper::Error) -> Error {
+    let status = if exceeds_limit(&err) {
+        StatusCode::PAYLOAD_TOO_LARGE
+    } else {
+        StatusCode::BAD_REQUEST
+    };
+    Error::new(status, err)
+}
+
+/// Whether `err` or one of its sources is a [`BodyTooLarge`].
+pub(crate) fn exceeds_limit(err: &(dyn StdError + 'static)) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if err.is::<BodyTooLarge>() {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+/// A response streaming the contents of `reader`, e.g. a file or the output
+/// of a process.
+pub fn from_reader<R>(reader: R) -> Response<Body>
+where
+    R: AsyncRead + Send + 'static,
+{
+    from_stream(ReaderStream::new(reader))
+}
+
+/// A response streaming the chunks of `stream`. An error ends the response
+/// abruptly, so the client can tell it's incomplete.
+pub fn from_stream<S, O, E>(stream: S) -> Response<Body>
+where
+    S: Stream<Item = Result<O, E>> + Send + 'static,
+    O: Into<Bytes> + 'static,
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+{
+    Response::new(Body::wrap_stream(stream))
+}
+
+/// A response streaming the chunks sent through the returned [`Sender`],
+/// e.g. from a spawned task. The response ends when the sender is dropped.
+#[must_use]
+pub fn channel() -> (Sender, Response<Body>) {
+    let (sender, body) = Body::channel();
+    (Sender(sender), Response::new(body))
+}
+
+/// Sends the chunks of a response body, see [`channel`].
+#[derive(Debug)]
+pub struct Sender(hyper::body::Sender);
+
+impl Sender {
+    /// Send a chunk, waiting until the previous one was taken.
+    ///
+    /// # Errors
+    ///
+    /// Fails once the client is gone.
+    pub async fn send(&mut self, chunk: impl Into<Bytes>) -> crate::Result<()> {
+        self.0.send_data(chunk.into()).await?;
+        Ok(())
+    }
+
+    /// Send `value` as a line of JSON, for `application/x-ndjson` bodies.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `value` can't be serialized, or once the client is gone.
+    pub async fn send_json<T: Serialize>(&mut self, value: &T) -> crate::Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+        self.send(line).await
+    }
+
+    /// End the response abruptly, so the client can tell it's incomplete.
+    pub fn abort(self) {
+        self.0.abort();
+    }
+}
@@ -0,0 +1,338 @@
+//! A radix tree over route patterns.
+//!
+//! Patterns are parsed by `routefinder` and stored as runs of literal bytes,
+//! separated by params and ended by an optional wildcard. Looking up a path
+//! walks the tree once and returns every pattern that matches it, leaving the
+//! choice between them to the [`Router`](crate::router::Router). The matching
+//! rules are the ones of `routefinder`:
+//!
+//! - leading and trailing slashes of both patterns and paths are ignored;
+//! - a param matches up to the next slash, or up to the next dot if the
+//!   pattern continues with a dot;
+//! - a wildcard matches the rest of the path, and `/*` also matches nothing.
+
+use routefinder::{RouteSpec, Segment};
+
+/// A pattern broken up into the pieces the tree is built from.
+#[derive(Debug, PartialEq, Eq)]
+enum Token {
+    Literal(Vec<u8>),
+    /// A param followed by a slash or the end of the pattern.
+    Param,
+    /// A param followed by a dot.
+    DottedParam,
+    /// A wildcard following a dot or standing alone.
+    Wildcard,
+    /// A slash followed by a wildcard, which matches an empty rest as well.
+    SlashWildcard,
+}
+
+fn tokenize(spec: &RouteSpec) -> Vec<Token> {
+    let segments = spec.segments();
+    let mut tokens = Vec::new();
+    let mut literal = Vec::new();
+    let mut i = 0;
+    while i < segments.len() {
+        let next = segments.get(i + 1);
+        let token = match (&segments[i], next) {
+            (Segment::Slash, Some(Segment::Wildcard)) => {
+                i += 1;
+                Some(Token::SlashWildcard)
+            }
+            (Segment::Slash, _) => {
+                literal.push(b'/');
+                None
+            }
+            (Segment::Dot, _) => {
+                literal.push(b'.');
+                None
+            }
+            (Segment::Exact(exact), _) => {
+                literal.extend_from_slice(exact.as_bytes());
+                None
+            }
+            (Segment::Param(_), Some(Segment::Dot)) => Some(Token::DottedParam),
+            (Segment::Param(_), _) => Some(Token::Param),
+            (Segment::Wildcard, _) => Some(Token::Wildcard),
+        };
+        if let Some(token) = token {
+            if !literal.is_empty() {
+                tokens.push(Token::Literal(std::mem::take(&mut literal)));
+            }
+            tokens.push(token);
+        }
+        i += 1;
+    }
+    if !literal.is_empty() {
+        tokens.push(Token::Literal(literal));
+    }
+    tokens
+}
+
+/// A radix tree mapping route patterns to values.
+#[derive(Debug, Clone)]
+pub(crate) struct Tree<T> {
+    root: Node<T>,
+}
+
+#[derive(Debug, Clone)]
+struct Node<T> {
+    /// The literal bytes leading from the parent to this node.
+    prefix: Vec<u8>,
+    /// Children reached through a literal, with distinct first bytes.
+    children: Vec<Node<T>>,
+    param: Option<Box<Node<T>>>,
+    dotted_param: Option<Box<Node<T>>>,
+    /// Values of patterns ending in a wildcard at this node.
+    wildcard: Vec<T>,
+    /// Values of patterns ending in `/*` at this node.
+    slash_wildcard: Vec<T>,
+    /// Values of patterns ending at this node.
+    values: Vec<T>,
+}
+
+/// A value whose pattern matches a path, with the captured params and
+/// wildcard in the order they appear in the pattern.
+#[derive(Debug)]
+pub(crate) struct Found<'a, 'p, T> {
+    pub(crate) value: &'a T,
+    pub(crate) captures: Vec<&'p str>,
+}
+
+impl<T> Default for Tree<T> {
+    fn default() -> Self {
+        Self {
+            root: Node::new(Vec::new()),
+        }
+    }
+}
+
+impl<T> Tree<T> {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn insert(&mut self, spec: &RouteSpec, value: T) {
+        self.root.insert(&tokenize(spec), value);
+    }
+
+    /// All values whose pattern matches `path`.
+    pub(crate) fn find<'a, 'p>(&'a self, path: &'p str) -> Vec<Found<'a, 'p, T>> {
+        let path = path.trim_start_matches('/').trim_end_matches('/');
+        let mut found = Vec::new();
+        self.root.find(path, &mut Vec::new(), &mut found);
+        found
+    }
+}
+
+impl<T> Node<T> {
+    fn new(prefix: Vec<u8>) -> Self {
+        Self {
+            prefix,
+            children: Vec::new(),
+            param: None,
+            dotted_param: None,
+            wildcard: Vec::new(),
+            slash_wildcard: Vec::new(),
+            values: Vec::new(),
+        }
+    }
+
+    fn insert(&mut self, tokens: &[Token], value: T) {
+        match tokens.split_first() {
+            None => self.values.push(value),
+            Some((Token::Literal(literal), rest)) => self.insert_literal(literal, rest, value),
+            Some((Token::Param, rest)) => self
+                .param
+                .get_or_insert_with(|| Box::new(Node::new(Vec::new())))
+                .insert(rest, value),
+            Some((Token::DottedParam, rest)) => self
+                .dotted_param
+                .get_or_insert_with(|| Box::new(Node::new(Vec::new())))
+                .insert(rest, value),
+            Some((Token::Wildcard, _)) => self.wildcard.push(value),
+            Some((Token::SlashWildcard, _)) => self.slash_wildcard.push(value),
+        }
+    }
+
+    fn insert_literal(&mut self, literal: &[u8], tokens: &[Token], value: T) {
+        let child = match self
+            .children
+            .iter_mut()
+            .position(|child| child.prefix[0] == literal[0])
+        {
+            Some(index) => &mut self.children[index],
+            None => {
+                self.children.push(Node::new(literal.to_vec()));
+                self.children.last_mut().unwrap()
+            }
+        };
+
+        let common = child
+            .prefix
+            .iter()
+            .zip(literal)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if common < child.prefix.len() {
+            child.split(common);
+        }
+        if common == literal.len() {
+            child.insert(tokens, value);
+        } else {
+            child.insert_literal(&literal[common..], tokens, value);
+        }
+    }
+
+    /// Move everything past the first `at` bytes of the prefix into a child.
+    fn split(&mut self, at: usize) {
+        let suffix = self.prefix.split_off(at);
+        let prefix = std::mem::take(&mut self.prefix);
+        let mut child = std::mem::replace(self, Node::new(prefix));
+        child.prefix = suffix;
+        self.children.push(child);
+    }
+
+    fn find<'a, 'p>(
+        &'a self,
+        rest: &'p str,
+        captures: &mut Vec<&'p str>,
+        found: &mut Vec<Found<'a, 'p, T>>,
+    ) {
+        let mut push = |values: &'a [T], captures: &[&'p str]| {
+            found.extend(values.iter().map(|value| Found {
+                value,
+                captures: captures.to_vec(),
+            }));
+        };
+
+        if rest.is_empty() {
+            push(&self.values, captures);
+        }
+
+        if !self.wildcard.is_empty() {
+            captures.push(rest);
+            push(&self.wildcard, captures);
+            captures.pop();
+        }
+
+        if !self.slash_wildcard.is_empty() {
+            let wildcard = match rest.strip_prefix('/') {
+                Some(wildcard) => Some(wildcard),
+                None if rest.is_empty() => Some(rest),
+                None => None,
+            };
+            if let Some(wildcard) = wildcard {
+                captures.push(wildcard);
+                push(&self.slash_wildcard, captures);
+                captures.pop();
+            }
+        }
+
+        if let Some(&first) = rest.as_bytes().first() {
+            if let Some(child) = self.children.iter().find(|child| child.prefix[0] == first) {
+                if rest.as_bytes().starts_with(&child.prefix) {
+                    child.find(&rest[child.prefix.len()..], captures, found);
+                }
+            }
+        }
+
+        if rest.is_empty() {
+            return;
+        }
+
+        if let Some(param) = &self.param {
+            let end = rest.find('/').unwrap_or(rest.len());
+            captures.push(&rest[..end]);
+            param.find(&rest[end..], captures, found);
+            captures.pop();
+        }
+
+        if let Some(param) = &self.dotted_param {
+            if let Some(end) = rest.find(['.', '/']) {
+                if rest.as_bytes()[end] == b'.' {
+                    captures.push(&rest[..end]);
+                    param.find(&rest[end..], captures, found);
+                    captures.pop();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tree(patterns: &[&str]) -> Tree<String> {
+        let mut tree = Tree::new();
+        for pattern in patterns {
+            tree.insert(&pattern.parse().unwrap(), (*pattern).to_owned());
+        }
+        tree
+    }
+
+    fn find(tree: &Tree<String>, path: &str) -> Vec<String> {
+        let mut found: Vec<String> = tree
+            .find(path)
+            .into_iter()
+            .map(|found| format!("{} {:?}", found.value, found.captures))
+            .collect();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn matches_like_routefinder() {
+        let patterns = [
+            "/",
+            "*",
+            "/users",
+            "/users/:id",
+            "/users/:id/posts",
+            "/user-groups/:group",
+            "/files/*",
+            "/files/:name.:ext",
+            "/files/readme.md",
+            "/:a/:b",
+        ];
+        let tree = tree(&patterns);
+        let paths = [
+            "/",
+            "/users",
+            "/users/",
+            "/users/42",
+            "/users/42/posts",
+            "/user-groups/admins",
+            "/files",
+            "/files/readme.md",
+            "/files/archive.tar.gz",
+            "/files/a/b",
+            "/usersx",
+            "//users//42",
+        ];
+
+        for path in paths {
+            let mut expected: Vec<String> = patterns
+                .iter()
+                .filter_map(|pattern| {
+                    let spec: RouteSpec = pattern.parse().unwrap();
+                    spec.matches(path)
+                        .map(|captures| format!("{} {:?}", pattern, captures))
+                })
+                .collect();
+            expected.sort();
+            assert_eq!(find(&tree, path), expected, "matching `{}`", path);
+        }
+    }
+
+    #[test]
+    fn splits_shared_prefixes() {
+        let tree = tree(&["/abc", "/abd", "/ab"]);
+        assert_eq!(tree.root.children.len(), 1);
+        assert_eq!(tree.root.children[0].prefix, b"ab");
+        assert_eq!(find(&tree, "/abd"), ["/abd []"]);
+        assert_eq!(find(&tree, "/ab"), ["/ab []"]);
+        assert!(find(&tree, "/a").is_empty());
+    }
+}
@@ -0,0 +1,213 @@
+//! API versioning.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use headers::HeaderMapExt;
+use hyper::header::{HeaderName, HeaderValue, LINK};
+use hyper::HeaderMap;
+
+use crate::middleware::Next;
+use crate::{Accept, Context, Middleware};
+
+/// A version of an API, and how requests select it.
+///
+/// Versions are added with [`Route::version`](crate::Route::version). A
+/// version is selected by a path prefix, a request header, or a parameter of
+/// the media types in the `Accept` header:
+///
+/// ```rust,no_run
+/// # use envoy_http as envoy;
+/// use envoy::{ApiVersion, Body, Response};
+///
+/// async fn users_v1(_: &mut envoy::Context) -> envoy::Result {
+///     Ok(Response::new(Body::from(r#"["ann"]"#)))
+/// }
+///
+/// async fn users_v2(_: &mut envoy::Context) -> envoy::Result {
+///     Ok(Response::new(Body::from(r#"{"users": ["ann"]}"#)))
+/// }
+///
+/// let mut app = envoy::Server::new();
+/// // `/v1/users`
+/// app.at("/").version(ApiVersion::path("v1"), |v1| {
+///     v1.at("/users").get(users_v1);
+/// });
+/// // `/users` with `Accept: application/json; version=2`
+/// app.at("/").version(ApiVersion::accept("version", "2"), |v2| {
+///     v2.at("/users").get(users_v2);
+/// });
+/// ```
+///
+/// A version selected by a header or the `Accept` header takes precedence
+/// over routes outside of any version, which serve requests that don't ask
+/// for a version. Responses at such paths list the header in `Vary`.
+///
+/// Responses of a [deprecated](ApiVersion::deprecated) version carry the
+/// `Deprecation`, `Sunset` and `Link` headers. Endpoints and middleware can
+/// find out which version handles the request through
+/// [`Context::meta`](crate::Context::meta).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiVersion {
+    name: String,
+    selector: Selector,
+    deprecation: Option<SystemTime>,
+    sunset: Option<SystemTime>,
+    successor: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Selector {
+    Path(String),
+    Header(String, String),
+    Accept(String, String),
+}
+
+impl ApiVersion {
+    fn new(name: &str, selector: Selector) -> Self {
+        Self {
+            name: name.to_owned(),
+            selector,
+            deprecation: None,
+            sunset: None,
+            successor: None,
+        }
+    }
+
+    /// A version whose routes are nested under the path `prefix`, e.g. `v1`.
+    #[must_use]
+    pub fn path(prefix: &str) -> Self {
+        Self::new(prefix.trim_matches('/'), Selector::Path(prefix.to_owned()))
+    }
+
+    /// A version selected by a request header, e.g. `Accept-Version: 2`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `name` isn't a valid header name, or `version` a valid
+    /// header value.
+    #[must_use]
+    pub fn header(name: &str, version: &str) -> Self {
+        if let Err(err) = HeaderName::from_bytes(name.as_bytes()) {
+            panic!("invalid header name {:?}: {}", name, err);
+        }
+        if let Err(err) = HeaderValue::from_str(version) {
+            panic!("invalid header value {:?}: {}", version, err);
+        }
+        Self::new(version, Selector::Header(name.to_owned(), version.to_owned()))
+    }
+
+    /// A version selected by a parameter of a media type in the `Accept`
+    /// header, e.g. `Accept: application/json; version=2`.
+    #[must_use]
+    pub fn accept(param: &str, version: &str) -> Self {
+        Self::new(version, Selector::Accept(param.to_owned(), version.to_owned()))
+    }
+
+    /// Mark the version as deprecated since the given time.
+    ///
+    /// Responses carry a `Deprecation` header with the time, as described in
+    /// RFC 9745.
+    #[must_use]
+    pub fn deprecated(mut self, since: SystemTime) -> Self {
+        self.deprecation = Some(since);
+        self
+    }
+
+    /// Announce when the version stops being served, in the `Sunset` header
+    /// described in RFC 8594.
+    #[must_use]
+    pub fn sunset(mut self, at: SystemTime) -> Self {
+        self.sunset = Some(at);
+        self
+    }
+
+    /// Point clients to the version replacing this one, in a
+    /// `Link: <url>; rel="successor-version"` header on its responses.
+    #[must_use]
+    pub fn successor(mut self, url: &str) -> Self {
+        self.successor = Some(url.to_owned());
+        self
+    }
+
+    /// The name of the version, e.g. `v1` or `2`.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the version is deprecated or has a sunset announced.
+    #[must_use]
+    pub fn is_deprecated(&self) -> bool {
+        self.deprecation.is_some() || self.sunset.is_some()
+    }
+
+    pub(crate) fn selector(&self) -> &Selector {
+        &self.selector
+    }
+
+    /// Whether responses of the version carry any headers.
+    pub(crate) fn has_headers(&self) -> bool {
+        self.is_deprecated() || self.successor.is_some()
+    }
+
+    /// Add the deprecation headers to a response of this version.
+    fn annotate(&self, headers: &mut HeaderMap) {
+        if let Some(since) = self.deprecation {
+            let seconds = since.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+            let value = HeaderValue::from_str(&format!("@{}", seconds)).unwrap();
+            headers.insert("deprecation", value);
+        }
+        if let Some(sunset) = self.sunset {
+            let date = httpdate::fmt_http_date(sunset);
+            headers.insert("sunset", HeaderValue::from_str(&date).unwrap());
+        }
+        if let Some(successor) = &self.successor {
+            let link = format!("<{}>; rel=\"successor-version\"", successor);
+            if let Ok(link) = HeaderValue::from_str(&link) {
+                headers.append(LINK, link);
+            }
+        }
+    }
+}
+
+/// Adds the deprecation headers of a version to its responses, including
+/// error responses.
+#[derive(Debug)]
+pub(crate) struct Deprecation;
+
+#[async_trait::async_trait]
+impl Middleware for Deprecation {
+    async fn handle(&self, ctx: &mut Context, next: Next) -> crate::Result {
+        if let Some(version) = ctx.meta::<ApiVersion>().cloned() {
+            version.annotate(ctx.response_headers_mut());
+        }
+        next.run(ctx).await
+    }
+}
+
+/// Whether a media type in the `Accept` header has the parameter `name` set
+/// to `value`.
+pub(crate) fn accepts_param(headers: &HeaderMap, name: &str, value: &str) -> bool {
+    headers
+        .typed_get::<Accept>()
+        .is_some_and(|accept| accept.iter().any(|range| range.param(name) == Some(value)))
+}
+
+#[cfg(test)]
+mod test {
+    use hyper::header::ACCEPT;
+
+    use super::*;
+
+    #[test]
+    fn matches_accept_params() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT,
+            HeaderValue::from_static("text/html, application/vnd.api+json; Version=\"2\"; q=0.9"),
+        );
+        assert!(accepts_param(&headers, "version", "2"));
+        assert!(!accepts_param(&headers, "version", "1"));
+        assert!(!accepts_param(&headers, "q", "1"));
+    }
+}
@@ -0,0 +1,509 @@
+//! WebSocket connections.
+//!
+//! A [`WebSocket`] endpoint upgrades HTTP/1.1 requests to WebSocket
+//! connections, then runs its handler in its own task with the [`Context`] of
+//! the request, so params, state and headers are still available:
+//!
+//! ```rust,no_run
+//! # use envoy_http as envoy;
+//! use envoy::websocket::{Message, WebSocket, WebSocketConnection};
+//! use futures_util::{SinkExt, StreamExt};
+//!
+//! async fn echo(ctx: envoy::Context, conn: WebSocketConnection) -> envoy::Result<()> {
+//!     let room = ctx.param("room")?.to_owned();
+//!     let (mut sender, mut receiver) = conn.split();
+//!     while let Some(message) = receiver.next().await {
+//!         match message? {
+//!             Message::Text(text) => sender.send(Message::Text(format!("{}: {}", room, text))).await?,
+//!             Message::Binary(data) => sender.send(Message::Binary(data)).await?,
+//!             _ => {}
+//!         }
+//!     }
+//!     Ok(())
+//! }
+//!
+//! let mut app = envoy::Server::new();
+//! app.at("/rooms/:room").get(
+//!     WebSocket::new(echo)
+//!         .protocols(["chat"])
+//!         .allowed_origins(["https://example.com"])
+//!         .max_message_size(64 * 1024),
+//! );
+//! ```
+//!
+//! Messages are compressed with the `permessage-deflate` extension when the
+//! client offers it, unless it's turned off with
+//! [`WebSocket::without_deflate`]. Pings from the client are answered
+//! automatically.
+
+use std::convert::TryFrom;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context as TaskContext, Poll};
+
+use async_trait::async_trait;
+use futures_util::{stream, Sink, Stream};
+use headers::{HeaderMapExt, SecWebsocketAccept, SecWebsocketKey};
+use hyper::header::{
+    HeaderValue, CONNECTION, ORIGIN, SEC_WEBSOCKET_EXTENSIONS, SEC_WEBSOCKET_KEY, SEC_WEBSOCKET_PROTOCOL,
+    SEC_WEBSOCKET_VERSION, UPGRADE,
+};
+use hyper::http::Extensions;
+use hyper::upgrade::Upgraded;
+use hyper::{Body, HeaderMap, Method, Request, Response, StatusCode, Version};
+use soketto::connection::{self, Builder, CloseReason, Mode};
+use soketto::data::{ByteSlice125, Incoming};
+use soketto::extension::deflate::Deflate;
+use soketto::extension::{Extension, Param};
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+use crate::{Context, Endpoint, Error};
+
+type Socket = Compat<Upgraded>;
+
+/// An endpoint upgrading requests to WebSocket connections.
+pub struct WebSocket<F> {
+    handler: Arc<F>,
+    protocols: Vec<String>,
+    origins: Option<Vec<String>>,
+    deflate: bool,
+    max_frame_size: usize,
+    max_message_size: usize,
+}
+
+impl<F, Fut> WebSocket<F>
+where
+    F: Fn(Context, WebSocketConnection) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    /// An endpoint running `handler` for every connection.
+    pub fn new(handler: F) -> Self {
+        Self {
+            handler: Arc::new(handler),
+            protocols: Vec::new(),
+            origins: None,
+            deflate: true,
+            max_frame_size: 16 * 1024 * 1024,
+            max_message_size: 64 * 1024 * 1024,
+        }
+    }
+}
+
+impl<F> WebSocket<F> {
+    /// The subprotocols the endpoint speaks, by preference. The first one the
+    /// client also offers is picked, see [`WebSocketConnection::protocol`].
+    #[must_use]
+    pub fn protocols<I>(mut self, protocols: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.protocols = protocols.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Reject requests with `403 Forbidden` unless their `Origin` is one of
+    /// `origins`, e.g. `https://example.com`. Browsers send the origin of the
+    /// page opening the connection, so this keeps other sites from using
+    /// the cookies of a user. Any origin is allowed by default.
+    #[must_use]
+    pub fn allowed_origins<I>(mut self, origins: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Into<String>,
+    {
+        self.origins = Some(origins.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// Don't compress messages, even if the client offers
+    /// `permessage-deflate`.
+    #[must_use]
+    pub fn without_deflate(mut self) -> Self {
+        self.deflate = false;
+        self
+    }
+
+    /// Close the connection when the client sends a frame larger than
+    /// `size` bytes. Defaults to 16 MiB.
+    #[must_use]
+    pub fn max_frame_size(mut self, size: usize) -> Self {
+        self.max_frame_size = size;
+        self
+    }
+
+    /// Close the connection when the client sends a message larger than
+    /// `size` bytes, across all its frames. Defaults to 64 MiB.
+    #[must_use]
+    pub fn max_message_size(mut self, size: usize) -> Self {
+        self.max_message_size = size;
+        self
+    }
+
+    /// Check the handshake request, returning the accept key.
+    fn validate(&self, ctx: &mut Context) -> crate::Result<SecWebsocketAccept> {
+        let headers = ctx.try_borrow::<HeaderMap>().cloned().unwrap_or_default();
+        let upgrade = ctx.try_borrow::<Method>() == Some(&Method::GET)
+            && ctx.try_borrow::<Version>() == Some(&Version::HTTP_11)
+            && is_upgrade(&headers);
+        let supported = headers.get(SEC_WEBSOCKET_VERSION).map(HeaderValue::as_bytes) == Some(b"13");
+        if !upgrade || !supported {
+            let staged = ctx.response_headers_mut();
+            staged.insert(UPGRADE, HeaderValue::from_static("websocket"));
+            staged.insert(SEC_WEBSOCKET_VERSION, HeaderValue::from_static("13"));
+            let msg = if upgrade {
+                "Unsupported WebSocket version"
+            } else {
+                "Expected a WebSocket upgrade request"
+            };
+            return Err(Error::from_str(StatusCode::UPGRADE_REQUIRED, msg));
+        }
+
+        let key = headers
+            .get(SEC_WEBSOCKET_KEY)
+            .filter(|key| is_valid_key(key.as_bytes()))
+            .and_then(|_| headers.typed_get::<SecWebsocketKey>())
+            .ok_or_else(|| {
+                Error::from_str(StatusCode::BAD_REQUEST, "Invalid `Sec-WebSocket-Key` header")
+            })?;
+
+        if let Some(origins) = &self.origins {
+            let origin = headers.get(ORIGIN).and_then(|value| value.to_str().ok());
+            let allowed = |origin: &str| origins.iter().any(|allowed| allowed.eq_ignore_ascii_case(origin));
+            if !origin.is_some_and(allowed) {
+                return Err(Error::from_str(StatusCode::FORBIDDEN, "Origin not allowed"));
+            }
+        }
+
+        Ok(SecWebsocketAccept::from(key))
+    }
+
+    /// The first of our protocols the client offers.
+    fn protocol(&self, headers: Option<&HeaderMap>) -> Option<String> {
+        let offered: Vec<&str> = headers
+            .into_iter()
+            .flat_map(|headers| headers.get_all(SEC_WEBSOCKET_PROTOCOL))
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+            .collect();
+        self.protocols
+            .iter()
+            .find(|protocol| offered.contains(&protocol.as_str()))
+            .cloned()
+    }
+
+    /// Accept the first `permessage-deflate` offer of the client we can
+    /// handle, returning the extension and the response to the offer.
+    fn deflate(&self, headers: Option<&HeaderMap>) -> Option<(Deflate, String)> {
+        if !self.deflate {
+            return None;
+        }
+        let offers = headers
+            .into_iter()
+            .flat_map(|headers| headers.get_all(SEC_WEBSOCKET_EXTENSIONS))
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for offer in offers {
+            let mut parts = offer.split(';').map(str::trim);
+            if parts.next() != Some("permessage-deflate") {
+                continue;
+            }
+            let params: Vec<Param<'_>> = parts
+                .filter(|part| !part.is_empty())
+                .map(|part| {
+                    let (name, value) = match part.split_once('=') {
+                        Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                        None => (part, None),
+                    };
+                    let mut param = Param::new(name);
+                    param.set_value(value);
+                    param
+                })
+                .collect();
+
+            let mut deflate = Deflate::new(Mode::Server);
+            if deflate.configure(&params).is_ok() && deflate.is_enabled() {
+                let mut response = String::from("permessage-deflate");
+                for param in deflate.params() {
+                    response.push_str("; ");
+                    response.push_str(param.name());
+                    if let Some(value) = param.value() {
+                        response.push('=');
+                        response.push_str(value);
+                    }
+                }
+                return Some((deflate, response));
+            }
+        }
+        None
+    }
+}
+
+/// Whether the request asks to upgrade the connection to a WebSocket.
+fn is_upgrade(headers: &HeaderMap) -> bool {
+    let has_token = |name, token: &str| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case(token))
+    };
+    has_token(CONNECTION, "upgrade") && has_token(UPGRADE, "websocket")
+}
+
+/// Whether `key` is 16 bytes, base64 encoded.
+fn is_valid_key(key: &[u8]) -> bool {
+    key.len() == 24
+        && key.ends_with(b"==")
+        && key[..22]
+            .iter()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'+' | b'/'))
+}
+
+impl<F> Debug for WebSocket<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("protocols", &self.protocols)
+            .field("origins", &self.origins)
+            .field("deflate", &self.deflate)
+            .field("max_frame_size", &self.max_frame_size)
+            .field("max_message_size", &self.max_message_size)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl<F, Fut> Endpoint for WebSocket<F>
+where
+    F: Fn(Context, WebSocketConnection) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = crate::Result<()>> + Send + 'static,
+{
+    async fn call(&self, ctx: &mut Context) -> crate::Result {
+        let accept = self.validate(ctx)?;
+        let protocol = self.protocol(ctx.try_borrow::<HeaderMap>());
+        let deflate = self.deflate(ctx.try_borrow::<HeaderMap>());
+
+        // The upgrade is a request extension, so pull it out of the
+        // extensions of the context.
+        let mut upgrade_req = Request::new(());
+        *upgrade_req.extensions_mut() = ctx.try_take::<Extensions>().unwrap_or_default();
+        let on_upgrade = hyper::upgrade::on(&mut upgrade_req);
+        ctx.insert(std::mem::take(upgrade_req.extensions_mut()));
+
+        let mut res = Response::new(Body::empty());
+        *res.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+        let headers = res.headers_mut();
+        headers.insert(CONNECTION, HeaderValue::from_static("upgrade"));
+        headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+        headers.typed_insert(accept);
+        if let Some(protocol) = protocol.as_deref().and_then(|protocol| HeaderValue::from_str(protocol).ok()) {
+            headers.insert(SEC_WEBSOCKET_PROTOCOL, protocol);
+        }
+        if let Some((_, response)) = &deflate {
+            if let Ok(response) = HeaderValue::from_str(response) {
+                headers.insert(SEC_WEBSOCKET_EXTENSIONS, response);
+            }
+        }
+        ctx.apply_response_headers(&mut res);
+        ctx.apply_cookies(&mut res);
+
+        let handler = self.handler.clone();
+        let (max_frame_size, max_message_size) = (self.max_frame_size, self.max_message_size);
+        let ctx = ctx.take_all();
+        tokio::spawn(async move {
+            let upgraded = match on_upgrade.await {
+                Ok(upgraded) => upgraded,
+                Err(err) => {
+                    tracing::error!("WebSocket upgrade failed: {}", err);
+                    return;
+                }
+            };
+            let mut builder = Builder::new(upgraded.compat(), Mode::Server);
+            builder.set_max_frame_size(max_frame_size);
+            builder.set_max_message_size(max_message_size);
+            if let Some((deflate, _)) = deflate {
+                builder.add_extensions(Some(Box::new(deflate) as Box<dyn Extension + Send>));
+            }
+            let (sender, receiver) = builder.finish();
+            let conn = WebSocketConnection {
+                sender: WsSender::new(sender),
+                receiver: WsReceiver::new(receiver),
+                protocol,
+            };
+            if let Err(err) = handler(ctx, conn).await {
+                tracing::error!("WebSocket handler failed: {}", err);
+            }
+        });
+        Ok(res)
+    }
+}
+
+/// A message sent or received over a WebSocket connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    /// A text message.
+    Text(String),
+    /// A binary message.
+    Binary(Vec<u8>),
+    /// A ping, of at most 125 bytes. Only sent, as pings from the client
+    /// are answered automatically.
+    Ping(Vec<u8>),
+    /// A pong, of at most 125 bytes.
+    Pong(Vec<u8>),
+    /// The connection is closing, with the frame the client sent if any.
+    ///
+    /// Only `Close(None)` can be sent, which closes the connection with code
+    /// 1000, "normal closure": sending a close frame with a code and reason
+    /// is an error.
+    Close(Option<CloseFrame>),
+}
+
+/// The code and reason the client closed the connection with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFrame {
+    /// The status code, e.g. 1001 when the client is going away.
+    pub code: u16,
+    /// The reason, possibly empty.
+    pub reason: String,
+}
+
+/// A WebSocket connection, handed to the handler of a [`WebSocket`]
+/// endpoint.
+#[derive(Debug)]
+pub struct WebSocketConnection {
+    sender: WsSender,
+    receiver: WsReceiver,
+    protocol: Option<String>,
+}
+
+impl WebSocketConnection {
+    /// The subprotocol agreed on with the client, if any.
+    #[must_use]
+    pub fn protocol(&self) -> Option<&str> {
+        self.protocol.as_deref()
+    }
+
+    /// Split the connection into a sink of messages to send and a stream of
+    /// the messages received, to use them from different tasks.
+    #[must_use]
+    pub fn split(self) -> (WsSender, WsReceiver) {
+        (self.sender, self.receiver)
+    }
+}
+
+type BoxSink = Pin<Box<dyn Sink<Message, Error = Error> + Send>>;
+type BoxStream = Pin<Box<dyn Stream<Item = crate::Result<Message>> + Send>>;
+
+/// The sending half of a WebSocket connection, a `Sink` of [`Message`]s.
+pub struct WsSender(BoxSink);
+
+impl WsSender {
+    fn new(sender: connection::Sender<Socket>) -> Self {
+        Self(Box::pin(futures_util::sink::unfold(sender, |mut sender, message| async move {
+            send(&mut sender, message).await?;
+            Ok::<_, Error>(sender)
+        })))
+    }
+}
+
+async fn send(sender: &mut connection::Sender<Socket>, message: Message) -> crate::Result<()> {
+    let too_long = |_| {
+        Error::from_str(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Pings and pongs are limited to 125 bytes",
+        )
+    };
+    match message {
+        Message::Text(text) => sender.send_text_owned(text).await?,
+        Message::Binary(data) => sender.send_binary_mut(data).await?,
+        Message::Ping(data) => {
+            sender.send_ping(ByteSlice125::try_from(&data[..]).map_err(too_long)?).await?;
+        }
+        Message::Pong(data) => {
+            sender.send_pong(ByteSlice125::try_from(&data[..]).map_err(too_long)?).await?;
+        }
+        Message::Close(None) => return Ok(sender.close().await?),
+        Message::Close(Some(_)) => {
+            return Err(Error::from_str(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Close codes and reasons can't be sent, send Message::Close(None) instead",
+            ))
+        }
+    }
+    sender.flush().await?;
+    Ok(())
+}
+
+impl Sink<Message> for WsSender {
+    type Error = Error;
+
+    fn poll_ready(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Result<(), Error>> {
+        self.0.as_mut().poll_ready(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, message: Message) -> Result<(), Error> {
+        self.0.as_mut().start_send(message)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Result<(), Error>> {
+        self.0.as_mut().poll_flush(cx)
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Result<(), Error>> {
+        self.0.as_mut().poll_close(cx)
+    }
+}
+
+impl Debug for WsSender {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsSender").finish()
+    }
+}
+
+/// The receiving half of a WebSocket connection, a `Stream` of
+/// [`Message`]s. It ends once the connection is closed.
+pub struct WsReceiver(BoxStream);
+
+impl WsReceiver {
+    fn new(receiver: connection::Receiver<Socket>) -> Self {
+        Self(Box::pin(stream::unfold(Some(receiver), |receiver| async move {
+            let mut receiver = receiver?;
+            let mut data = Vec::new();
+            let message = match receiver.receive(&mut data).await {
+                Ok(Incoming::Data(incoming)) if incoming.is_text() => String::from_utf8(data)
+                    .map(Message::Text)
+                    .map_err(|err| Error::new(StatusCode::BAD_REQUEST, err)),
+                Ok(Incoming::Data(_)) => Ok(Message::Binary(data)),
+                Ok(Incoming::Pong(data)) => Ok(Message::Pong(data.to_vec())),
+                Ok(Incoming::Closed(CloseReason { code, descr })) => {
+                    let frame = CloseFrame {
+                        code,
+                        reason: descr.unwrap_or_default(),
+                    };
+                    return Some((Ok(Message::Close(Some(frame))), None));
+                }
+                Err(connection::Error::Closed) => return None,
+                Err(err) => return Some((Err(Error::from(err)), None)),
+            };
+            Some((message, Some(receiver)))
+        })))
+    }
+}
+
+impl Stream for WsReceiver {
+    type Item = crate::Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+impl Debug for WsReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("WsReceiver").finish()
+    }
+}
@@ -0,0 +1,130 @@
+use envoy_http as envoy;
+
+use envoy::channels::{Channels, LagPolicy, RecvError};
+use envoy::sse::{self, SseSender};
+use envoy::{Body, Method, Request, Response};
+use futures_util::StreamExt;
+use hyper::body::HttpBody;
+
+#[tokio::test]
+async fn broadcasts_to_every_subscriber() {
+    let channels = Channels::<String>::new();
+    let mut first = channels.subscribe("news");
+    let mut second = channels.subscribe("news");
+    let mut other = channels.subscribe("sports");
+    assert_eq!(channels.presence("news"), 2);
+
+    assert_eq!(channels.topic("news").publish("hello".to_owned()), 2);
+    assert_eq!(first.recv().await, Ok("hello".to_owned()));
+    assert_eq!(second.recv().await, Ok("hello".to_owned()));
+    assert_eq!(other.try_recv(), Ok(None));
+
+    drop(second);
+    assert_eq!(channels.presence("news"), 1);
+    assert_eq!(channels.topic("news").publish("again".to_owned()), 1);
+    assert_eq!(channels.presence("weather"), 0);
+
+    let mut topics = channels.topics();
+    topics.sort();
+    assert_eq!(topics, ["news", "sports"]);
+
+    channels.topic("news").publish("last".to_owned());
+    assert!(channels.remove("news"));
+    assert_eq!(first.recv().await, Ok("again".to_owned()));
+    assert_eq!(first.recv().await, Ok("last".to_owned()));
+    assert_eq!(first.recv().await, Err(RecvError::Closed));
+    assert!(!channels.remove("news"));
+}
+
+#[tokio::test]
+async fn removes_topics_without_subscribers() {
+    let channels = Channels::<String>::new().capacity(1);
+    assert_eq!(channels.topic("news").publish("nobody".to_owned()), 0);
+    assert!(channels.topics().is_empty());
+
+    let topic = channels.topic("news");
+    let first = topic.subscribe();
+    let second = topic.subscribe();
+    drop(first);
+    assert_eq!(channels.topics(), ["news"]);
+    drop(second);
+    assert!(channels.topics().is_empty());
+
+    // Handles outlive the topic, and recreate it on subscription.
+    let mut third = topic.subscribe();
+    assert_eq!(topic.publish("again".to_owned()), 1);
+    assert_eq!(third.recv().await, Ok("again".to_owned()));
+
+    // Subscribers disconnected for lagging leave too.
+    let _lagging = topic.subscribe_with(1, LagPolicy::Disconnect);
+    drop(third);
+    topic.publish("one".to_owned());
+    assert_eq!(topic.publish("two".to_owned()), 0);
+    assert!(channels.topics().is_empty());
+}
+
+#[tokio::test]
+async fn applies_lag_policies() {
+    let channels = Channels::<u32>::new().capacity(2);
+    let topic = channels.topic("ticks");
+    let mut dropping = topic.subscribe();
+    let mut disconnected = topic.subscribe_with(2, LagPolicy::Disconnect);
+    let mut stream = topic.subscribe();
+
+    for tick in 1..=5 {
+        topic.publish(tick);
+    }
+    assert_eq!(topic.subscriber_count(), 2);
+
+    assert_eq!(dropping.recv().await, Err(RecvError::Lagged(3)));
+    assert_eq!(dropping.recv().await, Ok(4));
+    assert_eq!(dropping.recv().await, Ok(5));
+
+    assert_eq!(disconnected.recv().await, Ok(1));
+    assert_eq!(disconnected.recv().await, Ok(2));
+    assert_eq!(disconnected.recv().await, Err(RecvError::Closed));
+
+    // As a stream, lagging is skipped over.
+    assert_eq!(stream.next().await, Some(4));
+    assert_eq!(stream.next().await, Some(5));
+}
+
+async fn feed(ctx: envoy::Context, sender: SseSender) -> envoy::Result<()> {
+    let mut feed = ctx.subscribe::<String>("feed");
+    sender.send_comment("subscribed").await?;
+    while let Ok(item) = feed.recv().await {
+        sender.send_data(item).await?;
+    }
+    Ok(())
+}
+
+async fn publish(ctx: &mut envoy::Context) -> envoy::Result {
+    let channels = ctx.channels::<String>();
+    let sent = channels.topic("feed").publish("news".to_owned());
+    Ok(Response::new(Body::from(sent.to_string())))
+}
+
+#[tokio::test]
+async fn subscribes_from_handlers() {
+    let channels = Channels::<String>::new();
+    let mut app = envoy::new();
+    app.channels(channels.clone());
+    app.at("/feed").get(sse::endpoint(feed).without_keep_alive()).post(publish);
+
+    let req = Request::builder().uri("/feed").body(Body::empty()).unwrap();
+    let res: Response<Body> = app.clone().respond(req).await.unwrap();
+    let mut body = res.into_body();
+    assert_eq!(body.data().await.unwrap().unwrap(), ": subscribed\n\n");
+    assert_eq!(channels.presence("feed"), 1);
+
+    let req = Request::builder().method(Method::POST).uri("/feed").body(Body::empty()).unwrap();
+    let res: Response<Body> = app.respond(req).await.unwrap();
+    assert_eq!(hyper::body::to_bytes(res.into_body()).await.unwrap(), "1");
+    assert_eq!(body.data().await.unwrap().unwrap(), "data: news\n\n");
+
+    // The subscription ends with the connection.
+    drop(body);
+    channels.topic("feed").publish("gone".to_owned());
+    tokio::task::yield_now().await;
+    assert_eq!(channels.presence("feed"), 0);
+}
@@ -0,0 +1,45 @@
+//! Helpers shared by the integration tests.
+
+// Every test crate compiles its own copy, and uses only some of them.
+#![allow(dead_code)]
+
+use envoy_http as envoy;
+
+use envoy::{Body, Method, Request, Response};
+use hyper::body;
+
+/// An endpoint answering with `body`.
+pub fn text(body: &'static str) -> impl envoy::Endpoint {
+    move |_: &mut envoy::Context| async move { Ok(Response::new(Body::from(body))) }
+}
+
+/// A request with an empty body.
+pub fn request(method: Method, uri: &str, headers: &[(&str, &str)]) -> Request<Body> {
+    let mut req = Request::builder().method(method).uri(uri);
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    req.body(Body::empty()).unwrap()
+}
+
+/// A `GET` request without headers.
+pub fn get(uri: &str) -> Request<Body> {
+    request(Method::GET, uri, &[])
+}
+
+/// Send `req` to `app`, leaving the body of the response unread.
+pub async fn respond(app: &envoy::Server, req: Request<Body>) -> Response<Body> {
+    app.clone().respond(req).await.unwrap()
+}
+
+/// Send `req` to `app`, reading the body of the response.
+pub async fn send_bytes(app: &envoy::Server, req: Request<Body>) -> Response<Vec<u8>> {
+    let (parts, body) = respond(app, req).await.into_parts();
+    let body = body::to_bytes(body).await.unwrap();
+    Response::from_parts(parts, body.to_vec())
+}
+
+/// Send `req` to `app`, reading the body of the response as text.
+pub async fn send(app: &envoy::Server, req: Request<Body>) -> Response<String> {
+    send_bytes(app, req).await.map(|body| String::from_utf8(body).unwrap())
+}
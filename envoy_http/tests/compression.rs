@@ -0,0 +1,127 @@
+mod common;
+
+use std::time::Duration;
+
+use envoy_http as envoy;
+
+use async_compression::tokio::bufread::{BrotliDecoder, GzipDecoder, ZlibDecoder, ZstdDecoder};
+use common::{request, respond, send_bytes};
+use envoy::compression::{Compression, Encoding};
+use envoy::{stream, Body, Method, Response};
+use hyper::body::HttpBody;
+use tokio::io::AsyncReadExt;
+
+const TEXT: &str = "All work and no play makes Jack a dull boy. ";
+
+async fn text(ctx: &mut envoy::Context) -> envoy::Result {
+    let times: usize = ctx.param("times")?.parse()?;
+    let mut res = Response::new(Body::from(TEXT.repeat(times)));
+    res.headers_mut().insert("content-type", "text/plain".parse().unwrap());
+    res.headers_mut().insert("content-length", (TEXT.len() * times).to_string().parse().unwrap());
+    res.headers_mut().insert("etag", "\"v1\"".parse().unwrap());
+    res.headers_mut().insert("vary", "origin".parse().unwrap());
+    Ok(res)
+}
+
+async fn image(_: &mut envoy::Context) -> envoy::Result {
+    let mut res = Response::new(Body::from(vec![0; 4096]));
+    res.headers_mut().insert("content-type", "image/png".parse().unwrap());
+    Ok(res)
+}
+
+async fn decode(coding: &str, body: &[u8]) -> String {
+    let mut decoded = String::new();
+    match coding {
+        "br" => BrotliDecoder::new(body).read_to_string(&mut decoded).await,
+        "zstd" => ZstdDecoder::new(body).read_to_string(&mut decoded).await,
+        "gzip" => GzipDecoder::new(body).read_to_string(&mut decoded).await,
+        "deflate" => ZlibDecoder::new(body).read_to_string(&mut decoded).await,
+        other => panic!("unexpected coding {}", other),
+    }
+    .unwrap();
+    decoded
+}
+
+fn app(compression: Compression) -> envoy::Server {
+    let mut app = envoy::new();
+    app.with(compression);
+    app.at("/text/:times").get(text);
+    app.at("/image").get(image);
+    app
+}
+
+#[tokio::test]
+async fn negotiates_encodings() {
+    let app = app(Compression::new());
+    let cases = [
+        ("gzip, deflate, br, zstd", "br"),
+        ("gzip;q=1, br;q=0.5", "gzip"),
+        ("deflate", "deflate"),
+        ("zstd, gzip;q=0.9", "zstd"),
+        ("*", "br"),
+    ];
+    for (accept_encoding, coding) in cases {
+        let res = send_bytes(&app, request(Method::GET, "/text/100", &[("accept-encoding", accept_encoding)])).await;
+        assert_eq!(res.headers()["content-encoding"], coding, "{}", accept_encoding);
+        assert!(res.headers().get("content-length").is_none());
+        assert_eq!(res.headers()["etag"], "W/\"v1\"");
+        let vary: Vec<_> = res.headers().get_all("vary").iter().collect();
+        assert_eq!(vary, ["origin", "accept-encoding"]);
+        assert!(res.body().len() < TEXT.len() * 100);
+        assert_eq!(decode(coding, res.body()).await, TEXT.repeat(100));
+    }
+
+    for accept_encoding in [None, Some("identity"), Some("compress")] {
+        let headers: Vec<_> = accept_encoding.map(|coding| ("accept-encoding", coding)).into_iter().collect();
+        let res = send_bytes(&app, request(Method::GET, "/text/100", &headers)).await;
+        assert!(res.headers().get("content-encoding").is_none(), "{:?}", accept_encoding);
+        assert_eq!(res.headers()["etag"], "\"v1\"");
+        assert_eq!(res.body().len(), TEXT.len() * 100);
+    }
+
+    let app = self::app(Compression::new().encodings([Encoding::Gzip]));
+    let res = send_bytes(&app, request(Method::GET, "/text/100", &[("accept-encoding", "br, gzip;q=0.1")])).await;
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+}
+
+#[tokio::test]
+async fn skips_small_and_compressed_bodies() {
+    let app = app(Compression::new().threshold(100));
+    let res = send_bytes(&app, request(Method::GET, "/text/2", &[("accept-encoding", "gzip")])).await;
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.headers()["content-length"], (TEXT.len() * 2).to_string().as_str());
+    let res = send_bytes(&app, request(Method::GET, "/text/3", &[("accept-encoding", "gzip")])).await;
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+
+    let res = send_bytes(&app, request(Method::GET, "/image", &[("accept-encoding", "gzip")])).await;
+    assert!(res.headers().get("content-encoding").is_none());
+    assert!(res.headers().get("vary").is_none());
+    assert_eq!(res.body().len(), 4096);
+}
+
+#[tokio::test]
+async fn compresses_streams_incrementally() {
+    let mut app = envoy::new();
+    app.with(Compression::new());
+    app.at("/").get(|_: &mut envoy::Context| async {
+        let (mut sender, res) = stream::channel();
+        tokio::spawn(async move {
+            sender.send(TEXT).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            sender.send(TEXT).await.unwrap();
+        });
+        Ok(res)
+    });
+
+    let res = respond(&app, request(Method::GET, "/", &[("accept-encoding", "gzip")])).await;
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    let mut body = res.into_body();
+    let mut compressed = Vec::new();
+    // The first chunk is sent before the rest of the body is produced.
+    let first = tokio::time::timeout(Duration::from_millis(40), body.data()).await;
+    compressed.extend_from_slice(&first.expect("first chunk was buffered").unwrap().unwrap());
+    while let Some(chunk) = body.data().await {
+        compressed.extend_from_slice(&chunk.unwrap());
+    }
+    assert_eq!(decode("gzip", &compressed).await, TEXT.repeat(2));
+}
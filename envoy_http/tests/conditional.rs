@@ -0,0 +1,139 @@
+mod common;
+
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use envoy_http as envoy;
+
+use common::{get, request, send};
+use envoy::conditional::{Conditional, Validators};
+use envoy::{Body, Method, Response, StatusCode};
+
+#[tokio::test]
+async fn computes_etags_of_buffered_bodies() {
+    let mut app = envoy::new();
+    app.with(Conditional::new().max_size(16));
+    app.at("/small").get(|_: &mut envoy::Context| async { Ok(Response::new(Body::from("Hello, world!"))) });
+    app.at("/large").get(|_: &mut envoy::Context| async {
+        Ok(Response::new(Body::from("Hello, world! Hello, world!")))
+    });
+
+    let res = send(&app, get("/small")).await;
+    let etag = res.headers()["etag"].to_str().unwrap().to_owned();
+    assert!(etag.starts_with("\"d-"), "{}", etag);
+    assert_eq!(res.body(), "Hello, world!");
+
+    let res = send(&app, request(Method::GET, "/small", &[("if-none-match", &etag)])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()["etag"], etag.as_str());
+    assert!(res.headers().get("content-length").is_none());
+    assert_eq!(res.body(), "");
+
+    // `If-None-Match` uses the weak comparison.
+    let weak = format!("\"other\", W/{}", etag);
+    let res = send(&app, request(Method::GET, "/small", &[("if-none-match", &weak)])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    let res = send(&app, request(Method::GET, "/small", &[("if-match", "\"other\"")])).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+    let res = send(&app, get("/large")).await;
+    assert!(res.headers().get("etag").is_none());
+    assert_eq!(res.body(), "Hello, world! Hello, world!");
+
+    let mut app = envoy::new();
+    app.with(Conditional::new().weak());
+    app.at("/").get(|_: &mut envoy::Context| async { Ok(Response::new(Body::from("Hello, world!"))) });
+    let res = send(&app, get("/")).await;
+    assert_eq!(res.headers()["etag"], format!("W/{}", etag).as_str());
+}
+
+#[tokio::test]
+async fn computes_etags_for_head_requests() {
+    let mut app = envoy::new();
+    app.with(Conditional::new());
+    app.at("/page").get(|_: &mut envoy::Context| async { Ok(Response::new(Body::from("Hello, world!"))) });
+    app.at("/empty").head(|_: &mut envoy::Context| async { Ok(Response::new(Body::empty())) });
+
+    let etag = send(&app, get("/page")).await.headers()["etag"].to_str().unwrap().to_owned();
+    let res = send(&app, request(Method::HEAD, "/page", &[])).await;
+    assert_eq!(res.headers()["etag"], etag.as_str());
+    let res = send(&app, request(Method::HEAD, "/page", &[("if-none-match", &etag)])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // The empty body of a `HEAD` endpoint isn't the one a `GET` would get.
+    let res = send(&app, request(Method::HEAD, "/empty", &[])).await;
+    assert!(res.headers().get("etag").is_none());
+}
+
+#[tokio::test]
+async fn uses_validators_of_the_endpoint() {
+    let modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+    let mut app = envoy::new();
+    app.with(Conditional::new());
+    app.at("/doc").get(move |_: &mut envoy::Context| async move {
+        let mut res = Response::new(Body::from("document"));
+        Validators::new().weak_etag("v3").last_modified(modified).apply(&mut res);
+        Ok(res)
+    });
+
+    let res = send(&app, get("/doc")).await;
+    assert_eq!(res.headers()["etag"], "W/\"v3\"");
+    let last_modified = res.headers()["last-modified"].to_str().unwrap().to_owned();
+
+    let res = send(&app, request(Method::GET, "/doc", &[("if-modified-since", &last_modified)])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    let res = send(&app, request(Method::HEAD, "/doc", &[("if-none-match", "\"v3\"")])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // `If-None-Match` takes precedence over `If-Modified-Since`.
+    let headers = [("if-none-match", "W/\"v2\""), ("if-modified-since", last_modified.as_str())];
+    let res = send(&app, request(Method::GET, "/doc", &headers)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body(), "document");
+
+    // `If-Match` uses the strong comparison, which weak tags never pass.
+    let res = send(&app, request(Method::GET, "/doc", &[("if-match", "W/\"v3\"")])).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    let earlier = "Sat, 01 Jan 2000 00:00:00 GMT";
+    let res = send(&app, request(Method::GET, "/doc", &[("if-unmodified-since", earlier)])).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+}
+
+/// The version of the document, if it exists.
+static DOCUMENT: Mutex<Option<u32>> = Mutex::new(None);
+
+async fn update(ctx: &mut envoy::Context) -> envoy::Result {
+    let current = *DOCUMENT.lock().unwrap();
+    let validators = current.map(|version| Validators::new().etag(version));
+    ctx.check_preconditions(validators.as_ref())?;
+
+    let version = current.map_or(1, |version| version + 1);
+    *DOCUMENT.lock().unwrap() = Some(version);
+    let mut res = Response::new(Body::empty());
+    Validators::new().etag(version).apply(&mut res);
+    Ok(res)
+}
+
+#[tokio::test]
+async fn checks_preconditions_before_changes() {
+    let mut app = envoy::new();
+    app.at("/doc").put(update);
+
+    // Only create the document if it doesn't exist yet.
+    let res = send(&app, request(Method::PUT, "/doc", &[("if-match", "*")])).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    let res = send(&app, request(Method::PUT, "/doc", &[("if-none-match", "*")])).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["etag"], "\"1\"");
+    let res = send(&app, request(Method::PUT, "/doc", &[("if-none-match", "*")])).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+
+    // A lost update is refused.
+    let res = send(&app, request(Method::PUT, "/doc", &[("if-match", "\"1\"")])).await;
+    assert_eq!(res.headers()["etag"], "\"2\"");
+    let res = send(&app, request(Method::PUT, "/doc", &[("if-match", "\"1\"")])).await;
+    assert_eq!(res.status(), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(res.body(), "Precondition failed");
+    let res = send(&app, request(Method::PUT, "/doc", &[])).await;
+    assert_eq!(res.headers()["etag"], "\"3\"");
+}
@@ -0,0 +1,57 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::{get, send, text};
+use envoy::StatusCode;
+
+#[tokio::test]
+async fn constrained_param_falls_through() {
+    let mut app = envoy::new();
+    app.at(r"/users/:id(\d+)").get(text("by id"));
+    app.at("/users/:name").get(text("by name"));
+
+    assert_eq!(send(&app, get("http://example.com/users/42")).await.body(), "by id");
+    assert_eq!(send(&app, get("http://example.com/users/nori")).await.body(), "by name");
+}
+
+#[tokio::test]
+async fn constraint_outranks_registration_order() {
+    let mut app = envoy::new();
+    app.at("/posts/:slug").get(text("slug"));
+    app.at("/posts/:kind(draft|published)").get(text("kind"));
+
+    assert_eq!(send(&app, get("http://example.com/posts/draft")).await.body(), "kind");
+    assert_eq!(send(&app, get("http://example.com/posts/drafts")).await.body(), "slug");
+}
+
+#[tokio::test]
+async fn tied_constraints_go_to_the_route_added_first() {
+    let mut app = envoy::new();
+    app.at(r"/users/:id(\d+)").get(text("digits"));
+    app.at("/users/:id([0-9a-f]+)").get(text("hex"));
+
+    assert_eq!(send(&app, get("http://example.com/users/42")).await.body(), "digits");
+    assert_eq!(send(&app, get("http://example.com/users/4a")).await.body(), "hex");
+
+    let mut app = envoy::new();
+    app.at("/users/:id([0-9a-f]+)").get(text("hex"));
+    app.at(r"/users/:id(\d+)").get(text("digits"));
+    assert_eq!(send(&app, get("http://example.com/users/42")).await.body(), "hex");
+}
+
+#[tokio::test]
+async fn unmatched_constraint_is_not_found() {
+    let mut app = envoy::new();
+    app.at(r"/users/:id(\d+)").get(text("by id"));
+    app.at("/articles/:slug([a-z-]+)").post(text("article"));
+
+    let res = send(&app, get("http://example.com/users/nori")).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = send(&app, get("http://example.com/users/4a")).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = send(&app, get("http://example.com/articles/hello-world")).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let res = send(&app, get("http://example.com/articles/Hello")).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+}
@@ -0,0 +1,117 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::request;
+use envoy::cookie::{Cookie, Key};
+use envoy::{Body, CookieKeys, Method, Response};
+
+async fn send(app: &envoy::Server, cookie: Option<&str>) -> (Vec<String>, String) {
+    let headers: Vec<_> = cookie.map(|cookie| ("cookie", cookie)).into_iter().collect();
+    let res = common::send(app, request(Method::GET, "/", &headers)).await;
+    let set_cookies = res
+        .headers()
+        .get_all("set-cookie")
+        .iter()
+        .map(|value| value.to_str().unwrap().to_owned())
+        .collect();
+    (set_cookies, res.into_body())
+}
+
+/// The `name=value` pair of a `Set-Cookie` header.
+fn pair(set_cookie: &str) -> &str {
+    set_cookie.split(';').next().unwrap()
+}
+
+#[tokio::test]
+async fn reads_sets_and_removes_cookies() {
+    let mut app = envoy::new();
+    app.at("/").get(|ctx: &mut envoy::Context| {
+        let theme = ctx.cookie("theme").map(|c| c.value().to_owned());
+        ctx.set_cookie(Cookie::build(("visited", "1")).path("/").http_only(true).build());
+        ctx.remove_cookie(Cookie::build("tracking").path("/"));
+        let visited = ctx.cookie("visited").map(|c| c.value().to_owned());
+        let body = format!("{:?} {:?}", theme, visited);
+        async move { Ok(Response::new(Body::from(body))) }
+    });
+
+    let (set_cookies, body) = send(&app, Some("theme=dark; tracking=abc")).await;
+    assert_eq!(body, "Some(\"dark\") Some(\"1\")");
+    assert_eq!(set_cookies.len(), 2);
+    assert!(set_cookies.contains(&"visited=1; HttpOnly; Path=/".to_owned()));
+    let removal = set_cookies.iter().find(|c| c.starts_with("tracking=")).unwrap();
+    assert!(removal.contains("Max-Age=0"));
+
+    let (set_cookies, body) = send(&app, None).await;
+    assert_eq!(body, "None Some(\"1\")");
+    assert_eq!(set_cookies, ["visited=1; HttpOnly; Path=/"]);
+}
+
+fn app(keys: CookieKeys) -> envoy::Server {
+    let mut app = envoy::new();
+    app.cookie_keys(keys);
+    app.at("/").get(|ctx: &mut envoy::Context| {
+        let user = ctx.signed_cookies().get("user").map(|c| c.value().to_owned());
+        let cart = ctx.private_cookies().get("cart").map(|c| c.value().to_owned());
+        if ctx.signed_cookies().needs_rotation("user") {
+            let cookie = Cookie::build(("user", user.clone().unwrap())).path("/").http_only(true).secure(true);
+            ctx.signed_cookies().add(cookie.build());
+        }
+        if user.is_none() {
+            ctx.signed_cookies().add(Cookie::new("user", "ann"));
+            ctx.private_cookies().add(Cookie::new("cart", "3 apples"));
+        }
+        let body = format!("{:?} {:?}", user, cart);
+        async move { Ok(Response::new(Body::from(body))) }
+    });
+    app
+}
+
+#[tokio::test]
+async fn signs_and_encrypts_cookies() {
+    let app = app(CookieKeys::new(Key::generate()));
+
+    let (set_cookies, body) = send(&app, None).await;
+    assert_eq!(body, "None None");
+    let signed = set_cookies.iter().find(|c| c.starts_with("user=")).unwrap();
+    let private = set_cookies.iter().find(|c| c.starts_with("cart=")).unwrap();
+    assert!(pair(signed).ends_with("ann"));
+    assert!(!private.contains("apples"));
+
+    let cookies = format!("{}; {}", pair(signed), pair(private));
+    let (set_cookies, body) = send(&app, Some(&cookies)).await;
+    assert_eq!(body, "Some(\"ann\") Some(\"3 apples\")");
+    assert!(set_cookies.is_empty());
+
+    let tampered = format!("{}bob; {}", pair(signed).trim_end_matches("ann"), pair(private));
+    assert_eq!(send(&app, Some(&tampered)).await.1, "None Some(\"3 apples\")");
+    assert_eq!(send(&app, Some("user=ann; cart=3 apples")).await.1, "None None");
+}
+
+#[tokio::test]
+async fn rotates_keys() {
+    let old = Key::generate();
+    let new = Key::generate();
+
+    let (set_cookies, _) = send(&app(CookieKeys::new(old.clone())), None).await;
+    let cookies = set_cookies.iter().map(|c| pair(c)).collect::<Vec<_>>().join("; ");
+
+    // Only the cookie the endpoint adds again is re-issued, with the
+    // attributes it's given.
+    let rotated = app(CookieKeys::new(new.clone()).previous(old));
+    let (reissued, body) = send(&rotated, Some(&cookies)).await;
+    assert_eq!(body, "Some(\"ann\") Some(\"3 apples\")");
+    assert_eq!(reissued.len(), 1);
+    let signed = reissued[0].as_str();
+    assert!(signed.starts_with("user="));
+    for attribute in ["HttpOnly", "Secure", "Path=/"] {
+        assert!(signed.split("; ").any(|a| a == attribute), "{}", signed);
+    }
+
+    // The cart is still encrypted with the old key, which is gone now.
+    let cart = cookies.split("; ").find(|c| c.starts_with("cart=")).unwrap();
+    let cookies = format!("{}; {}", pair(signed), cart);
+    let (set_cookies, body) = send(&app(CookieKeys::new(new)), Some(&cookies)).await;
+    assert_eq!(body, "Some(\"ann\") None");
+    assert!(set_cookies.is_empty());
+}
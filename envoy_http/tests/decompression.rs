@@ -0,0 +1,117 @@
+mod common;
+
+use envoy_http as envoy;
+
+use async_compression::tokio::bufread::{BrotliEncoder, GzipEncoder, ZlibEncoder, ZstdEncoder};
+use common::{request, send};
+use envoy::compression::{Decompression, Encoding};
+use envoy::extract::Json;
+use envoy::{Body, Method, Response, StatusCode};
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+use tokio::io::AsyncReadExt;
+
+#[derive(Deserialize)]
+struct Upload {
+    name: String,
+    readings: Vec<u32>,
+}
+
+async fn upload(Json(upload): Json<Upload>) -> envoy::Result {
+    let sum: u32 = upload.readings.iter().sum();
+    Ok(Response::new(Body::from(format!("{} {}", upload.name, sum))))
+}
+
+/// The length of the body, and whether it still has a `Content-Length`.
+async fn length(ctx: &mut envoy::Context) -> envoy::Result {
+    let has_length = ctx.header("content-length").is_some();
+    let mut stream = ctx.body_stream();
+    while stream.try_next().await?.is_some() {}
+    Ok(Response::new(Body::from(format!("{} {}", stream.read(), has_length))))
+}
+
+async fn encode(coding: &str, data: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    match coding {
+        "br" => BrotliEncoder::new(data).read_to_end(&mut encoded).await,
+        "zstd" => ZstdEncoder::new(data).read_to_end(&mut encoded).await,
+        "gzip" => GzipEncoder::new(data).read_to_end(&mut encoded).await,
+        "deflate" => ZlibEncoder::new(data).read_to_end(&mut encoded).await,
+        other => panic!("unexpected coding {}", other),
+    }
+    .unwrap();
+    encoded
+}
+
+fn app(decompression: Decompression) -> envoy::Server {
+    let mut app = envoy::new();
+    app.with(decompression);
+    app.at("/upload").post(upload);
+    app.at("/length").post(length);
+    app
+}
+
+async fn post(app: &envoy::Server, uri: &str, coding: &str, body: Vec<u8>) -> Response<String> {
+    let length = body.len().to_string();
+    let headers = [
+        ("content-type", "application/json"),
+        ("content-encoding", coding),
+        ("content-length", length.as_str()),
+    ];
+    let mut req = request(Method::POST, uri, &headers);
+    *req.body_mut() = Body::from(body);
+    send(app, req).await
+}
+
+const JSON: &[u8] = br#"{"name": "sensor-7", "readings": [1, 2, 3, 4]}"#;
+
+#[tokio::test]
+async fn decodes_request_bodies() {
+    let app = app(Decompression::new());
+    for coding in ["gzip", "zstd", "br", "deflate"] {
+        let res = post(&app, "/upload", coding, encode(coding, JSON).await).await;
+        assert_eq!(res.status(), StatusCode::OK, "{}", coding);
+        assert_eq!(res.body(), "sensor-7 10");
+    }
+
+    let body = post(&app, "/upload", "X-GZIP", encode("gzip", JSON).await).await.into_body();
+    assert_eq!(body, "sensor-7 10");
+    let body = post(&app, "/upload", "identity", JSON.to_vec()).await.into_body();
+    assert_eq!(body, "sensor-7 10");
+
+    // Codings are undone in the reverse order they were applied.
+    let stacked = encode("gzip", &encode("zstd", JSON).await).await;
+    let body = post(&app, "/upload", "zstd, gzip", stacked).await.into_body();
+    assert_eq!(body, "sensor-7 10");
+
+    let body = post(&app, "/length", "gzip", encode("gzip", JSON).await).await.into_body();
+    assert_eq!(body, format!("{} false", JSON.len()));
+}
+
+#[tokio::test]
+async fn limits_decompressed_size() {
+    let app = app(Decompression::new().limit(64 * 1024));
+    let bomb = encode("gzip", &vec![b' '; 1024 * 1024]).await;
+    assert!(bomb.len() < 64 * 1024);
+
+    let res = post(&app, "/upload", "gzip", bomb.clone()).await;
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    let res = post(&app, "/length", "gzip", bomb).await;
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let res = post(&app, "/length", "gzip", encode("gzip", &[b' '; 1024]).await).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn rejects_unsupported_and_corrupt_bodies() {
+    let app = app(Decompression::new().encodings([Encoding::Gzip, Encoding::Zstd]));
+    for coding in ["compress", "br", "gzip, snappy"] {
+        let res = post(&app, "/upload", coding, JSON.to_vec()).await;
+        assert_eq!(res.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE, "{}", coding);
+        assert_eq!(res.headers()["accept-encoding"], "gzip, zstd");
+    }
+
+    let res = post(&app, "/upload", "gzip", JSON.to_vec()).await;
+    assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::fs;
+
+use envoy_http as envoy;
+
+use common::{get, request, send};
+use envoy::embed::{self, EmbeddedDir, EmbeddedFile, Variant};
+use envoy::{Method, StatusCode};
+
+static ASSETS: EmbeddedDir = EmbeddedDir::new(&[
+    EmbeddedFile {
+        path: "app.js",
+        contents: b"console.log(1)",
+        etag: "\"e-1\"",
+        variants: &[Variant {
+            coding: "br",
+            contents: b"brotli",
+            etag: "\"6-2\"",
+        }],
+    },
+    EmbeddedFile {
+        path: "docs/index.html",
+        contents: b"<h1>Docs</h1>",
+        etag: "\"d-3\"",
+        variants: &[],
+    },
+    EmbeddedFile {
+        path: "index.html",
+        contents: b"<h1>Home</h1>",
+        etag: "\"d-4\"",
+        variants: &[],
+    },
+]);
+
+#[tokio::test]
+async fn generates_embedded_dirs() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("app.js"), "console.log(1)").unwrap();
+    fs::write(dir.path().join("app.js.br"), "brotli").unwrap();
+    fs::write(dir.path().join("archive.gz"), "gzip").unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs").join("index.html"), "<h1>Docs</h1>").unwrap();
+    let out = tempfile::tempdir().unwrap();
+    let out = out.path().join("assets.rs");
+
+    embed::generate(dir.path(), &out).unwrap();
+    let source = fs::read_to_string(&out).unwrap();
+    let paths: Vec<&str> = source
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("path: "))
+        .collect();
+    assert_eq!(paths, ["\"app.js\",", "\"archive.gz\",", "\"docs/index.html\","]);
+    assert!(source.starts_with("::envoy_http::embed::EmbeddedDir::new(&[\n"));
+    assert!(source.contains("coding: \"br\""));
+    assert!(!source.contains("coding: \"gzip\""));
+
+    // ETags only depend on the contents.
+    let etags: Vec<&str> = source.lines().filter(|line| line.contains("etag: ")).collect();
+    assert_eq!(etags.len(), 4);
+    embed::generate(dir.path(), &out).unwrap();
+    assert_eq!(fs::read_to_string(&out).unwrap(), source);
+}
+
+#[tokio::test]
+async fn serves_embedded_files() {
+    let mut app = envoy::new();
+    app.at("/static").serve_embedded(&ASSETS);
+
+    let res = send(&app, get("/static/app.js")).await;
+    assert_eq!(res.headers()["content-type"], "text/javascript; charset=utf-8");
+    assert_eq!(res.headers()["etag"], "\"e-1\"");
+    assert!(res.headers().get("last-modified").is_none());
+    assert_eq!(res.body(), "console.log(1)");
+
+    let res = send(&app, request(Method::GET, "/static/app.js", &[("accept-encoding", "br")])).await;
+    assert_eq!(res.headers()["content-encoding"], "br");
+    assert_eq!(res.headers()["etag"], "\"6-2\"");
+    assert_eq!(res.body(), "brotli");
+
+    let res = send(&app, request(Method::GET, "/static/app.js", &[("if-none-match", "\"e-1\"")])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    let res = send(&app, request(Method::GET, "/static/app.js", &[("range", "bytes=0-6")])).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.body(), "console");
+
+    let body = send(&app, get("/static/")).await.into_body();
+    assert_eq!(body, "<h1>Home</h1>");
+    let res = send(&app, get("/static/docs")).await;
+    assert_eq!(res.headers()["location"], "/static/docs/");
+    let body = send(&app, get("/static/docs/")).await.into_body();
+    assert_eq!(body, "<h1>Docs</h1>");
+
+    for uri in ["/static/missing.js", "/static/..%2Findex.html"] {
+        let res = send(&app, get(uri)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
@@ -0,0 +1,223 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::{get, request};
+use envoy::extract::{Header, Json, Path, Query, State};
+use envoy::multipart::Limits;
+use envoy::{Body, Method, Request, Response, StatusCode};
+use headers::{authorization::Bearer, Authorization, UserAgent};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+struct Pagination {
+    page: u32,
+    per_page: Option<u32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NewComment {
+    author: String,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CommentPath {
+    post: u64,
+    comment: u64,
+}
+
+#[derive(Debug, Clone)]
+struct SiteName(&'static str);
+
+/// Send `req` with `body`, returning the status and body of the response.
+async fn send(app: &envoy::Server, mut req: Request<Body>, body: &str) -> (StatusCode, String) {
+    *req.body_mut() = Body::from(body.to_owned());
+    let res = common::send(app, req).await;
+    (res.status(), res.into_body())
+}
+
+fn text(body: String) -> envoy::Result {
+    Ok(Response::new(Body::from(body)))
+}
+
+#[tokio::test]
+async fn extracts_path_query_and_json() {
+    async fn list(Path(post): Path<u64>, Query(pagination): Query<Pagination>) -> envoy::Result {
+        text(format!("post {} page {} per {:?}", post, pagination.page, pagination.per_page))
+    }
+
+    async fn create(Path(post): Path<u64>, Json(comment): Json<NewComment>) -> envoy::Result {
+        text(format!("post {}: {} says {}", post, comment.author, comment.text))
+    }
+
+    async fn show(Path(path): Path<CommentPath>) -> envoy::Result {
+        text(format!("comment {} of post {}", path.comment, path.post))
+    }
+
+    let mut app = envoy::new();
+    app.at("/posts/:post/comments").get(list).post(create);
+    app.at("/posts/:post/comments/:comment").get(show);
+
+    assert_eq!(
+        send(&app, get("/posts/7/comments?page=2"), "").await,
+        (StatusCode::OK, "post 7 page 2 per None".to_owned())
+    );
+    let req = request(Method::POST, "/posts/7/comments", &[("content-type", "application/json")]);
+    assert_eq!(
+        send(&app, req, r#"{"author": "ann", "text": "hi"}"#).await,
+        (StatusCode::OK, "post 7: ann says hi".to_owned())
+    );
+    assert_eq!(
+        send(&app, get("/posts/7/comments/3"), "").await,
+        (StatusCode::OK, "comment 3 of post 7".to_owned())
+    );
+}
+
+#[tokio::test]
+async fn path_uses_the_params_of_the_innermost_server() {
+    async fn user(Path(id): Path<u32>) -> envoy::Result {
+        text(format!("user {}", id))
+    }
+
+    let mut users = envoy::new();
+    users.at("/:id").get(user);
+    let mut app = envoy::new();
+    app.at("/orgs/:org/users").nest(users);
+    app.host(":tenant.example.com").at("/users/:id").get(user);
+
+    assert_eq!(
+        send(&app, get("/orgs/acme/users/7"), "").await,
+        (StatusCode::OK, "user 7".to_owned())
+    );
+    assert_eq!(
+        send(&app, get("http://shop.example.com/users/7"), "").await,
+        (StatusCode::OK, "user 7".to_owned())
+    );
+}
+
+#[tokio::test]
+async fn rejections_become_client_errors() {
+    async fn list(Path(post): Path<u64>, Query(pagination): Query<Pagination>) -> envoy::Result {
+        text(format!("post {} page {}", post, pagination.page))
+    }
+
+    async fn create(Json(comment): Json<NewComment>) -> envoy::Result {
+        text(comment.text)
+    }
+
+    let mut app = envoy::new();
+    app.at("/posts/:post/comments").get(list).post(create);
+
+    let status = |res: (StatusCode, String)| res.0;
+    let post = || request(Method::POST, "/posts/7/comments", &[]);
+    let json = || {
+        let headers = [("content-type", "application/json; charset=utf-8")];
+        request(Method::POST, "/posts/7/comments", &headers)
+    };
+
+    assert_eq!(status(send(&app, get("/posts/seven/comments?page=1"), "").await), StatusCode::BAD_REQUEST);
+    assert_eq!(status(send(&app, get("/posts/7/comments"), "").await), StatusCode::BAD_REQUEST);
+    assert_eq!(status(send(&app, get("/posts/7/comments?page=x"), "").await), StatusCode::BAD_REQUEST);
+    assert_eq!(status(send(&app, post(), r#"{"author": "ann", "text": "hi"}"#).await), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(status(send(&app, json(), r#"{"author": "#).await), StatusCode::BAD_REQUEST);
+    assert_eq!(status(send(&app, json(), r#"{"author": "ann"}"#).await), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(status(send(&app, json(), r#"{"author": "ann", "text": "hi"}"#).await), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn limits_the_size_of_json_bodies() {
+    async fn create(Json(comment): Json<NewComment>) -> envoy::Result {
+        text(comment.text)
+    }
+
+    let mut app = envoy::new();
+    app.with_state(Limits::new().total(32));
+    app.at("/comments").post(create);
+
+    let json = || request(Method::POST, "/comments", &[("content-type", "application/json")]);
+    assert_eq!(send(&app, json(), r#"{"author": "ann", "text": "hi"}"#).await.1, "hi");
+    let long = r#"{"author": "ann", "text": "a comment far too long"}"#;
+    assert_eq!(send(&app, json(), long).await.0, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn optional_extractors_only_skip_missing_values() {
+    async fn comment(comment: Option<Json<NewComment>>, agent: Option<Header<UserAgent>>) -> envoy::Result {
+        let comment = comment.map_or_else(|| "none".to_owned(), |Json(comment)| comment.text);
+        let agent = agent.map_or_else(|| "unknown".to_owned(), |Header(agent)| agent.to_string());
+        text(format!("{} from {}", comment, agent))
+    }
+
+    async fn page(pagination: Option<Query<Pagination>>) -> envoy::Result {
+        text(format!("page {:?}", pagination.map(|Query(pagination)| pagination.page)))
+    }
+
+    let mut app = envoy::new();
+    app.at("/comments").post(comment).get(page);
+
+    let post = |headers: &[(&str, &str)]| request(Method::POST, "/comments", headers);
+    assert_eq!(send(&app, post(&[]), "").await, (StatusCode::OK, "none from unknown".to_owned()));
+    let json = [("content-type", "application/json"), ("user-agent", "curl")];
+    assert_eq!(send(&app, post(&json), r#"{"author": "ann", "text": "hi"}"#).await.1, "hi from curl");
+    assert_eq!(send(&app, post(&json), r#"{"author": "#).await.0, StatusCode::BAD_REQUEST);
+    let form = [("content-type", "text/plain")];
+    assert_eq!(send(&app, post(&form), "hi").await.0, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+
+    assert_eq!(send(&app, get("/comments"), "").await.1, "page None");
+    assert_eq!(send(&app, get("/comments?page=2"), "").await.1, "page Some(2)");
+    assert_eq!(send(&app, get("/comments?page=two"), "").await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn extracts_state_headers_and_context() {
+    async fn whoami(
+        ctx: &mut envoy::Context,
+        State(site): State<SiteName>,
+        Header(auth): Header<Authorization<Bearer>>,
+        agent: Option<Header<UserAgent>>,
+    ) -> envoy::Result {
+        let route = ctx.route().map_or("", |route| route.pattern()).to_owned();
+        let agent = agent.map_or_else(|| "unknown".to_owned(), |Header(agent)| agent.to_string());
+        text(format!("{} {} {} {}", site.0, route, auth.token(), agent))
+    }
+
+    let mut app = envoy::new();
+    app.with_state(SiteName("blog"));
+    app.at("/whoami").get(whoami);
+
+    let req = request(Method::GET, "/whoami", &[("authorization", "Bearer abc")]);
+    assert_eq!(send(&app, req, "").await, (StatusCode::OK, "blog /whoami abc unknown".to_owned()));
+
+    let headers = [("authorization", "Bearer abc"), ("user-agent", "curl")];
+    let req = request(Method::GET, "/whoami", &headers);
+    assert_eq!(send(&app, req, "").await.1, "blog /whoami abc curl");
+
+    let req = request(Method::GET, "/whoami", &[("authorization", "Basic YTpi")]);
+    assert_eq!(send(&app, req, "").await.0, StatusCode::BAD_REQUEST);
+    assert_eq!(send(&app, get("/whoami"), "").await.0, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn later_state_replaces_earlier_state() {
+    async fn site(State(site): State<SiteName>) -> envoy::Result {
+        text(site.0.to_owned())
+    }
+
+    let mut app = envoy::new();
+    app.with_state(SiteName("blog"));
+    app.with_state(SiteName("shop"));
+    app.at("/").get(site);
+    assert_eq!(send(&app, get("/"), "").await.1, "shop");
+}
+
+#[tokio::test]
+async fn missing_state_is_a_server_error() {
+    async fn site(State(site): State<SiteName>) -> envoy::Result {
+        text(site.0.to_owned())
+    }
+
+    let mut app = envoy::new();
+    app.at("/").get(site);
+    assert_eq!(send(&app, get("/"), "").await.0, StatusCode::INTERNAL_SERVER_ERROR);
+}
@@ -0,0 +1,194 @@
+mod common;
+
+use std::fs;
+
+use envoy_http as envoy;
+
+use common::{get, request, send};
+use envoy::fs::ServeDir;
+use envoy::{Method, StatusCode};
+use tempfile::TempDir;
+
+fn site() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("hello.txt"), "Hello, world!").unwrap();
+    fs::write(dir.path().join("app.js"), "console.log(1)").unwrap();
+    fs::write(dir.path().join("app.js.br"), "brotli").unwrap();
+    fs::write(dir.path().join("app.js.gz"), "gzip").unwrap();
+    fs::create_dir(dir.path().join("docs")).unwrap();
+    fs::write(dir.path().join("docs").join("a <b>.md"), "# A").unwrap();
+    fs::create_dir(dir.path().join("blog")).unwrap();
+    fs::write(dir.path().join("blog").join("index.html"), "<h1>Blog</h1>").unwrap();
+    dir
+}
+
+#[tokio::test]
+async fn serves_files_below_the_root() {
+    let site = site();
+    let outside = tempfile::tempdir().unwrap();
+    fs::write(outside.path().join("secret.txt"), "secret").unwrap();
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(outside.path().join("secret.txt"), site.path().join("link.txt")).unwrap();
+
+    let mut app = envoy::new();
+    app.at("/static").serve_dir_with(ServeDir::new(site.path()).unwrap().listing());
+    app.at("/hello").serve_file(site.path().join("hello.txt")).unwrap();
+
+    let res = send(&app, get("/static/hello.txt")).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.headers()["content-type"], "text/plain; charset=utf-8");
+    assert_eq!(res.headers()["content-length"], "13");
+    assert_eq!(res.headers()["accept-ranges"], "bytes");
+    assert_eq!(res.body(), "Hello, world!");
+    let body = send(&app, get("/hello")).await.into_body();
+    assert_eq!(body, "Hello, world!");
+
+    let res = send(&app, request(Method::HEAD, "/static/hello.txt", &[])).await;
+    assert_eq!(res.headers()["content-length"], "13");
+    assert_eq!(res.body(), "");
+
+    for uri in ["/static/missing.txt", "/static/..%2F..%2Fetc%2Fpasswd", "/static/link.txt"] {
+        let res = send(&app, get(uri)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+
+    let res = send(&app, get("/static/blog?page=2")).await;
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(res.headers()["location"], "/static/blog/?page=2");
+    let body = send(&app, get("/static/blog/")).await.into_body();
+    assert_eq!(body, "<h1>Blog</h1>");
+
+    let res = send(&app, get("/static/docs/")).await;
+    assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+    assert!(res.body().contains("<h1>Index of /static/docs/</h1>"));
+    assert!(res.body().contains("<a href=\"../\">../</a>"));
+    assert!(res.body().contains("<a href=\"a%20%3Cb%3E.md\">a &lt;b&gt;.md</a>"));
+    let body = send(&app, get("/static/docs/a%20%3Cb%3E.md")).await.into_body();
+    assert_eq!(body, "# A");
+
+    let mut app = envoy::new();
+    app.at("/static").serve_dir(site.path()).unwrap();
+    let res = send(&app, get("/static/docs/")).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    assert!(envoy::new().at("/").serve_dir(site.path().join("hello.txt")).is_err());
+}
+
+#[tokio::test]
+async fn answers_conditional_requests() {
+    let site = site();
+    let mut app = envoy::new();
+    app.at("/*").serve_dir(site.path()).unwrap();
+
+    let res = send(&app, get("/hello.txt")).await;
+    let etag = res.headers()["etag"].to_str().unwrap().to_owned();
+    let last_modified = res.headers()["last-modified"].to_str().unwrap().to_owned();
+
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("if-none-match", &etag)])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(res.headers()["etag"], etag.as_str());
+    assert_eq!(res.body(), "");
+
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("if-modified-since", &last_modified)])).await;
+    assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+    // `If-None-Match` takes precedence over `If-Modified-Since`.
+    let headers = [("if-none-match", "\"other\""), ("if-modified-since", last_modified.as_str())];
+    let res = send(&app, request(Method::GET, "/hello.txt", &headers)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn serves_ranges() {
+    let site = site();
+    let mut app = envoy::new();
+    app.at("/").serve_dir(site.path()).unwrap();
+
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("range", "bytes=7-")])).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()["content-range"], "bytes 7-12/13");
+    assert_eq!(res.body(), "world!");
+
+    let body = send(&app, request(Method::GET, "/hello.txt", &[("range", "bytes=-6")])).await.into_body();
+    assert_eq!(body, "world!");
+
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("range", "bytes=0-4, 100-200, 12-20")])).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    let content_type = res.headers()["content-type"].to_str().unwrap();
+    let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+    assert_eq!(res.headers()["content-length"], res.body().len().to_string().as_str());
+    assert_eq!(
+        *res.body(),
+        format!(
+            "\r\n--{0}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 0-4/13\r\n\r\nHello\
+             \r\n--{0}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Range: bytes 12-12/13\r\n\r\n!\
+             \r\n--{0}--\r\n",
+            boundary
+        )
+    );
+
+    // Overlapping and adjacent ranges are coalesced, and too many ranges
+    // get the whole file.
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("range", "bytes=0-,0-,0-")])).await;
+    assert_eq!(res.headers()["content-range"], "bytes 0-12/13");
+    assert_eq!(res.body(), "Hello, world!");
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("range", "bytes=7-9, 0-2, 1-4, 10-")])).await;
+    assert!(res.headers()["content-type"].to_str().unwrap().starts_with("multipart/byteranges"));
+    assert!(res.body().contains("Content-Range: bytes 0-4/13\r\n\r\nHello\r\n"));
+    assert!(res.body().contains("Content-Range: bytes 7-12/13\r\n\r\nworld!\r\n"));
+    let many = (0..13).map(|i| format!("{0}-{0}", i * 2 % 13)).collect::<Vec<_>>().join(",");
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("range", &format!("bytes={}", many))])).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    fs::write(site.path().join("digits.txt"), "0123456789".repeat(10)).unwrap();
+    let many = (0..40).map(|i| format!("{0}-{0}", i * 2)).collect::<Vec<_>>().join(",");
+    let res = send(&app, request(Method::GET, "/digits.txt", &[("range", &format!("bytes={}", many))])).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body().len(), 100);
+
+    let res = send(&app, request(Method::GET, "/hello.txt", &[("range", "bytes=20-30")])).await;
+    assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(res.headers()["content-range"], "bytes */13");
+
+    // A stale `If-Range` gets the whole file.
+    let headers = [("range", "bytes=7-"), ("if-range", "\"stale\"")];
+    let res = send(&app, request(Method::GET, "/hello.txt", &headers)).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body(), "Hello, world!");
+}
+
+#[tokio::test]
+async fn serves_precompressed_siblings() {
+    let site = site();
+    let mut app = envoy::new();
+    app.at("/").serve_dir(site.path()).unwrap();
+
+    let res = send(&app, request(Method::GET, "/app.js", &[("accept-encoding", "gzip, br")])).await;
+    assert_eq!(res.headers()["content-encoding"], "br");
+    assert_eq!(res.headers()["content-type"], "text/javascript; charset=utf-8");
+    assert_eq!(res.headers()["vary"], "accept-encoding");
+    assert_eq!(res.body(), "brotli");
+    let brotli_etag = res.headers()["etag"].clone();
+
+    let res = send(&app, request(Method::GET, "/app.js", &[("accept-encoding", "gzip, br; q=0.5")])).await;
+    assert_eq!(res.headers()["content-encoding"], "gzip");
+    assert_ne!(res.headers()["etag"], brotli_etag);
+    assert_eq!(res.body(), "gzip");
+
+    let res = send(&app, get("/app.js")).await;
+    assert!(res.headers().get("content-encoding").is_none());
+    assert_eq!(res.body(), "console.log(1)");
+
+    let headers = [("accept-encoding", "br"), ("range", "bytes=1-3")];
+    let res = send(&app, request(Method::GET, "/app.js", &headers)).await;
+    assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(res.headers()["content-encoding"], "br");
+    assert_eq!(res.headers()["content-range"], "bytes 1-3/6");
+    assert_eq!(res.body(), "rot");
+    let headers = [("accept-encoding", "gzip"), ("range", "bytes=1-")];
+    assert_eq!(send(&app, request(Method::GET, "/app.js", &headers)).await.body(), "zip");
+
+    let mut app = envoy::new();
+    app.at("/").serve_dir_with(ServeDir::new(site.path()).unwrap().without_precompressed());
+    let res = send(&app, request(Method::GET, "/app.js", &[("accept-encoding", "br")])).await;
+    assert!(res.headers().get("vary").is_none());
+    assert_eq!(res.body(), "console.log(1)");
+}
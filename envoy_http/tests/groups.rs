@@ -0,0 +1,132 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::request;
+use envoy::{Body, Error, Method, Response, StatusCode};
+
+#[derive(Debug)]
+struct RequiredPermission(&'static str);
+
+#[derive(Debug)]
+struct Tags(Vec<&'static str>);
+
+async fn check_permission(ctx: &mut envoy::Context, next: envoy::Next) -> envoy::Result {
+    let granted = ctx
+        .borrow::<envoy::HeaderMap>()
+        .get("x-permission")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_owned);
+    match ctx.meta::<RequiredPermission>() {
+        Some(RequiredPermission(required)) if granted.as_deref() != Some(*required) => {
+            Err(Error::from_str(StatusCode::FORBIDDEN, "missing permission"))
+        }
+        _ => next.run(ctx).await,
+    }
+}
+
+async fn describe(ctx: &mut envoy::Context) -> envoy::Result {
+    let permission = ctx.meta::<RequiredPermission>().map(|p| p.0).unwrap_or("none");
+    let tags = ctx.meta::<Tags>().map(|t| t.0.join(",")).unwrap_or_default();
+    Ok(Response::new(format!("{} [{}]", permission, tags).into()))
+}
+
+async fn fail(_ctx: &mut envoy::Context) -> envoy::Result {
+    Err(Error::from_str(StatusCode::CONFLICT, "conflict"))
+}
+
+async fn send(app: &envoy::Server, uri: &str, permission: Option<&str>) -> (StatusCode, String) {
+    let headers: Vec<_> = permission.map(|permission| ("x-permission", permission)).into_iter().collect();
+    let res = common::send(app, request(Method::GET, uri, &headers)).await;
+    (res.status(), res.into_body())
+}
+
+#[tokio::test]
+async fn group_scopes_middleware_and_metadata() {
+    let mut app = envoy::new();
+    app.group("/admin", |admin| {
+        admin.with(check_permission);
+        admin.meta(RequiredPermission("admin")).meta(Tags(vec!["admin"]));
+        admin.at("/users").get(describe);
+        admin.group("/audit", |audit| {
+            audit.meta(RequiredPermission("auditor"));
+            audit.at("/log").get(describe);
+        });
+    });
+    app.at("/public").get(describe);
+
+    assert_eq!(send(&app, "/admin/users", None).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send(&app, "/admin/users", Some("admin")).await.1, "admin [admin]");
+    assert_eq!(send(&app, "/admin/audit/log", Some("admin")).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send(&app, "/admin/audit/log", Some("auditor")).await.1, "auditor [admin]");
+    assert_eq!(send(&app, "/public", None).await.1, "none []");
+}
+
+#[tokio::test]
+async fn group_error_handlers() {
+    let mut app = envoy::new();
+    app.group("/api", |api| {
+        api.on_error(|_ctx: &mut envoy::Context, err: Error| async move {
+            let mut res = Response::new(Body::from(format!("api: {}", err)));
+            *res.status_mut() = err.status();
+            Ok(res)
+        });
+        api.with(check_permission).meta(RequiredPermission("api"));
+        api.at("/fail").get(fail);
+        api.group("/v2", |v2| {
+            v2.on_error(|_ctx: &mut envoy::Context, err: Error| async move {
+                Err(Error::from_str(StatusCode::BAD_GATEWAY, format!("v2: {}", err)))
+            });
+            v2.at("/fail").get(fail);
+        });
+    });
+    app.at("/fail").get(fail);
+
+    assert_eq!(
+        send(&app, "/api/fail", None).await,
+        (StatusCode::FORBIDDEN, "api: missing permission".to_owned())
+    );
+    assert_eq!(
+        send(&app, "/api/fail", Some("api")).await,
+        (StatusCode::CONFLICT, "api: conflict".to_owned())
+    );
+    assert_eq!(
+        send(&app, "/api/v2/fail", Some("api")).await,
+        (StatusCode::BAD_GATEWAY, "api: v2: conflict".to_owned())
+    );
+    assert_eq!(send(&app, "/fail", None).await, (StatusCode::CONFLICT, "conflict".to_owned()));
+}
+
+#[tokio::test]
+async fn named_routes() {
+    let mut app = envoy::new();
+    app.at("/").name("home").get(describe);
+    app.group("/admin", |admin| {
+        admin.name_prefix("admin.");
+        admin.at("/users/:id(\\d+)").name("user").get(describe);
+        admin.group("/files", |files| {
+            files.name_prefix("files.");
+            files.at("/*").name("show").get(describe);
+            files.at("/").name("index").get(describe);
+        });
+    });
+
+    assert_eq!(app.url_for("home", &[]).unwrap(), "/");
+    assert_eq!(app.url_for("admin.user", &[("id", "7")]).unwrap(), "/admin/users/7");
+    assert_eq!(app.url_for("admin.user", &[("id", "x")]), None);
+    assert_eq!(app.url_for("admin.user", &[]), None);
+    assert_eq!(app.url_for("user", &[("id", "7")]), None);
+    assert_eq!(
+        app.url_for("admin.files.show", &[("*", "a/b.txt")]).unwrap(),
+        "/admin/files/a/b.txt"
+    );
+    assert_eq!(app.url_for("admin.files.index", &[]).unwrap(), "/admin/files");
+}
+
+#[test]
+#[should_panic(expected = "already registered")]
+fn duplicate_names_panic() {
+    let mut app = envoy::new();
+    app.at("/a").name("a");
+    app.at("/b").name("a");
+}
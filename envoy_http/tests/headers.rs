@@ -0,0 +1,115 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::{get, request, send};
+use envoy::headers::{authorization::Bearer, Authorization, ContentType, ETag, HeaderMapExt, IfNoneMatch};
+use envoy::{Accept, Body, Error, Method, Response, StatusCode};
+
+#[tokio::test]
+async fn reads_raw_headers() {
+    let mut app = envoy::new();
+    app.at("/").get(|ctx: &mut envoy::Context| {
+        let auth = ctx.header("X-Auth").is_some_and(|auth| auth == "secret_key");
+        let tags: Vec<_> = ctx.header_all("x-tag").filter_map(|tag| tag.to_str().ok()).collect();
+        let body = format!("{} {}", auth, tags.join(","));
+        async move { Ok(Response::new(Body::from(body))) }
+    });
+
+    let req = request(Method::GET, "/", &[("x-auth", "secret_key"), ("x-tag", "a"), ("x-tag", "b")]);
+    assert_eq!(send(&app, req).await.body(), "true a,b");
+    assert_eq!(send(&app, get("/")).await.body(), "false ");
+}
+
+#[tokio::test]
+async fn decodes_typed_headers() {
+    let mut app = envoy::new();
+    app.at("/").get(|ctx: &mut envoy::Context| {
+        let content_type = ctx.typed_header::<ContentType>().map(|c| c.to_string());
+        let token = ctx
+            .typed_header::<Authorization<Bearer>>()
+            .map(|auth| auth.token().to_owned());
+        let etag: ETag = "\"v1\"".parse().unwrap();
+        let fresh = ctx
+            .typed_header::<IfNoneMatch>()
+            .is_some_and(|if_none_match| !if_none_match.precondition_passes(&etag));
+        let body = format!("{:?} {:?} {}", content_type, token, fresh);
+        async move { Ok(Response::new(Body::from(body))) }
+    });
+
+    let headers = [
+        ("content-type", "application/json"),
+        ("authorization", "Bearer abc"),
+        ("if-none-match", "\"v0\", \"v1\""),
+    ];
+    let req = request(Method::GET, "/", &headers);
+    assert_eq!(send(&app, req).await.body(), "Some(\"application/json\") Some(\"abc\") true");
+
+    let req = request(Method::GET, "/", &[("authorization", "Basic YTpi")]);
+    assert_eq!(send(&app, req).await.body(), "None None false");
+}
+
+#[tokio::test]
+async fn negotiates_with_accept() {
+    let mut app = envoy::new();
+    app.at("/").get(|ctx: &mut envoy::Context| {
+        let accept = ctx.typed_header::<Accept>().unwrap_or_default();
+        let res = match accept.negotiate(&["application/json", "text/html"]) {
+            Some(media_type) => Response::new(Body::from(media_type)),
+            None => {
+                let mut res = Response::new(Body::empty());
+                *res.status_mut() = StatusCode::NOT_ACCEPTABLE;
+                res
+            }
+        };
+        async move { Ok(res) }
+    });
+
+    let req = request(Method::GET, "/", &[("accept", "text/html;q=0.9, application/json;q=0.8")]);
+    assert_eq!(send(&app, req).await.body(), "text/html");
+    let req = request(Method::GET, "/", &[("accept", "text/*;q=0.5, */*;q=0.6")]);
+    assert_eq!(send(&app, req).await.body(), "application/json");
+    let req = request(Method::GET, "/", &[("accept", "image/png")]);
+    assert_eq!(send(&app, req).await.status(), StatusCode::NOT_ACCEPTABLE);
+    assert_eq!(send(&app, get("/")).await.body(), "application/json");
+}
+
+#[tokio::test]
+async fn merges_staged_response_headers() {
+    async fn stage(ctx: &mut envoy::Context, next: envoy::Next) -> envoy::Result {
+        let headers = ctx.response_headers_mut();
+        headers.insert("x-request-id", "42".parse().unwrap());
+        headers.append("vary", "accept".parse().unwrap());
+        headers.append("vary", "origin".parse().unwrap());
+        headers.insert("cache-control", "no-store".parse().unwrap());
+        headers.typed_insert(ContentType::text());
+        next.run(ctx).await
+    }
+
+    let mut app = envoy::new();
+    app.with(stage);
+    app.at("/ok").get(|ctx: &mut envoy::Context| {
+        let staged = ctx.response_headers().map_or(0, |headers| headers.len());
+        async move {
+            let mut res = Response::new(Body::from(staged.to_string()));
+            res.headers_mut().insert("cache-control", "max-age=60".parse().unwrap());
+            Ok(res)
+        }
+    });
+    app.at("/error").get(|_ctx: &mut envoy::Context| async {
+        Err(Error::from_str(StatusCode::CONFLICT, "conflict"))
+    });
+
+    let res = send(&app, get("/ok")).await;
+    assert_eq!(res.body(), "5");
+    assert_eq!(res.headers()["x-request-id"], "42");
+    assert_eq!(res.headers()["cache-control"], "max-age=60");
+    assert_eq!(res.headers()["content-type"], "text/plain");
+    let vary: Vec<_> = res.headers().get_all("vary").iter().collect();
+    assert_eq!(vary, ["accept", "origin"]);
+
+    let res = send(&app, get("/error")).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    assert_eq!(res.headers()["x-request-id"], "42");
+    assert_eq!(res.headers()["cache-control"], "no-store");
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::{get, request, respond, send, text};
+use envoy::{Method, Response, StatusCode};
+
+async fn echo_tenant(ctx: &mut envoy::Context) -> envoy::Result {
+    Ok(Response::new(ctx.param("tenant")?.to_owned().into()))
+}
+
+async fn echo_subdomain(ctx: &mut envoy::Context) -> envoy::Result {
+    Ok(Response::new(ctx.param("subdomain")?.to_owned().into()))
+}
+
+#[tokio::test]
+async fn dispatches_on_host() {
+    let mut app = envoy::new();
+    app.host("api.example.com").at("/").get(text("api"));
+    app.host("www.example.com").at("/").get(text("www"));
+    app.at("/").get(text("fallback"));
+
+    let req = get("http://api.example.com/");
+    assert_eq!(send(&app, req).await.body(), "api");
+
+    let req = request(Method::GET, "/", &[("Host", "WWW.example.com:8080")]);
+    assert_eq!(send(&app, req).await.body(), "www");
+
+    let req = get("http://other.example.com/");
+    assert_eq!(send(&app, req).await.body(), "fallback");
+
+    let req = get("/");
+    assert_eq!(send(&app, req).await.body(), "fallback");
+}
+
+#[tokio::test]
+async fn host_routes_without_fallback() {
+    let mut app = envoy::new();
+    app.host("api.example.com").at("/users").get(text("users"));
+
+    let req = get("http://api.example.com/users");
+    assert_eq!(send(&app, req).await.status(), StatusCode::OK);
+
+    let req = get("http://www.example.com/users");
+    assert_eq!(send(&app, req).await.status(), StatusCode::NOT_FOUND);
+
+    let req = request(Method::POST, "http://www.example.com/users", &[]);
+    assert_eq!(send(&app, req).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn captures_host_params() {
+    let mut app = envoy::new();
+    app.host(":tenant.example.com").at("/").get(echo_tenant);
+    app.at("/dashboard").host("*.tenant.example.com").get(echo_subdomain);
+
+    let req = get("http://acme.example.com/");
+    assert_eq!(send(&app, req).await.body(), "acme");
+
+    let req = get("http://eu.acme.tenant.example.com/dashboard");
+    assert_eq!(send(&app, req).await.body(), "eu.acme");
+}
+
+#[tokio::test]
+async fn exact_host_outranks_pattern() {
+    let mut app = envoy::new();
+    app.host("*.example.com").at("/").get(text("pattern"));
+    app.host("api.example.com").at("/").get(text("exact"));
+
+    let req = get("http://api.example.com/");
+    assert_eq!(send(&app, req).await.body(), "exact");
+
+    let req = get("http://www.example.com/");
+    assert_eq!(send(&app, req).await.body(), "pattern");
+}
+
+#[tokio::test]
+async fn dispatches_on_header() {
+    let mut app = envoy::new();
+    app.at("/users").get(text("v1"));
+    app.at("/users").header("Accept-Version", "2").get(text("v2"));
+
+    let req = request(Method::GET, "/users", &[("accept-version", "2")]);
+    assert_eq!(send(&app, req).await.body(), "v2");
+
+    let req = request(Method::GET, "/users", &[("accept-version", "3")]);
+    assert_eq!(send(&app, req).await.body(), "v1");
+
+    let req = get("/users");
+    assert_eq!(send(&app, req).await.body(), "v1");
+}
+
+#[tokio::test]
+async fn header_routes_vary_on_the_header() {
+    let mut app = envoy::new();
+    app.at("/users").get(text("v1"));
+    app.at("/users").header("Accept-Version", "2").get(text("v2"));
+    app.at("/teams").get(text("teams"));
+
+    for version in &["2", "3"] {
+        let req = request(Method::GET, "/users", &[("accept-version", *version)]);
+        let res = respond(&app, req).await;
+        assert_eq!(res.headers()["vary"], "accept-version");
+    }
+
+    let req = request(Method::POST, "/users", &[]);
+    let res = respond(&app, req).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(res.headers()["vary"], "accept-version");
+
+    let req = get("/teams");
+    let res = respond(&app, req).await;
+    assert!(res.headers().get("vary").is_none());
+}
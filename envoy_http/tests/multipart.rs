@@ -0,0 +1,193 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::request;
+use envoy::multipart::{Limits, Spooled};
+use envoy::{Body, Method, Response, StatusCode};
+use futures_util::TryStreamExt;
+use serde::Deserialize;
+
+const BOUNDARY: &str = "X-BOUNDARY";
+
+/// A `multipart/form-data` body with the given text fields and files.
+fn form(fields: &[(&str, &str)], files: &[(&str, &str, &str, &[u8])]) -> Vec<u8> {
+    let mut body = Vec::new();
+    for (name, value) in fields {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"\r\n\r\n{}\r\n",
+                BOUNDARY, name, value
+            )
+            .as_bytes(),
+        );
+    }
+    for (name, file_name, content_type, contents) in files {
+        body.extend_from_slice(
+            format!(
+                "--{}\r\nContent-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\nContent-Type: {}\r\n\r\n",
+                BOUNDARY, name, file_name, content_type
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(contents);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{}--\r\n", BOUNDARY).as_bytes());
+    body
+}
+
+async fn send(app: &envoy::Server, content_type: &str, body: Vec<u8>) -> (StatusCode, String) {
+    let mut req = request(Method::POST, "/", &[("content-type", content_type)]);
+    *req.body_mut() = Body::from(body);
+    let res = common::send(app, req).await;
+    (res.status(), res.into_body())
+}
+
+fn multipart() -> String {
+    format!("multipart/form-data; boundary={}", BOUNDARY)
+}
+
+/// Describe every field: its name, file name, content type, and either its
+/// size in memory or on disk.
+async fn describe(ctx: &mut envoy::Context) -> envoy::Result {
+    let mut multipart = ctx.multipart()?;
+    let mut lines = Vec::new();
+    while let Some(field) = multipart.next_field().await? {
+        let description = format!(
+            "{:?} {:?} {:?}",
+            field.name(),
+            field.file_name(),
+            field.content_type()
+        );
+        let contents = match field.spool().await? {
+            Spooled::Memory(bytes) => format!("memory {}", bytes.len()),
+            Spooled::File(file) => format!("file {}", std::fs::read(file.path())?.len()),
+        };
+        lines.push(format!("{} {}", description, contents));
+    }
+    Ok(Response::new(Body::from(lines.join("\n"))))
+}
+
+#[tokio::test]
+async fn reads_fields_and_spools_large_files() {
+    let mut app = envoy::new();
+    app.with_state(Limits::new().in_memory(1024));
+    app.at("/").post(describe);
+
+    let large = vec![b'a'; 4096];
+    let body = form(
+        &[("title", "holiday")],
+        &[
+            ("small", "note.txt", "text/plain", b"hello"),
+            ("large", "photo.png", "image/png", &large),
+        ],
+    );
+    let (status, body) = send(&app, &multipart(), body).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        body.lines().collect::<Vec<_>>(),
+        [
+            "Some(\"title\") None None memory 7",
+            "Some(\"small\") Some(\"note.txt\") Some(\"text/plain\") memory 5",
+            "Some(\"large\") Some(\"photo.png\") Some(\"image/png\") file 4096",
+        ]
+    );
+
+    let (status, _) = send(&app, "application/json", b"{}".to_vec()).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    let (status, _) = send(&app, &multipart(), b"--X-BOUNDARY\r\nbroken".to_vec()).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn streams_fields_in_chunks() {
+    let mut app = envoy::new();
+    app.at("/").post(|ctx: &mut envoy::Context| {
+        let multipart = ctx.multipart();
+        async move {
+            let field = multipart?.next_field().await?.unwrap();
+            let chunks: Vec<_> = field.try_collect().await?;
+            let size: usize = chunks.iter().map(|chunk| chunk.len()).sum();
+            Ok(Response::new(Body::from(size.to_string())))
+        }
+    });
+
+    let upload = vec![b'z'; 100_000];
+    let body = form(&[], &[("upload", "data.bin", "application/octet-stream", &upload)]);
+    assert_eq!(send(&app, &multipart(), body).await.1, "100000");
+}
+
+#[tokio::test]
+async fn enforces_size_limits() {
+    let mut app = envoy::new();
+    app.with_state(Limits::new().total(8 * 1024).per_field(1024).field("bio", 16));
+    app.at("/").post(describe);
+
+    let ok = form(&[("name", "ann")], &[("avatar", "a.png", "image/png", &[0; 1000])]);
+    assert_eq!(send(&app, &multipart(), ok).await.0, StatusCode::OK);
+
+    let field = form(&[], &[("avatar", "a.png", "image/png", &[0; 2000])]);
+    assert_eq!(send(&app, &multipart(), field).await.0, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let named = form(&[("bio", "a rather long biography")], &[]);
+    assert_eq!(send(&app, &multipart(), named).await.0, StatusCode::PAYLOAD_TOO_LARGE);
+
+    let files: Vec<_> = (0..10).map(|i| (format!("file{}", i), vec![0; 1000])).collect();
+    let files: Vec<_> = files
+        .iter()
+        .map(|(name, contents)| (name.as_str(), "f.bin", "application/octet-stream", contents.as_slice()))
+        .collect();
+    let total = form(&[], &files);
+    assert_eq!(send(&app, &multipart(), total).await.0, StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[derive(Deserialize)]
+struct Signup {
+    name: String,
+    age: u8,
+    newsletter: Option<bool>,
+}
+
+async fn signup(ctx: &mut envoy::Context) -> envoy::Result {
+    let signup: Signup = ctx.form_data().await?;
+    let body = format!("{} {} {:?}", signup.name, signup.age, signup.newsletter);
+    Ok(Response::new(Body::from(body)))
+}
+
+#[tokio::test]
+async fn deserializes_form_data() {
+    let mut app = envoy::new();
+    app.at("/").post(signup);
+    let expected = "ann 42 Some(true)";
+
+    let body = form(
+        &[("name", "ann"), ("age", "42"), ("newsletter", "true")],
+        &[("avatar", "a.png", "image/png", b"png")],
+    );
+    assert_eq!(send(&app, &multipart(), body).await.1, expected);
+
+    let body = b"name=ann&age=42&newsletter=true".to_vec();
+    let urlencoded = "application/x-www-form-urlencoded";
+    assert_eq!(send(&app, urlencoded, body).await.1, expected);
+
+    let body = form(&[("name", "ann"), ("age", "old")], &[]);
+    assert_eq!(send(&app, &multipart(), body).await.0, StatusCode::BAD_REQUEST);
+    let (status, body) = send(&app, urlencoded, b"name=ann&age=7".to_vec()).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "ann 7 None"));
+    let (status, _) = send(&app, "text/plain", b"ann".to_vec()).await;
+    assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+}
+
+#[tokio::test]
+async fn limits_urlencoded_forms() {
+    let mut app = envoy::new();
+    app.with_state(Limits::new().total(32));
+    app.at("/").post(signup);
+    let urlencoded = "application/x-www-form-urlencoded";
+
+    let (status, body) = send(&app, urlencoded, b"name=ann&age=42".to_vec()).await;
+    assert_eq!((status, body.as_str()), (StatusCode::OK, "ann 42 None"));
+    let long = format!("name={}&age=42", "a".repeat(64)).into_bytes();
+    assert_eq!(send(&app, urlencoded, long).await.0, StatusCode::PAYLOAD_TOO_LARGE);
+}
@@ -0,0 +1,72 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::{get, send};
+use envoy::{Response, StatusCode, TrailingSlash, Uri};
+
+async fn describe(ctx: &mut envoy::Context) -> envoy::Result {
+    let body = format!(
+        "{} {} {}",
+        ctx.borrow::<Uri>(),
+        ctx.original_uri(),
+        ctx.mount_path()
+    );
+    Ok(Response::new(body.into()))
+}
+
+#[tokio::test]
+async fn top_level_uri() {
+    let mut app = envoy::new();
+    app.at("/echo").get(describe);
+
+    assert_eq!(send(&app, get("/echo?a=1")).await.body(), "/echo?a=1 /echo?a=1 ");
+}
+
+#[tokio::test]
+async fn nested_keeps_query_and_authority() {
+    let mut inner = envoy::new();
+    inner.at("/echo").get(describe);
+    inner.at("/").get(describe);
+
+    let mut app = envoy::new();
+    app.at("/foo").nest(inner);
+
+    assert_eq!(
+        send(&app, get("http://example.com/foo/echo?a=1&b=2")).await.body(),
+        "http://example.com/echo?a=1&b=2 http://example.com/foo/echo?a=1&b=2 /foo"
+    );
+    assert_eq!(send(&app, get("/foo/echo/")).await.body(), "/echo/ /foo/echo/ /foo");
+    assert_eq!(send(&app, get("/foo")).await.body(), "/ /foo /foo");
+}
+
+#[tokio::test]
+async fn mount_paths_accumulate() {
+    let mut innermost = envoy::new();
+    innermost.at("/echo").get(describe);
+
+    let mut inner = envoy::new();
+    inner.at("/v1").nest(innermost);
+
+    let mut app = envoy::new();
+    app.at("/api").nest(inner);
+
+    assert_eq!(
+        send(&app, get("/api/v1/echo?q")).await.body(),
+        "/echo?q /api/v1/echo?q /api/v1"
+    );
+}
+
+#[tokio::test]
+async fn nested_redirect_includes_mount_path() {
+    let mut inner = envoy::new();
+    inner.trailing_slash(TrailingSlash::Redirect(StatusCode::MOVED_PERMANENTLY));
+    inner.at("/users/").get(describe);
+
+    let mut app = envoy::new();
+    app.at("/admin").nest(inner);
+
+    let res = send(&app, get("/admin/users?page=2")).await;
+    assert_eq!(res.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(res.headers()["location"], "/admin/users/?page=2");
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::{get, send};
+use envoy::{Normalization, PercentDecoding, Response, StatusCode, TrailingSlash, Uri};
+
+async fn echo_path(ctx: &mut envoy::Context) -> envoy::Result {
+    Ok(Response::new(ctx.borrow::<Uri>().to_string().into()))
+}
+
+async fn echo_name(ctx: &mut envoy::Context) -> envoy::Result {
+    Ok(Response::new(ctx.param("name")?.to_owned().into()))
+}
+
+#[tokio::test]
+async fn trailing_slash_ignored_by_default() {
+    let mut app = envoy::new();
+    app.at("/foo").get(echo_path);
+    app.at("/bar/").get(echo_path);
+
+    assert_eq!(send(&app, get("/foo/")).await.status(), StatusCode::OK);
+    assert_eq!(send(&app, get("/bar")).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn strict_trailing_slash() {
+    let mut app = envoy::new();
+    app.trailing_slash(TrailingSlash::Strict);
+    app.at("/foo").get(echo_path);
+    app.at("/bar/").get(echo_path);
+    app.at("/files/*").get(echo_path);
+
+    assert_eq!(send(&app, get("/foo")).await.status(), StatusCode::OK);
+    assert_eq!(send(&app, get("/foo/")).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, get("/bar/")).await.status(), StatusCode::OK);
+    assert_eq!(send(&app, get("/bar")).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, get("/files/a/")).await.status(), StatusCode::OK);
+    assert_eq!(send(&app, get("/")).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn redirects_to_canonical_path() {
+    let mut app = envoy::new();
+    app.trailing_slash(TrailingSlash::Redirect(StatusCode::PERMANENT_REDIRECT));
+    app.at("/foo").get(echo_path);
+    app.at("/bar/").get(echo_path);
+
+    let res = send(&app, get("/foo/?page=2")).await;
+    assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(res.headers()["location"], "/foo?page=2");
+
+    let res = send(&app, get("/bar")).await;
+    assert_eq!(res.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(res.headers()["location"], "/bar/");
+
+    assert_eq!(send(&app, get("/foo")).await.status(), StatusCode::OK);
+    assert_eq!(send(&app, get("/baz/")).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn normalizes_before_routing() {
+    let mut app = envoy::new();
+    app.at("/a/b").get(echo_path);
+    app.at("/~user").get(echo_path);
+
+    assert_eq!(send(&app, get("/a//b?x=//y")).await.body(), "/a/b?x=//y");
+    assert_eq!(send(&app, get("/a/./c/../b")).await.body(), "/a/b");
+    assert_eq!(send(&app, get("/%7euser")).await.body(), "/~user");
+}
+
+#[tokio::test]
+async fn normalization_can_be_disabled() {
+    let mut app = envoy::new();
+    app.normalize_path(Normalization {
+        merge_slashes: false,
+        dot_segments: false,
+        percent_decoding: PercentDecoding::None,
+    });
+    app.at("/a/b").get(echo_path);
+
+    assert_eq!(send(&app, get("/a/b")).await.status(), StatusCode::OK);
+    assert_eq!(send(&app, get("/a//b")).await.status(), StatusCode::NOT_FOUND);
+    assert_eq!(send(&app, get("/a/./b")).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn decodes_params() {
+    let mut app = envoy::new();
+    app.at("/users/:name").get(echo_name);
+    assert_eq!(send(&app, get("/users/jane%20doe")).await.body(), "jane%20doe");
+
+    let mut app = envoy::new();
+    app.normalize_path(Normalization {
+        percent_decoding: PercentDecoding::Params,
+        ..Normalization::default()
+    });
+    app.at("/users/:name").get(echo_name);
+    assert_eq!(send(&app, get("/users/jane%20doe")).await.body(), "jane doe");
+    assert_eq!(send(&app, get("/users/a%2Fb")).await.body(), "a/b");
+}
@@ -0,0 +1,101 @@
+mod common;
+
+use std::sync::Arc;
+
+use envoy_http as envoy;
+
+use common::{get, send, text};
+use envoy::{Body, Response, StatusCode};
+use tokio::sync::Notify;
+
+#[tokio::test]
+async fn adds_and_removes_routes_while_running() {
+    let mut app = envoy::new();
+    app.at("/old").name("old").get(text("old"));
+    let running = app.clone();
+
+    app.update_routes(|app| {
+        app.at("/new").get(text("new"));
+        app.at("/old").remove();
+    });
+
+    assert_eq!(send(&running, get("/new")).await.body(), "new");
+    assert_eq!(send(&running, get("/old")).await.status(), StatusCode::NOT_FOUND);
+    assert!(running.url_for("old", &[]).is_none());
+
+    // The name is free again once its route is removed.
+    app.update_routes(|app| {
+        app.at("/old").name("old").get(text("back"));
+    });
+    assert_eq!(send(&running, get("/old")).await.body(), "back");
+    assert_eq!(running.url_for("old", &[]).unwrap(), "/old");
+}
+
+#[tokio::test]
+async fn replaces_nested_server() {
+    let mut v1 = envoy::new();
+    v1.at("/hello").get(text("v1"));
+    let mut app = envoy::new();
+    app.at("/plugin").nest(v1);
+    app.at("/plugin/status").get(text("status"));
+
+    let mut v2 = envoy::new();
+    v2.at("/hello").get(text("v2"));
+    app.update_routes(|app| {
+        app.at("/plugin").remove().nest(v2);
+    });
+
+    assert_eq!(send(&app, get("/plugin/hello")).await.body(), "v2");
+    assert_eq!(send(&app, get("/plugin/status")).await.body(), "status");
+}
+
+#[tokio::test]
+async fn updates_nested_server_in_place() {
+    let inner = envoy::new();
+    let handle = inner.clone();
+    let mut app = envoy::new();
+    app.at("/plugin").nest(inner);
+
+    assert_eq!(send(&app, get("/plugin/hello")).await.status(), StatusCode::NOT_FOUND);
+    handle.update_routes(|inner| {
+        inner.at("/hello").get(text("hello"));
+    });
+    assert_eq!(send(&app, get("/plugin/hello")).await.body(), "hello");
+}
+
+#[tokio::test]
+async fn in_flight_requests_keep_their_routes() {
+    let started = Arc::new(Notify::new());
+    let release = Arc::new(Notify::new());
+
+    let mut app = envoy::new();
+    let (s, r) = (started.clone(), release.clone());
+    app.at("/slow").get(move |_ctx: &mut envoy::Context| {
+        let (started, release) = (s.clone(), r.clone());
+        async move {
+            started.notify_one();
+            release.notified().await;
+            Ok(Response::new(Body::from("old")))
+        }
+    });
+
+    let running = app.clone();
+    let in_flight = tokio::spawn(async move { send(&running, get("/slow")).await });
+    started.notified().await;
+
+    app.update_routes(|app| {
+        app.at("/slow").remove().get(text("new"));
+    });
+    release.notify_one();
+
+    assert_eq!(in_flight.await.unwrap().body(), "old");
+    assert_eq!(send(&app, get("/slow")).await.body(), "new");
+}
+
+#[test]
+#[should_panic(expected = "update_routes")]
+fn registering_routes_after_start_panics() {
+    let mut app = envoy::new();
+    let _running = app.clone();
+    app.at("/late").get(text("late"));
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::request;
+use envoy::{Body, Method, Response, StatusCode};
+
+#[derive(Debug)]
+struct RequiresScope(&'static str);
+
+/// Server-wide middleware reporting the selected route in a header.
+async fn describe_route(ctx: &mut envoy::Context, next: envoy::Next) -> envoy::Result {
+    let description = match ctx.route() {
+        Some(route) => format!("{} {}", route.pattern(), route.name().unwrap_or("-")),
+        None => "none".to_owned(),
+    };
+    let mut res = next.run(ctx).await?;
+    res.headers_mut()
+        .insert("x-route", description.parse().unwrap());
+    Ok(res)
+}
+
+/// Server-wide middleware checking the scope required by the route.
+async fn check_scope(ctx: &mut envoy::Context, next: envoy::Next) -> envoy::Result {
+    let granted = ctx
+        .borrow::<envoy::HeaderMap>()
+        .get("x-scope")
+        .map(|v| v.as_bytes().to_vec());
+    match ctx.meta::<RequiresScope>() {
+        Some(RequiresScope(scope)) if granted.as_deref() != Some(scope.as_bytes()) => {
+            Ok(Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(Body::empty())?)
+        }
+        _ => next.run(ctx).await,
+    }
+}
+
+async fn ok(_ctx: &mut envoy::Context) -> envoy::Result {
+    Ok(Response::new(Body::from("ok")))
+}
+
+async fn send(app: &envoy::Server, uri: &str, scope: Option<&str>) -> (StatusCode, String) {
+    let headers: Vec<_> = scope.map(|scope| ("x-scope", scope)).into_iter().collect();
+    let res = common::respond(app, request(Method::GET, uri, &headers)).await;
+    let route = res.headers()["x-route"].to_str().unwrap().to_owned();
+    (res.status(), route)
+}
+
+#[tokio::test]
+async fn server_middleware_sees_matched_route() {
+    let mut app = envoy::new();
+    app.with(describe_route);
+    app.at("/users/:id(\\d+)").name("user").get(ok);
+    app.at("/posts/:slug").get(ok).name("post");
+    app.at("/health").get(ok);
+
+    assert_eq!(send(&app, "/users/42", None).await.1, "/users/:id(\\d+) user");
+    assert_eq!(send(&app, "/posts/hello", None).await.1, "/posts/:slug post");
+    assert_eq!(send(&app, "/health", None).await.1, "/health -");
+    assert_eq!(send(&app, "/missing", None).await, (StatusCode::NOT_FOUND, "none".to_owned()));
+}
+
+#[tokio::test]
+async fn server_middleware_sees_route_metadata() {
+    let mut app = envoy::new();
+    app.with(describe_route).with(check_scope);
+    app.group("/admin", |admin| {
+        admin.meta(RequiresScope("admin"));
+        admin.at("/users").get(ok);
+    });
+    app.at("/public").get(ok);
+
+    assert_eq!(send(&app, "/admin/users", None).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(send(&app, "/admin/users", Some("admin")).await.0, StatusCode::OK);
+    assert_eq!(send(&app, "/public", None).await.0, StatusCode::OK);
+}
+
+#[tokio::test]
+async fn nested_routes_report_full_pattern() {
+    let mut inner = envoy::new();
+    inner.with(describe_route).with(check_scope);
+    inner.at("/users/:id").name("user").get(ok);
+    inner.at("/").get(ok);
+
+    let mut app = envoy::new();
+    app.group("/api", |api| {
+        api.meta(RequiresScope("api"));
+        api.nest(inner);
+    });
+
+    assert_eq!(send(&app, "/api/users/7", None).await.0, StatusCode::FORBIDDEN);
+    assert_eq!(
+        send(&app, "/api/users/7", Some("api")).await,
+        (StatusCode::OK, "/api/users/:id user".to_owned())
+    );
+    assert_eq!(send(&app, "/api", Some("api")).await.1, "/api -");
+}
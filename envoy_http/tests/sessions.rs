@@ -0,0 +1,169 @@
+mod common;
+
+use std::time::Duration;
+
+use envoy_http as envoy;
+
+use common::request;
+use envoy::sessions::{FileStore, MemoryStore, SessionStore, Sessions};
+use envoy::{Body, Method, Response};
+
+async fn count(ctx: &mut envoy::Context) -> envoy::Result {
+    let count = ctx.session().get::<u32>("count").unwrap_or(0) + 1;
+    ctx.session_mut().insert("count", count)?;
+    Ok(Response::new(Body::from(count.to_string())))
+}
+
+async fn peek(ctx: &mut envoy::Context) -> envoy::Result {
+    let user = ctx.session().get::<String>("user").unwrap_or_default();
+    Ok(Response::new(Body::from(user)))
+}
+
+async fn login(ctx: &mut envoy::Context) -> envoy::Result {
+    let session = ctx.session_mut();
+    session.insert("user", "ann")?;
+    session.regenerate();
+    Ok(Response::new(Body::empty()))
+}
+
+async fn logout(ctx: &mut envoy::Context) -> envoy::Result {
+    ctx.session_mut().destroy();
+    Ok(Response::new(Body::empty()))
+}
+
+fn app<S: SessionStore>(sessions: Sessions<S>) -> envoy::Server {
+    let mut app = envoy::new();
+    app.with(sessions);
+    app.at("/count").get(count);
+    app.at("/peek").get(peek);
+    app.at("/login").post(login);
+    app.at("/logout").post(logout);
+    app
+}
+
+/// A client keeping the session cookie between requests.
+#[derive(Default)]
+struct Client {
+    cookie: Option<String>,
+}
+
+impl Client {
+    /// Send a request, returning the body and the `Set-Cookie` header.
+    async fn send(&mut self, app: &envoy::Server, method: Method, uri: &str) -> (String, Option<String>) {
+        let headers: Vec<_> = self.cookie.as_deref().map(|cookie| ("cookie", cookie)).into_iter().collect();
+        let res = common::send(app, request(method, uri, &headers)).await;
+        let set_cookie = res
+            .headers()
+            .get("set-cookie")
+            .map(|value| value.to_str().unwrap().to_owned());
+        if let Some(set_cookie) = &set_cookie {
+            let pair = set_cookie.split(';').next().unwrap();
+            self.cookie = Some(pair.to_owned()).filter(|pair| !pair.ends_with('='));
+        }
+        (res.into_body(), set_cookie)
+    }
+}
+
+#[tokio::test]
+async fn keeps_data_between_requests() {
+    let store = MemoryStore::new();
+    let app = app(Sessions::new(store.clone()).cookie_name("sid"));
+    let mut client = Client::default();
+
+    let (body, set_cookie) = client.send(&app, Method::GET, "/peek").await;
+    assert_eq!(body, "");
+    assert!(set_cookie.is_none());
+    assert!(store.is_empty());
+
+    let (body, set_cookie) = client.send(&app, Method::GET, "/count").await;
+    assert_eq!(body, "1");
+    let set_cookie = set_cookie.unwrap();
+    assert!(set_cookie.starts_with("sid="));
+    assert!(set_cookie.contains("HttpOnly"));
+    assert!(set_cookie.contains("SameSite=Lax"));
+    assert!(set_cookie.contains("Max-Age=86400"));
+
+    assert_eq!(client.send(&app, Method::GET, "/count").await.0, "2");
+    assert_eq!(Client::default().send(&app, Method::GET, "/count").await.0, "1");
+    assert_eq!(store.len(), 2);
+
+    let mut forged = Client {
+        cookie: Some("sid=../../etc/passwd".to_owned()),
+    };
+    assert_eq!(forged.send(&app, Method::GET, "/count").await.0, "1");
+}
+
+#[tokio::test]
+async fn regenerates_and_destroys_sessions() {
+    let store = MemoryStore::new();
+    let app = app(Sessions::new(store.clone()));
+    let mut client = Client::default();
+
+    client.send(&app, Method::GET, "/count").await;
+    let before = client.cookie.clone();
+
+    client.send(&app, Method::POST, "/login").await;
+    assert_ne!(client.cookie, before);
+    assert_eq!(client.send(&app, Method::GET, "/peek").await.0, "ann");
+    assert_eq!(client.send(&app, Method::GET, "/count").await.0, "2");
+    assert_eq!(store.len(), 1);
+
+    let mut attacker = Client { cookie: before };
+    assert_eq!(attacker.send(&app, Method::GET, "/peek").await.0, "");
+
+    let (_, set_cookie) = client.send(&app, Method::POST, "/logout").await;
+    assert!(set_cookie.unwrap().contains("Max-Age=0"));
+    assert!(client.cookie.is_none());
+    assert!(store.is_empty());
+}
+
+#[tokio::test]
+async fn expires_idle_and_old_sessions() {
+    let idle = app(Sessions::new(MemoryStore::new()).idle_timeout(Duration::from_millis(200)));
+    let mut client = Client::default();
+    client.send(&idle, Method::GET, "/count").await;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(client.send(&idle, Method::GET, "/count").await.0, "2");
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert_eq!(client.send(&idle, Method::GET, "/count").await.0, "3");
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(client.send(&idle, Method::GET, "/count").await.0, "1");
+
+    let absolute = app(Sessions::new(MemoryStore::new()).absolute_timeout(Duration::from_millis(250)));
+    let mut client = Client::default();
+    client.send(&absolute, Method::GET, "/count").await;
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(client.send(&absolute, Method::GET, "/count").await.0, "2");
+    tokio::time::sleep(Duration::from_millis(150)).await;
+    assert_eq!(client.send(&absolute, Method::GET, "/count").await.0, "1");
+}
+
+#[tokio::test]
+async fn stores_sessions_in_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let files = || std::fs::read_dir(dir.path()).map_or(0, |entries| entries.count());
+
+    let app = app(Sessions::new(FileStore::new(dir.path())));
+    let mut client = Client::default();
+    assert_eq!(client.send(&app, Method::GET, "/count").await.0, "1");
+    assert_eq!(client.send(&app, Method::GET, "/count").await.0, "2");
+    assert_eq!(files(), 1);
+
+    client.send(&app, Method::POST, "/login").await;
+    assert_eq!(client.send(&app, Method::GET, "/count").await.0, "3");
+    assert_eq!(files(), 1);
+
+    let short = app_with_file_store(dir.path(), Duration::from_millis(50));
+    Client::default().send(&short, Method::GET, "/count").await;
+    assert_eq!(files(), 2);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    FileStore::new(dir.path()).cleanup().await.unwrap();
+    assert_eq!(files(), 1);
+
+    client.send(&app, Method::POST, "/logout").await;
+    assert_eq!(files(), 0);
+}
+
+fn app_with_file_store(dir: &std::path::Path, idle_timeout: Duration) -> envoy::Server {
+    app(Sessions::new(FileStore::new(dir)).idle_timeout(idle_timeout))
+}
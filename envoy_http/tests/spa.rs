@@ -0,0 +1,90 @@
+mod common;
+
+use std::fs;
+
+use envoy_http as envoy;
+
+use common::{request, send};
+use envoy::{Body, Method, Response, StatusCode};
+use tempfile::TempDir;
+
+fn dist() -> TempDir {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("index.html"), "<div id=app></div>").unwrap();
+    fs::write(dir.path().join("robots.txt"), "User-agent: *").unwrap();
+    fs::create_dir(dir.path().join("assets")).unwrap();
+    fs::write(dir.path().join("assets").join("index-Bk9x2sLq.js"), "app()").unwrap();
+    fs::write(dir.path().join("assets").join("app.3f2a9c1b.css"), "body {}").unwrap();
+    dir
+}
+
+async fn health(_: &mut envoy::Context) -> envoy::Result {
+    Ok(Response::new(Body::from("ok")))
+}
+
+fn app(dist: &TempDir) -> envoy::Server {
+    let mut app = envoy::new();
+    app.at("/api/health").get(health);
+    app.at("/").serve_spa(dist.path()).unwrap();
+    app
+}
+
+const NAVIGATION: &str = "text/html,application/xhtml+xml,*/*;q=0.8";
+
+#[tokio::test]
+async fn falls_back_to_the_index_for_navigations() {
+    let dist = dist();
+    let app = app(&dist);
+
+    for uri in ["/", "/settings/profile", "/assets"] {
+        let res = send(&app, request(Method::GET, uri, &[("accept", NAVIGATION)])).await;
+        assert_eq!(res.status(), StatusCode::OK, "{}", uri);
+        assert_eq!(res.headers()["content-type"], "text/html; charset=utf-8");
+        assert_eq!(res.headers()["cache-control"], "no-cache");
+        assert_eq!(res.body(), "<div id=app></div>");
+    }
+
+    let body = send(&app, request(Method::GET, "/api/health", &[("accept", NAVIGATION)])).await.into_body();
+    assert_eq!(body, "ok");
+    let body = send(&app, request(Method::GET, "/robots.txt", &[("accept", NAVIGATION)])).await.into_body();
+    assert_eq!(body, "User-agent: *");
+
+    // Only navigations get the index.
+    let res = send(&app, request(Method::GET, "/settings/profile", &[("accept", "application/json")])).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = send(&app, request(Method::GET, "/settings/profile", &[("accept", "*/*")])).await;
+    assert_eq!(res.status(), StatusCode::NOT_FOUND);
+    let res = send(&app, request(Method::POST, "/settings/profile", &[("accept", NAVIGATION)])).await;
+    assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn answers_missing_assets_with_not_found() {
+    let dist = dist();
+    let app = app(&dist);
+
+    for uri in ["/assets/index-Missing1.js", "/favicon.ico", "/../secret.txt"] {
+        let res = send(&app, request(Method::GET, uri, &[("accept", NAVIGATION)])).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND, "{}", uri);
+    }
+}
+
+#[tokio::test]
+async fn caches_hashed_assets() {
+    let dist = dist();
+    let app = app(&dist);
+
+    for uri in ["/assets/index-Bk9x2sLq.js", "/assets/app.3f2a9c1b.css"] {
+        let res = send(&app, request(Method::GET, uri, &[("accept", "*/*")])).await;
+        assert_eq!(res.headers()["cache-control"], "public, max-age=31536000, immutable", "{}", uri);
+    }
+
+    let res = send(&app, request(Method::GET, "/robots.txt", &[("accept", "*/*")])).await;
+    assert!(res.headers().get("cache-control").is_none());
+
+    let res = send(&app, request(Method::HEAD, "/", &[("accept", NAVIGATION)])).await;
+    assert_eq!(res.headers()["cache-control"], "no-cache");
+    assert_eq!(res.body(), "");
+
+    assert!(envoy::new().at("/").serve_spa(dist.path().join("assets")).is_err());
+}
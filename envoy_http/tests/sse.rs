@@ -0,0 +1,92 @@
+mod common;
+
+use std::time::Duration;
+
+use envoy_http as envoy;
+
+use common::{get, request, respond, send};
+use envoy::sse::{self, Event, SseSender};
+use envoy::{Body, Method, Next};
+use hyper::body::HttpBody;
+use tokio::sync::oneshot;
+
+/// The next chunk of the body, as text.
+async fn next(body: &mut Body) -> Option<String> {
+    let chunk = body.data().await?.unwrap();
+    Some(String::from_utf8(chunk.to_vec()).unwrap())
+}
+
+async fn count(ctx: envoy::Context, sender: SseSender) -> envoy::Result<()> {
+    let start: u32 = sender.last_event_id().and_then(|id| id.parse().ok()).unwrap_or(0);
+    let name = ctx.param("name")?.to_owned();
+    for n in start + 1..=start + 2 {
+        let event = Event::new().id(n.to_string()).event(name.as_str()).data(format!("{}\nof\n2", n));
+        sender.send(event).await?;
+    }
+    sender.send(Event::new().retry(Duration::from_secs(3)).json_data(&vec![1, 2])?).await?;
+    Ok(())
+}
+
+async fn stage_header(ctx: &mut envoy::Context, next: Next) -> envoy::Result {
+    ctx.response_headers_mut().insert("x-stream", "yes".parse().unwrap());
+    next.run(ctx).await
+}
+
+#[tokio::test]
+async fn streams_events() {
+    let mut app = envoy::new();
+    app.with(stage_header);
+    app.at("/:name").get(sse::endpoint(count).without_keep_alive());
+    let res = send(&app, get("/counter")).await;
+    assert_eq!(res.headers()["content-type"], "text/event-stream");
+    assert_eq!(res.headers()["cache-control"], "no-cache");
+    assert_eq!(res.headers()["x-stream"], "yes");
+    assert_eq!(
+        res.body(),
+        "event: counter\ndata: 1\ndata: of\ndata: 2\nid: 1\n\n\
+         event: counter\ndata: 2\ndata: of\ndata: 2\nid: 2\n\n\
+         data: [1,2]\nretry: 3000\n\n"
+    );
+
+    let req = request(Method::GET, "/counter", &[("last-event-id", "5")]);
+    assert!(send(&app, req).await.body().starts_with("event: counter\ndata: 6\n"));
+}
+
+#[tokio::test]
+async fn sends_keep_alive_comments() {
+    let mut app = envoy::new();
+    app.at("/").get(
+        sse::endpoint(|_, sender: SseSender| async move {
+            tokio::time::sleep(Duration::from_millis(130)).await;
+            sender.send_data("done").await
+        })
+        .keep_alive(Duration::from_millis(50)),
+    );
+
+    let mut body = respond(&app, get("/")).await.into_body();
+    assert_eq!(next(&mut body).await.unwrap(), ": keep-alive\n\n");
+    assert_eq!(next(&mut body).await.unwrap(), ": keep-alive\n\n");
+    assert_eq!(next(&mut body).await.unwrap(), "data: done\n\n");
+    assert_eq!(next(&mut body).await, None);
+}
+
+#[tokio::test]
+async fn detects_disconnects() {
+    let (closed_tx, closed_rx) = oneshot::channel();
+    let closed_tx = std::sync::Mutex::new(Some(closed_tx));
+    let mut app = envoy::new();
+    app.at("/").get(sse::endpoint(move |_, sender: SseSender| {
+        let closed_tx = closed_tx.lock().unwrap().take().unwrap();
+        async move {
+            sender.send_comment("hello").await?;
+            sender.closed().await;
+            closed_tx.send(sender.send_data("too late").await.is_err()).unwrap();
+            Ok(())
+        }
+    }));
+
+    let mut body = respond(&app, get("/")).await.into_body();
+    assert_eq!(next(&mut body).await.unwrap(), ": hello\n\n");
+    drop(body);
+    assert!(closed_rx.await.unwrap());
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use std::convert::Infallible;
+
+use envoy_http as envoy;
+
+use common::{get, request, respond, send};
+use envoy::body::Bytes;
+use envoy::{stream, Body, Method, Request, Response, StatusCode};
+use futures_util::{StreamExt, TryStreamExt};
+use hyper::body;
+use serde::Serialize;
+
+/// Count the chunks and bytes of the request body, up to 10 bytes.
+async fn count(ctx: &mut envoy::Context) -> envoy::Result {
+    let mut stream = ctx.body_stream().limit(10);
+    let mut chunks = 0;
+    while let Some(chunk) = stream.try_next().await? {
+        assert!(!chunk.is_empty());
+        chunks += 1;
+    }
+    Ok(Response::new(Body::from(format!("{} {}", chunks, stream.read()))))
+}
+
+fn post(chunks: &'static [&'static str]) -> Request<Body> {
+    let stream = futures_util::stream::iter(chunks.iter().map(|chunk| Ok::<_, Infallible>(*chunk)));
+    let mut req = request(Method::POST, "/", &[]);
+    *req.body_mut() = Body::wrap_stream(stream);
+    req
+}
+
+#[tokio::test]
+async fn streams_request_bodies_with_a_limit() {
+    let mut app = envoy::new();
+    app.at("/").post(count);
+
+    let res = send(&app, post(&["abc", "de", "fgh"])).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(res.body(), "3 8");
+
+    let res = send(&app, post(&["abcdef", "ghijkl"])).await;
+    assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let mut req = request(Method::POST, "/", &[("content-length", "1000")]);
+    *req.body_mut() = Body::from("abc");
+    assert_eq!(send(&app, req).await.status(), StatusCode::PAYLOAD_TOO_LARGE);
+}
+
+#[tokio::test]
+async fn streams_responses_from_readers_and_streams() {
+    let mut app = envoy::new();
+    app.at("/reader").get(|_: &mut envoy::Context| async {
+        Ok(stream::from_reader(&b"read from a reader"[..]))
+    });
+    app.at("/stream").get(|_: &mut envoy::Context| async {
+        let numbers = futures_util::stream::iter(1..=3)
+            .map(|n| Ok::<_, envoy::Error>(Bytes::from(format!("{},", n))));
+        Ok(stream::from_stream(numbers))
+    });
+
+    assert_eq!(send(&app, get("/reader")).await.body(), "read from a reader");
+    assert_eq!(send(&app, get("/stream")).await.body(), "1,2,3,");
+}
+
+#[derive(Serialize)]
+struct Line {
+    n: u32,
+}
+
+#[tokio::test]
+async fn streams_responses_from_channels() {
+    let mut app = envoy::new();
+    app.at("/").get(|_: &mut envoy::Context| async {
+        let (mut sender, res) = stream::channel();
+        tokio::spawn(async move {
+            for n in 0..3 {
+                sender.send_json(&Line { n }).await.unwrap();
+            }
+        });
+        Ok(res)
+    });
+    assert_eq!(send(&app, get("/")).await.body(), "{\"n\":0}\n{\"n\":1}\n{\"n\":2}\n");
+
+    let mut aborted = envoy::new();
+    aborted.at("/").get(|_: &mut envoy::Context| async {
+        let (mut sender, res) = stream::channel();
+        tokio::spawn(async move {
+            sender.send("partial").await.unwrap();
+            sender.abort();
+        });
+        Ok(res)
+    });
+    let res = respond(&aborted, get("/")).await;
+    assert!(body::to_bytes(res.into_body()).await.is_err());
+}
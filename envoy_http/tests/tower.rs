@@ -0,0 +1,146 @@
+mod common;
+
+use envoy_http as envoy;
+
+use common::{get, request, respond};
+use envoy::http::header::{HeaderValue, SERVER};
+use envoy::{Body, Error, Method, Request, Response, StatusCode};
+use hyper::body;
+use tower::layer::layer_fn;
+use tower::{service_fn, Service, ServiceBuilder, ServiceExt};
+use tower_http::set_header::{SetRequestHeaderLayer, SetResponseHeaderLayer};
+
+async fn into_string(res: Response<Body>) -> String {
+    String::from_utf8(body::to_bytes(res.into_body()).await.unwrap().to_vec()).unwrap()
+}
+
+#[tokio::test]
+async fn server_is_a_tower_service() {
+    let mut app = envoy::new();
+    app.at("/hello").get(|_ctx: &mut envoy::Context| async { Ok(Response::new(Body::from("hello"))) });
+
+    let service = ServiceBuilder::new()
+        .layer(SetResponseHeaderLayer::overriding(SERVER, HeaderValue::from_static("envoy")))
+        .service(app);
+    let res = service.oneshot(get("/hello")).await.unwrap();
+    assert_eq!(res.headers()[SERVER], "envoy");
+    assert_eq!(into_string(res).await, "hello");
+}
+
+#[tokio::test]
+async fn mounts_tower_services() {
+    let mut app = envoy::new();
+    app.at("/legacy").service(service_fn(|req: Request<Body>| async move {
+        let body = format!("{} {}", req.method(), req.uri());
+        Ok::<_, std::convert::Infallible>(Response::new(Body::from(body)))
+    }));
+    app.at("/failing").service(service_fn(|_req: Request<Body>| async {
+        Err::<Response<Body>, _>(std::io::Error::other("broken"))
+    }));
+
+    let res = respond(&app, get("/legacy/users?page=2")).await;
+    assert_eq!(into_string(res).await, "GET /users?page=2");
+
+    let req = request(Method::DELETE, "/legacy", &[]);
+    let res: Response<Body> = app.clone().respond(req).await.unwrap();
+    assert_eq!(into_string(res).await, "DELETE /");
+
+    let res = respond(&app, get("/failing")).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+}
+
+#[tokio::test]
+async fn applies_tower_layers_as_middleware() {
+    async fn report_route(ctx: &mut envoy::Context, next: envoy::Next) -> envoy::Result {
+        let mut res = next.run(ctx).await?;
+        let pattern = ctx.route().map(|r| r.pattern().to_owned()).unwrap_or_default();
+        res.headers_mut().insert("x-route", pattern.parse().unwrap());
+        Ok(res)
+    }
+
+    let mut app = envoy::new();
+    app.with(report_route);
+    app.with(envoy::TowerLayer::new(SetRequestHeaderLayer::overriding(
+        "x-user".parse().unwrap(),
+        HeaderValue::from_static("jane"),
+    )));
+    app.with(envoy::TowerLayer::new(SetResponseHeaderLayer::overriding(
+        SERVER,
+        HeaderValue::from_static("envoy"),
+    )));
+    app.at("/users/:id").get(|ctx: &mut envoy::Context| {
+        let user = ctx.borrow::<envoy::HeaderMap>()["x-user"].to_str().unwrap().to_owned();
+        async move { Ok(Response::new(Body::from(user))) }
+    });
+
+    let res = respond(&app, get("/users/1")).await;
+    assert_eq!(res.headers()[SERVER], "envoy");
+    assert_eq!(res.headers()["x-route"], "/users/:id");
+    assert_eq!(into_string(res).await, "jane");
+}
+
+#[tokio::test]
+async fn errors_pass_through_layers() {
+    let mut app = envoy::new();
+    app.at("/conflict")
+        .on_error(|_ctx: &mut envoy::Context, err: Error| async move {
+            let mut res = Response::new(Body::from(format!("handled: {}", err)));
+            *res.status_mut() = err.status();
+            Ok(res)
+        })
+        .with(envoy::TowerLayer::new(SetResponseHeaderLayer::overriding(
+            SERVER,
+            HeaderValue::from_static("envoy"),
+        )))
+        .get(|_ctx: &mut envoy::Context| async {
+            Err(Error::from_str(StatusCode::CONFLICT, "taken"))
+        });
+
+    let res = respond(&app, get("/conflict")).await;
+    assert_eq!(res.status(), StatusCode::CONFLICT);
+    assert_eq!(into_string(res).await, "handled: taken");
+}
+
+#[tokio::test]
+async fn layers_can_answer_by_themselves() {
+    async fn report_path(ctx: &mut envoy::Context, next: envoy::Next) -> envoy::Result {
+        let res = next.run(ctx).await;
+        let path = ctx.borrow::<envoy::Uri>().path().to_owned();
+        let mut res = res?;
+        res.headers_mut().insert("x-path", path.parse().unwrap());
+        Ok(res)
+    }
+
+    let mut app = envoy::new();
+    app.with(report_path);
+    app.with(envoy::TowerLayer::new(layer_fn(|_inner: envoy::NextService| {
+        service_fn(|_req: Request<Body>| async {
+            let res = Response::builder().status(StatusCode::UNAUTHORIZED).body(Body::empty());
+            Ok::<_, std::convert::Infallible>(res.unwrap())
+        })
+    })));
+    app.at("/admin").get(|_ctx: &mut envoy::Context| async { Ok(Response::new(Body::empty())) });
+
+    let res = respond(&app, get("/admin")).await;
+    assert_eq!(res.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(res.headers()["x-path"], "/admin");
+}
+
+#[tokio::test]
+async fn calling_the_chain_twice_fails() {
+    let mut app = envoy::new();
+    app.with(envoy::TowerLayer::new(layer_fn(|inner: envoy::NextService| {
+        service_fn(move |req: Request<Body>| {
+            let mut inner = inner.clone();
+            async move {
+                inner.call(req).await?;
+                inner.call(get("/")).await
+            }
+        })
+    })));
+    app.at("/").get(|_ctx: &mut envoy::Context| async { Ok(Response::new(Body::from("hello"))) });
+
+    let res = respond(&app, get("/")).await;
+    assert_eq!(res.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(into_string(res).await, "The rest of the middleware chain can only be called once");
+}
@@ -0,0 +1,98 @@
+mod common;
+
+use std::time::{Duration, UNIX_EPOCH};
+
+use envoy_http as envoy;
+
+use common::{get, request, send};
+use envoy::{ApiVersion, Body, Method, Response, StatusCode};
+
+async fn version(ctx: &mut envoy::Context) -> envoy::Result {
+    let name = ctx.meta::<ApiVersion>().map_or("none", ApiVersion::name);
+    Ok(Response::new(Body::from(name.to_owned())))
+}
+
+#[tokio::test]
+async fn routes_by_path_prefix() {
+    let mut app = envoy::new();
+    app.at("/api").version(ApiVersion::path("v1"), |v1| {
+        v1.at("/users").get(version);
+    });
+    app.at("/api").version(ApiVersion::path("/v2/"), |v2| {
+        v2.at("/users").get(version);
+    });
+
+    assert_eq!(send(&app, get("/api/v1/users")).await.body(), "v1");
+    assert_eq!(send(&app, get("/api/v2/users")).await.body(), "v2");
+    assert_eq!(send(&app, get("/api/users")).await.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn routes_by_header_and_accept_param() {
+    let mut app = envoy::new();
+    app.at("/users").get(version);
+    app.at("/").version(ApiVersion::header("Accept-Version", "2"), |v2| {
+        v2.at("/users").get(version);
+    });
+    app.at("/").version(ApiVersion::accept("version", "3"), |v3| {
+        v3.at("/users").get(version);
+    });
+
+    let req = request(Method::GET, "/users", &[("accept-version", "2")]);
+    assert_eq!(send(&app, req).await.body(), "2");
+
+    let req = request(Method::GET, "/users", &[("accept", "application/json; version=3")]);
+    assert_eq!(send(&app, req).await.body(), "3");
+
+    let req = request(Method::GET, "/users", &[("accept", "application/json; version=4")]);
+    assert_eq!(send(&app, req).await.body(), "none");
+    let res = send(&app, get("/users")).await;
+    assert_eq!(res.body(), "none");
+
+    // Every response at the path depends on both headers.
+    let vary: Vec<_> = res.headers().get_all("vary").iter().collect();
+    assert_eq!(vary.len(), 2);
+    assert!(vary.contains(&&"accept-version".parse().unwrap()));
+    assert!(vary.contains(&&"accept".parse().unwrap()));
+}
+
+#[test]
+#[should_panic(expected = "invalid header name")]
+fn header_versions_need_valid_names() {
+    let _ = ApiVersion::header("Accept Version", "2");
+}
+
+#[tokio::test]
+async fn deprecated_versions_carry_headers() {
+    let since = UNIX_EPOCH + Duration::from_secs(1_688_169_599);
+    let sunset = UNIX_EPOCH + Duration::from_secs(1_735_689_600);
+
+    let mut app = envoy::new();
+    let v1 = ApiVersion::path("v1")
+        .deprecated(since)
+        .sunset(sunset)
+        .successor("/v2/users");
+    app.at("/").version(v1, |v1| {
+        v1.at("/users").get(version);
+        v1.at("/teams").get(|_: &mut envoy::Context| async {
+            Err(envoy::Error::from_str(StatusCode::SERVICE_UNAVAILABLE, "Gone fishing"))
+        });
+    });
+    app.at("/").version(ApiVersion::path("v2"), |v2| {
+        v2.at("/users").get(version);
+    });
+
+    let res = send(&app, get("/v1/users")).await;
+    assert_eq!(res.headers()["deprecation"], "@1688169599");
+    assert_eq!(res.headers()["sunset"], "Wed, 01 Jan 2025 00:00:00 GMT");
+    assert_eq!(res.headers()["link"], "</v2/users>; rel=\"successor-version\"");
+
+    // Error responses are annotated too.
+    let res = send(&app, get("/v1/teams")).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert_eq!(res.headers()["deprecation"], "@1688169599");
+
+    let res = send(&app, get("/v2/users")).await;
+    assert!(res.headers().get("deprecation").is_none());
+    assert!(res.headers().get("link").is_none());
+}
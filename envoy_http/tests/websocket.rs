@@ -0,0 +1,215 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+
+use envoy_http as envoy;
+
+use envoy::websocket::{CloseFrame, Message, WebSocket, WebSocketConnection};
+use envoy::{Body, Request, Response, StatusCode};
+use futures_util::{SinkExt, StreamExt};
+use soketto::connection::{Mode, Receiver, Sender};
+use soketto::extension::deflate::Deflate;
+use soketto::handshake::{Client, ServerResponse};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio_util::compat::{Compat, TokioAsyncReadCompatExt};
+
+type Socket = Compat<TcpStream>;
+
+async fn echo(ctx: envoy::Context, conn: WebSocketConnection) -> envoy::Result<()> {
+    let room = ctx.param("room")?.to_owned();
+    let origin = ctx.header("origin").map(|value| value.to_str().unwrap().to_owned());
+    let protocol = conn.protocol().map(str::to_owned);
+    let (mut sender, mut receiver) = conn.split();
+    while let Some(message) = receiver.next().await {
+        let reply = match message? {
+            Message::Text(text) if text == "ping me" => Message::Ping(b"are you there".to_vec()),
+            Message::Text(text) => Message::Text(format!("{} {:?} {:?}: {}", room, origin, protocol, text)),
+            Message::Binary(data) => Message::Binary(data.into_iter().rev().collect()),
+            Message::Pong(data) => Message::Text(format!("pong {}", String::from_utf8(data).unwrap())),
+            Message::Ping(_) => continue,
+            Message::Close(frame) => {
+                assert_eq!(frame.map(|frame| frame.code), Some(1000));
+                break;
+            }
+        };
+        sender.send(reply).await?;
+    }
+    Ok(())
+}
+
+async fn serve(app: envoy::Server) -> SocketAddr {
+    let make_svc = hyper::service::make_service_fn(move |_| {
+        let app = app.clone();
+        async move { Ok::<_, Infallible>(app) }
+    });
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_svc);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+async fn connect(addr: SocketAddr, path: &str) -> (Sender<Socket>, Receiver<Socket>) {
+    let socket = TcpStream::connect(addr).await.unwrap().compat();
+    let host = addr.to_string();
+    let headers = [soketto::handshake::client::Header {
+        name: "Origin",
+        value: b"https://example.com",
+    }];
+    let mut client = Client::new(socket, &host, path);
+    client
+        .set_headers(&headers)
+        .add_protocol("chat")
+        .add_extension(Box::new(Deflate::new(Mode::Client)));
+    match client.handshake().await.unwrap() {
+        ServerResponse::Accepted { .. } => {}
+        other => panic!("handshake failed: {:?}", other),
+    }
+    client.into_builder().finish()
+}
+
+async fn receive_text(receiver: &mut Receiver<Socket>) -> String {
+    let mut data = Vec::new();
+    receiver.receive_data(&mut data).await.unwrap();
+    String::from_utf8(data).unwrap()
+}
+
+#[tokio::test]
+async fn exchanges_messages() {
+    let mut app = envoy::new();
+    app.at("/rooms/:room").get(
+        WebSocket::new(echo)
+            .protocols(["json", "chat"])
+            .allowed_origins(["https://example.com"]),
+    );
+    let addr = serve(app).await;
+    let (mut sender, mut receiver) = connect(addr, "/rooms/lobby").await;
+
+    sender.send_text("hello").await.unwrap();
+    sender.flush().await.unwrap();
+    assert_eq!(
+        receive_text(&mut receiver).await,
+        "lobby Some(\"https://example.com\") Some(\"chat\"): hello"
+    );
+
+    sender.send_binary(&[1, 2, 3]).await.unwrap();
+    sender.flush().await.unwrap();
+    let mut data = Vec::new();
+    assert!(receiver.receive_data(&mut data).await.unwrap().is_binary());
+    assert_eq!(data, [3, 2, 1]);
+
+    // The client answers the ping of the server while receiving.
+    sender.send_text("ping me").await.unwrap();
+    sender.flush().await.unwrap();
+    assert_eq!(receive_text(&mut receiver).await, "pong are you there");
+
+    sender.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn enforces_size_limits() {
+    let (errors_tx, mut errors_rx) = mpsc::unbounded_channel();
+    let mut app = envoy::new();
+    app.at("/").get(
+        WebSocket::new(move |_, conn: WebSocketConnection| {
+            let errors_tx = errors_tx.clone();
+            async move {
+                let (_, mut receiver) = conn.split();
+                while let Some(message) = receiver.next().await {
+                    errors_tx.send(message.is_err()).unwrap();
+                }
+                Ok(())
+            }
+        })
+        .max_message_size(16),
+    );
+    let addr = serve(app).await;
+    let (mut sender, _receiver) = connect(addr, "/").await;
+
+    sender.send_text("small").await.unwrap();
+    sender.flush().await.unwrap();
+    assert_eq!(errors_rx.recv().await, Some(false));
+    sender.send_text("a message that is far too large").await.unwrap();
+    sender.flush().await.unwrap();
+    assert_eq!(errors_rx.recv().await, Some(true));
+}
+
+fn handshake(headers: &[(&str, &str)]) -> Request<Body> {
+    let mut req = Request::builder().uri("/");
+    for (name, value) in headers {
+        req = req.header(*name, *value);
+    }
+    req.body(Body::empty()).unwrap()
+}
+
+const UPGRADE: [(&str, &str); 4] = [
+    ("connection", "keep-alive, Upgrade"),
+    ("upgrade", "websocket"),
+    ("sec-websocket-version", "13"),
+    ("sec-websocket-key", "dGhlIHNhbXBsZSBub25jZQ=="),
+];
+
+#[tokio::test]
+async fn validates_handshakes() {
+    let closed = |_, _| async { Ok(()) };
+    let mut app = envoy::new();
+    app.at("/").get(WebSocket::new(closed).protocols(["chat"]).allowed_origins(["https://example.com"]));
+    let respond = |req| async { app.clone().respond::<_, Response<Body>>(req).await.unwrap() };
+
+    let res = respond(handshake(&[])).await;
+    assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+    assert_eq!(res.headers()["upgrade"], "websocket");
+
+    let old = handshake(&[UPGRADE[0], UPGRADE[1], ("sec-websocket-version", "8"), UPGRADE[3]]);
+    let res = respond(old).await;
+    assert_eq!(res.status(), StatusCode::UPGRADE_REQUIRED);
+    assert_eq!(res.headers()["sec-websocket-version"], "13");
+
+    let bad_key = handshake(&[UPGRADE[0], UPGRADE[1], UPGRADE[2], ("sec-websocket-key", "short")]);
+    assert_eq!(respond(bad_key).await.status(), StatusCode::BAD_REQUEST);
+
+    let mut headers = UPGRADE.to_vec();
+    headers.push(("origin", "https://evil.example"));
+    assert_eq!(respond(handshake(&headers)).await.status(), StatusCode::FORBIDDEN);
+
+    headers.pop();
+    headers.push(("origin", "https://example.com"));
+    headers.push(("sec-websocket-protocol", "json, chat"));
+    headers.push(("sec-websocket-extensions", "x-unknown, permessage-deflate; client_max_window_bits"));
+    let res = respond(handshake(&headers)).await;
+    assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert_eq!(res.headers()["sec-websocket-accept"], "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+    assert_eq!(res.headers()["sec-websocket-protocol"], "chat");
+    assert!(res.headers()["sec-websocket-extensions"]
+        .to_str()
+        .unwrap()
+        .starts_with("permessage-deflate"));
+
+    let mut app = envoy::new();
+    app.at("/").get(WebSocket::new(closed).without_deflate());
+    let res: Response<Body> = app.respond(handshake(&headers)).await.unwrap();
+    assert_eq!(res.status(), StatusCode::SWITCHING_PROTOCOLS);
+    assert!(res.headers().get("sec-websocket-protocol").is_none());
+    assert!(res.headers().get("sec-websocket-extensions").is_none());
+}
+
+
+#[tokio::test]
+async fn refuses_to_send_close_reasons() {
+    let (errors_tx, mut errors_rx) = mpsc::unbounded_channel();
+    let mut app = envoy::new();
+    app.at("/").get(WebSocket::new(move |_, conn: WebSocketConnection| {
+        let errors_tx = errors_tx.clone();
+        async move {
+            let (mut sender, _) = conn.split();
+            let frame = CloseFrame {
+                code: 4000,
+                reason: "bye".to_owned(),
+            };
+            errors_tx.send(sender.send(Message::Close(Some(frame))).await.is_err()).unwrap();
+            Ok(())
+        }
+    }));
+    let addr = serve(app).await;
+    let (_sender, _receiver) = connect(addr, "/").await;
+    assert_eq!(errors_rx.recv().await, Some(true));
+}